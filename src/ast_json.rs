@@ -0,0 +1,123 @@
+use crate::evaluator::print_format;
+use crate::json::Json;
+use crate::lexer::Token;
+use crate::parser::ASTNode;
+use crate::trivia::Attached;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// The syntax tree as JSON, for `--emit=ast-json` and tools built on the
+// parser. Every node is an object whose "type" names the `ASTNode` variant,
+// with the variant's fields under descriptive keys:
+//
+//   {"type":"Statement","line":1,"statement":
+//     {"type":"Assign","name":"x","value":
+//       {"type":"BinaryOp","operator":"+","left":{"type":"Number","value":1},"right":{"type":"Number","value":2}}}}
+//
+// Statements that have comments or follow a blank line also carry
+// "leading_comments", "trailing_comment" and "blank_line_before", and the
+// program lists comments after its last statement as "dangling_comments";
+// see trivia.rs. Comment text is everything after the `//`.
+//
+// Whole numbers are written digit for digit, so a reader that parses them
+// as 64-bit integers gets them back exactly, even beyond 2^53.
+
+pub fn to_json(node: &Rc<RefCell<ASTNode>>, trivia: &Attached) -> Json {
+    let node = node.borrow();
+    let (kind, mut fields) = match &*node {
+        ASTNode::Number(n) => ("Number", vec![("value", Json::Integer(*n))]),
+        ASTNode::Float(n) => ("Float", vec![("value", Json::Number(*n))]),
+        ASTNode::StringLiteral(text) => ("StringLiteral", vec![("value", Json::String(text.clone()))]),
+        ASTNode::Boolean(b) => ("Boolean", vec![("value", Json::Bool(*b))]),
+        ASTNode::Identifier(name) => ("Identifier", vec![("name", Json::String(name.clone()))]),
+        ASTNode::BinaryOp(left, operator, right) => ("BinaryOp", binary(left, operator, right, trivia)),
+        ASTNode::Comparison(left, operator, right) => ("Comparison", binary(left, operator, right, trivia)),
+        ASTNode::LogicalOp(left, operator, right) => ("LogicalOp", binary(left, operator, right, trivia)),
+        ASTNode::ChainedComparison(operands, operators) => {
+            let operators = operators.iter().map(|operator| Json::string(symbol(operator)));
+            ("ChainedComparison", vec![("operands", all(operands, trivia)), ("operators", Json::Array(operators.collect()))])
+        }
+        ASTNode::Not(operand) => ("Not", vec![("operand", to_json(operand, trivia))]),
+        ASTNode::Assign(name, value) => ("Assign", vec![("name", Json::String(name.clone())), ("value", to_json(value, trivia))]),
+        ASTNode::IndexAssign(name, indexes, value) => {
+            ("IndexAssign", vec![("name", Json::String(name.clone())), ("indexes", all(indexes, trivia)), ("value", to_json(value, trivia))])
+        }
+        ASTNode::Print(value) => ("Print", vec![("value", to_json(value, trivia))]),
+        ASTNode::Program(body) => {
+            let dangling = trivia.dangling.iter().map(|text| Json::String(text.clone()));
+            ("Program", vec![("body", all(body, trivia)), ("dangling_comments", Json::Array(dangling.collect()))])
+        }
+        ASTNode::FunctionCall(name, arguments) => ("FunctionCall", vec![("name", Json::String(name.clone())), ("arguments", all(arguments, trivia))]),
+        ASTNode::Array(elements) => ("Array", vec![("elements", all(elements, trivia))]),
+        ASTNode::Map(entries) => {
+            let entries = entries.iter().map(|(key, value)| Json::object(vec![("key", to_json(key, trivia)), ("value", to_json(value, trivia))]));
+            ("Map", vec![("entries", Json::Array(entries.collect()))])
+        }
+        ASTNode::IndexAccess(target, index) => ("IndexAccess", vec![("target", to_json(target, trivia)), ("index", to_json(index, trivia))]),
+        ASTNode::Slice(target, start, end) => {
+            let bound = |bound: &Option<Rc<RefCell<ASTNode>>>| bound.as_ref().map_or(Json::Null, |bound| to_json(bound, trivia));
+            ("Slice", vec![("target", to_json(target, trivia)), ("start", bound(start)), ("end", bound(end))])
+        }
+        ASTNode::If(condition, then_block, elifs, else_block) => {
+            let elifs = elifs.iter().map(|(condition, body)| Json::object(vec![("condition", to_json(condition, trivia)), ("body", all(body, trivia))]));
+            let else_block = else_block.as_ref().map_or(Json::Null, |body| all(body, trivia));
+            ("If", vec![("condition", to_json(condition, trivia)), ("then", all(then_block, trivia)), ("elif", Json::Array(elifs.collect())), ("else", else_block)])
+        }
+        ASTNode::While(condition, body) => ("While", vec![("condition", to_json(condition, trivia)), ("body", all(body, trivia))]),
+        ASTNode::For(init, condition, update, body) => {
+            ("For", vec![("init", to_json(init, trivia)), ("condition", to_json(condition, trivia)), ("update", to_json(update, trivia)), ("body", all(body, trivia))])
+        }
+        ASTNode::ForIn(variable, iterable, body) => {
+            ("ForIn", vec![("variable", Json::String(variable.clone())), ("iterable", to_json(iterable, trivia)), ("body", all(body, trivia))])
+        }
+        ASTNode::Spread(items) => ("Spread", vec![("items", to_json(items, trivia))]),
+        ASTNode::Evaluated(value) => ("Evaluated", vec![("value", Json::String(print_format(value)))]),
+        ASTNode::Comprehension(element, variable, iterable, condition) => {
+            let condition = condition.as_ref().map_or(Json::Null, |condition| to_json(condition, trivia));
+            ("Comprehension", vec![("element", to_json(element, trivia)), ("variable", Json::String(variable.clone())), ("iterable", to_json(iterable, trivia)), ("condition", condition)])
+        }
+        ASTNode::Repeat(count, body) => ("Repeat", vec![("count", to_json(count, trivia)), ("body", all(body, trivia))]),
+        ASTNode::TryCatch(body, error, handler) => ("TryCatch", vec![("body", all(body, trivia)), ("error", Json::String(error.clone())), ("handler", all(handler, trivia))]),
+        ASTNode::Labeled(label, body) => ("Labeled", vec![("label", Json::String(label.clone())), ("loop", to_json(body, trivia))]),
+        ASTNode::Break(label) => ("Break", vec![("label", label.as_ref().map_or(Json::Null, |label| Json::String(label.clone())))]),
+        ASTNode::Continue(label) => ("Continue", vec![("label", label.as_ref().map_or(Json::Null, |label| Json::String(label.clone())))]),
+        ASTNode::Statement(line, statement) => {
+            let mut fields = vec![("line", Json::Number(*line as f64)), ("statement", to_json(statement, trivia))];
+            if let Some(found) = trivia.get(&node) {
+                fields.push(("leading_comments", Json::Array(found.leading.iter().map(|text| Json::String(text.clone())).collect())));
+                fields.push(("trailing_comment", found.trailing.as_ref().map_or(Json::Null, |text| Json::String(text.clone()))));
+                fields.push(("blank_line_before", Json::Bool(found.blank_line_before)));
+            }
+            ("Statement", fields)
+        }
+    };
+    fields.insert(0, ("type", Json::string(kind)));
+    Json::object(fields)
+}
+
+fn all(nodes: &[Rc<RefCell<ASTNode>>], trivia: &Attached) -> Json {
+    Json::Array(nodes.iter().map(|node| to_json(node, trivia)).collect())
+}
+
+fn binary(left: &Rc<RefCell<ASTNode>>, operator: &Token, right: &Rc<RefCell<ASTNode>>, trivia: &Attached) -> Vec<(&'static str, Json)> {
+    vec![("operator", Json::string(symbol(operator))), ("left", to_json(left, trivia)), ("right", to_json(right, trivia))]
+}
+
+// Operators as they are written in source.
+fn symbol(operator: &Token) -> &'static str {
+    match operator {
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Star => "*",
+        Token::Slash => "/",
+        Token::Eq => "==",
+        Token::NotEq => "!=",
+        Token::Lt => "<",
+        Token::Gt => ">",
+        Token::LtEq => "<=",
+        Token::GtEq => ">=",
+        Token::And => "&&",
+        Token::Or => "||",
+        _ => "?",
+    }
+}
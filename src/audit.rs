@@ -0,0 +1,70 @@
+use crate::json::Json;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The log written by `--audit <file>`. Every builtin that reads or writes
+// files, opens a connection, reads the environment or starts a process adds
+// one JSON object per line:
+//
+//   {"time":"2024-06-01T09:30:12.481Z","line":4,"builtin":"read_file","action":"read","target":"data/input.csv","allowed":true}
+//
+// Calls are logged before they run, so a failing call is still listed.
+// "allowed" is false only when the sandbox refused the call. The file is
+// appended to, so several runs can share one log.
+
+pub struct AuditLog {
+    file: File,
+}
+
+impl AuditLog {
+    pub fn open(path: &str) -> Result<AuditLog, String> {
+        let file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| format!("Cannot open audit log {}: {}", path, e))?;
+        Ok(AuditLog { file })
+    }
+
+    // Failing to write the log never stops the script.
+    pub fn record(&mut self, line: usize, builtin: &str, action: &str, target: &str, allowed: bool) {
+        let entry = Json::object(vec![
+            ("time", Json::String(timestamp(SystemTime::now()))),
+            ("line", Json::Number(line as f64)),
+            ("builtin", Json::string(builtin)),
+            ("action", Json::string(action)),
+            ("target", Json::string(target)),
+            ("allowed", Json::Bool(allowed)),
+        ]);
+        let _ = writeln!(self.file, "{}", entry);
+    }
+}
+
+// RFC 3339 in UTC with milliseconds.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let of_day = seconds.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        of_day / 3600,
+        of_day / 60 % 60,
+        of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+// Howard Hinnant's days-to-date algorithm for the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,226 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+// Arbitrary-precision integers for the `big` builtin. The magnitude is kept
+// as base 2^32 digits, least significant first, with no trailing zeros, so
+// zero is an empty vector and is never negative. The algorithms are the
+// schoolbook ones; scripts rarely need numbers large enough for anything
+// faster to matter.
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn from_i64(n: i64) -> BigInt {
+        let mut magnitude = Vec::new();
+        let mut rest = n.unsigned_abs();
+        while rest > 0 {
+            magnitude.push(rest as u32);
+            rest >>= 32;
+        }
+        BigInt { negative: n < 0, magnitude }
+    }
+
+    pub fn parse(text: &str) -> Result<BigInt, String> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let digits = digits.replace('_', "");
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Invalid integer: {:?}", text));
+        }
+        let mut magnitude = Vec::new();
+        for digit in digits.bytes() {
+            mul_small_add(&mut magnitude, 10, (digit - b'0') as u32);
+        }
+        Ok(BigInt::new(negative, magnitude))
+    }
+
+    fn new(negative: bool, mut magnitude: Vec<u32>) -> BigInt {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        BigInt { negative: negative && !magnitude.is_empty(), magnitude }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.magnitude.len() * 4
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_magnitudes(&self.magnitude, &other.magnitude));
+        }
+        match compare_magnitudes(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::new(other.negative, sub_magnitudes(&other.magnitude, &self.magnitude)),
+            _ => BigInt::new(self.negative, sub_magnitudes(&self.magnitude, &other.magnitude)),
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&BigInt::new(!other.negative, other.magnitude.clone()))
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        let mut product = vec![0u32; self.magnitude.len() + other.magnitude.len()];
+        for (i, &a) in self.magnitude.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.magnitude.iter().enumerate() {
+                let total = product[i + j] as u64 + a as u64 * b as u64 + carry;
+                product[i + j] = total as u32;
+                carry = total >> 32;
+            }
+            product[i + other.magnitude.len()] = carry as u32;
+        }
+        BigInt::new(self.negative != other.negative, product)
+    }
+
+    // Truncates toward zero like `/` on whole numbers. The caller checks
+    // for a zero divisor.
+    pub fn div(&self, other: &BigInt) -> BigInt {
+        let mut quotient = vec![0u32; self.magnitude.len()];
+        let mut remainder: Vec<u32> = Vec::new();
+        for bit in (0..self.magnitude.len() * 32).rev() {
+            shift_left_one(&mut remainder);
+            if self.magnitude[bit / 32] >> (bit % 32) & 1 == 1 {
+                if remainder.is_empty() {
+                    remainder.push(1);
+                } else {
+                    remainder[0] |= 1;
+                }
+            }
+            if compare_magnitudes(&remainder, &other.magnitude) != Ordering::Less {
+                remainder = sub_magnitudes(&remainder, &other.magnitude);
+                quotient[bit / 32] |= 1 << (bit % 32);
+            }
+        }
+        BigInt::new(self.negative != other.negative, quotient)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.magnitude, &other.magnitude),
+            (true, true) => compare_magnitudes(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // Peel off nine decimal digits at a time, least significant first.
+        let mut chunks = Vec::new();
+        let mut rest = self.magnitude.clone();
+        while !rest.is_empty() {
+            chunks.push(div_small(&mut rest, 1_000_000_000));
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BigInt({})", self)
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let total = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push(total as u32);
+        carry = total >> 32;
+    }
+    if carry > 0 {
+        sum.push(carry as u32);
+    }
+    sum
+}
+
+// Requires `a` to be at least as large as `b`.
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &digit) in a.iter().enumerate() {
+        let mut total = digit as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = if total < 0 { 1 } else { 0 };
+        if total < 0 {
+            total += 1 << 32;
+        }
+        difference.push(total as u32);
+    }
+    while difference.last() == Some(&0) {
+        difference.pop();
+    }
+    difference
+}
+
+fn shift_left_one(magnitude: &mut Vec<u32>) {
+    let mut carry = 0;
+    for digit in magnitude.iter_mut() {
+        let next_carry = *digit >> 31;
+        *digit = *digit << 1 | carry;
+        carry = next_carry;
+    }
+    if carry > 0 {
+        magnitude.push(carry);
+    }
+}
+
+fn mul_small_add(magnitude: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = addend as u64;
+    for digit in magnitude.iter_mut() {
+        let total = *digit as u64 * factor as u64 + carry;
+        *digit = total as u32;
+        carry = total >> 32;
+    }
+    if carry > 0 {
+        magnitude.push(carry as u32);
+    }
+}
+
+// Divides in place and returns the remainder.
+fn div_small(magnitude: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut remainder = 0u64;
+    for digit in magnitude.iter_mut().rev() {
+        let current = remainder << 32 | *digit as u64;
+        *digit = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    while magnitude.last() == Some(&0) {
+        magnitude.pop();
+    }
+    remainder as u32
+}
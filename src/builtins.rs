@@ -0,0 +1,288 @@
+// Every builtin function with the number of arguments it accepts, in the
+// order the evaluator dispatches them. The evaluator still checks its own
+// arguments; this table is what `functions`, `defined` and `help` report
+// and what `check` holds calls to, so a new builtin needs an entry here as
+// well as a dispatch arm.
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: usize,
+    // How a call looks, with optional arguments in brackets. Arguments can
+    // also be passed by the names used here, as in `replace(s, a, b, limit: 1)`.
+    pub signature: &'static str,
+    pub doc: &'static str,
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin { name: "join", min_args: 2, max_args: 2, signature: "join(separator, items)", doc: "Joins the items of an array into a string with separator between them." },
+    Builtin { name: "split", min_args: 2, max_args: 2, signature: "split(text, separator)", doc: "Splits text at each separator, which may be a string or a regex." },
+    Builtin { name: "count", min_args: 2, max_args: 2, signature: "count(haystack, needle)", doc: "Counts occurrences of a substring in a string or of a value in an array." },
+    Builtin { name: "length", min_args: 1, max_args: 1, signature: "length(value)", doc: "Returns the length of a string, array, set or map, or the size of a heap." },
+    Builtin { name: "uppercase", min_args: 1, max_args: 1, signature: "uppercase(text)", doc: "Returns text in upper case." },
+    Builtin { name: "lowercase", min_args: 1, max_args: 1, signature: "lowercase(text)", doc: "Returns text in lower case." },
+    Builtin { name: "trim", min_args: 1, max_args: 1, signature: "trim(text)", doc: "Removes leading and trailing whitespace." },
+    Builtin { name: "replace", min_args: 3, max_args: 4, signature: "replace(text, from, to[, limit])", doc: "Replaces occurrences of from with to, at most limit times if given." },
+    Builtin { name: "push", min_args: 2, max_args: 2, signature: "push(array, value)", doc: "Appends value to the array or deque and returns it." },
+    Builtin { name: "pop", min_args: 1, max_args: 1, signature: "pop(array)", doc: "Removes and returns the last element of the array or deque." },
+    Builtin { name: "first", min_args: 1, max_args: 1, signature: "first(array)", doc: "Returns the first element of the array." },
+    Builtin { name: "last", min_args: 1, max_args: 1, signature: "last(array)", doc: "Returns the last element of the array." },
+    Builtin { name: "read_file", min_args: 1, max_args: 1, signature: "read_file(path)", doc: "Returns the contents of a file as a string." },
+    Builtin { name: "write_file", min_args: 2, max_args: 2, signature: "write_file(path, contents)", doc: "Writes a string to a file, replacing what was there." },
+    Builtin { name: "regex", min_args: 1, max_args: 1, signature: "regex(pattern)", doc: "Compiles a regular expression for split and regex_replace." },
+    Builtin { name: "split_lines", min_args: 1, max_args: 1, signature: "split_lines(text)", doc: "Splits text into lines, accepting both \\n and \\r\\n endings." },
+    Builtin { name: "split_whitespace", min_args: 1, max_args: 1, signature: "split_whitespace(text)", doc: "Splits text at runs of whitespace." },
+    Builtin { name: "regex_replace", min_args: 3, max_args: 4, signature: "regex_replace(text, pattern, replacement[, limit])", doc: "Replaces regex matches; $1 and so on refer to groups." },
+    Builtin { name: "range", min_args: 1, max_args: 3, signature: "range([start, ]stop[, step])", doc: "Lazily yields numbers from start up to but not including stop." },
+    Builtin { name: "collect", min_args: 1, max_args: 1, signature: "collect(iterable)", doc: "Gathers the items of an iterator or set into an array." },
+    Builtin { name: "set", min_args: 0, max_args: 1, signature: "set([items])", doc: "Makes a set, optionally from the items of an array." },
+    Builtin { name: "add", min_args: 2, max_args: 2, signature: "add(set, value)", doc: "Returns the set with value added." },
+    Builtin { name: "has", min_args: 2, max_args: 2, signature: "has(collection, value)", doc: "Returns whether a set contains a value or a map contains a key." },
+    Builtin { name: "remove", min_args: 2, max_args: 2, signature: "remove(set, value)", doc: "Returns the set with value removed." },
+    Builtin { name: "union", min_args: 2, max_args: 2, signature: "union(a, b)", doc: "Returns the values in either set." },
+    Builtin { name: "intersect", min_args: 2, max_args: 2, signature: "intersect(a, b)", doc: "Returns the values in both sets." },
+    Builtin { name: "builder_new", min_args: 0, max_args: 1, signature: "builder_new([initial])", doc: "Makes a string builder for appending text efficiently." },
+    Builtin { name: "builder_push", min_args: 2, max_args: 2, signature: "builder_push(builder, value)", doc: "Appends a value's text to the builder." },
+    Builtin { name: "builder_build", min_args: 1, max_args: 1, signature: "builder_build(builder)", doc: "Returns the builder's contents as a string." },
+    Builtin { name: "merge", min_args: 2, max_args: 2, signature: "merge(a, b)", doc: "Returns a map with the entries of both; b wins on shared keys." },
+    Builtin { name: "get", min_args: 2, max_args: 3, signature: "get(map, key[, default])", doc: "Returns the value for key, or default if it is missing." },
+    Builtin { name: "entries", min_args: 1, max_args: 1, signature: "entries(map)", doc: "Returns the map's [key, value] pairs as an array." },
+    Builtin { name: "from_entries", min_args: 1, max_args: 1, signature: "from_entries(pairs)", doc: "Builds a map from an array of [key, value] pairs." },
+    Builtin { name: "copy", min_args: 1, max_args: 1, signature: "copy(value)", doc: "Returns an unshared, unfrozen copy of the value." },
+    Builtin { name: "deep_copy", min_args: 1, max_args: 1, signature: "deep_copy(value)", doc: "Like copy, but also copies builders nested inside collections." },
+    Builtin { name: "freeze", min_args: 1, max_args: 1, signature: "freeze(value)", doc: "Returns the value marked read-only." },
+    Builtin { name: "error", min_args: 1, max_args: 2, signature: "error(message[, kind])", doc: "Raises an error, of kind \"user\" unless another is given." },
+    Builtin { name: "error_kind", min_args: 1, max_args: 1, signature: "error_kind(error)", doc: "Returns the kind of a caught error." },
+    Builtin { name: "error_message", min_args: 1, max_args: 1, signature: "error_message(error)", doc: "Returns the message of a caught error." },
+    Builtin { name: "open", min_args: 1, max_args: 2, signature: "open(path[, mode])", doc: "Opens a file; modes are r (default), w, a, r+, w+ and a+." },
+    Builtin { name: "read_line", min_args: 1, max_args: 1, signature: "read_line(file)", doc: "Reads one line from a file without its line ending." },
+    Builtin { name: "write", min_args: 2, max_args: 2, signature: "write(handle, text)", doc: "Writes text to a file and returns the number of bytes written." },
+    Builtin { name: "seek", min_args: 2, max_args: 3, signature: "seek(file, offset[, whence])", doc: "Moves within a file relative to \"start\", \"current\" or \"end\"." },
+    Builtin { name: "eof", min_args: 1, max_args: 1, signature: "eof(file)", doc: "Returns whether the file has been read to the end." },
+    Builtin { name: "close", min_args: 1, max_args: 1, signature: "close(handle)", doc: "Closes a file or socket, or the input of a process." },
+    Builtin { name: "lines", min_args: 1, max_args: 1, signature: "lines(path)", doc: "Lazily yields the lines of a file." },
+    Builtin { name: "stdin_lines", min_args: 0, max_args: 0, signature: "stdin_lines()", doc: "Lazily yields the lines of standard input." },
+    Builtin { name: "glob", min_args: 1, max_args: 1, signature: "glob(pattern)", doc: "Returns the sorted paths matching a pattern such as logs/**/*.txt." },
+    Builtin { name: "fnmatch", min_args: 2, max_args: 2, signature: "fnmatch(pattern, name)", doc: "Returns whether a name matches a glob pattern." },
+    Builtin { name: "walk_dir", min_args: 1, max_args: 3, signature: "walk_dir(path[, depth[, follow_links]])", doc: "Lazily yields an entry map for everything below a directory." },
+    Builtin { name: "temp_file", min_args: 0, max_args: 1, signature: "temp_file([prefix])", doc: "Creates an empty temporary file that is removed when the script ends." },
+    Builtin { name: "temp_dir", min_args: 0, max_args: 1, signature: "temp_dir([prefix])", doc: "Creates an empty temporary directory that is removed when the script ends." },
+    Builtin { name: "copy_file", min_args: 2, max_args: 2, signature: "copy_file(from, to)", doc: "Copies a file." },
+    Builtin { name: "move_file", min_args: 2, max_args: 2, signature: "move_file(from, to)", doc: "Moves or renames a file, even across filesystems." },
+    Builtin { name: "mkdir", min_args: 1, max_args: 1, signature: "mkdir(path)", doc: "Creates a directory whose parent already exists." },
+    Builtin { name: "mkdirs", min_args: 1, max_args: 1, signature: "mkdirs(path)", doc: "Creates a directory and any missing parents." },
+    Builtin { name: "chmod", min_args: 2, max_args: 2, signature: "chmod(path, mode)", doc: "Sets a file's permissions from an octal string such as \"755\"." },
+    Builtin { name: "touch", min_args: 1, max_args: 1, signature: "touch(path)", doc: "Creates a file or updates its modification time." },
+    Builtin { name: "memory_used", min_args: 0, max_args: 0, signature: "memory_used()", doc: "Returns the approximate bytes held by all variables." },
+    Builtin { name: "tcp_connect", min_args: 2, max_args: 2, signature: "tcp_connect(host, port)", doc: "Opens a TCP connection." },
+    Builtin { name: "tcp_listen", min_args: 1, max_args: 2, signature: "tcp_listen(port[, host])", doc: "Listens for TCP connections, on 127.0.0.1 unless a host is given." },
+    Builtin { name: "accept", min_args: 1, max_args: 1, signature: "accept(server)", doc: "Waits for and returns the next connection." },
+    Builtin { name: "socket_port", min_args: 1, max_args: 1, signature: "socket_port(socket)", doc: "Returns the local port of a socket, such as the one tcp_listen(0) picked." },
+    Builtin { name: "send", min_args: 2, max_args: 2, signature: "send(socket, text)", doc: "Sends text and returns the number of bytes sent." },
+    Builtin { name: "recv", min_args: 1, max_args: 2, signature: "recv(socket[, max_bytes])", doc: "Receives up to max_bytes (4096 by default); \"\" once closed." },
+    Builtin { name: "url_parse", min_args: 1, max_args: 1, signature: "url_parse(url)", doc: "Splits a URL into a map of its parts." },
+    Builtin { name: "url_encode", min_args: 1, max_args: 1, signature: "url_encode(text)", doc: "Percent-encodes text for use in a URL." },
+    Builtin { name: "url_decode", min_args: 1, max_args: 1, signature: "url_decode(text)", doc: "Decodes percent-encoding, reading + as a space." },
+    Builtin { name: "query_string", min_args: 1, max_args: 1, signature: "query_string(map)", doc: "Builds an encoded query string from a map." },
+    Builtin { name: "store_open", min_args: 1, max_args: 1, signature: "store_open(path)", doc: "Opens a key-value store file, creating it on the first write." },
+    Builtin { name: "store_get", min_args: 2, max_args: 3, signature: "store_get(store, key[, default])", doc: "Returns a stored value, or default if the key is missing." },
+    Builtin { name: "store_set", min_args: 3, max_args: 3, signature: "store_set(store, key, value)", doc: "Saves a string, number or boolean under key." },
+    Builtin { name: "store_delete", min_args: 2, max_args: 2, signature: "store_delete(store, key)", doc: "Deletes a key and returns whether it was there." },
+    Builtin { name: "load_env", min_args: 0, max_args: 1, signature: "load_env([path])", doc: "Loads KEY=VALUE lines from .env or the given file." },
+    Builtin { name: "env", min_args: 0, max_args: 2, signature: "env([name[, default]])", doc: "Returns an environment variable, or all of them as a map." },
+    Builtin { name: "color", min_args: 2, max_args: 2, signature: "color(text, name)", doc: "Returns text in a terminal color such as \"red\"." },
+    Builtin { name: "bold", min_args: 1, max_args: 1, signature: "bold(text)", doc: "Returns text in bold." },
+    Builtin { name: "is_tty", min_args: 0, max_args: 0, signature: "is_tty()", doc: "Returns whether output goes to a terminal." },
+    Builtin { name: "term_clear", min_args: 0, max_args: 0, signature: "term_clear()", doc: "Clears the terminal screen." },
+    Builtin { name: "term_size", min_args: 0, max_args: 0, signature: "term_size()", doc: "Returns the terminal's columns and rows as a map." },
+    Builtin { name: "term_move", min_args: 2, max_args: 2, signature: "term_move(row, column)", doc: "Moves the terminal cursor." },
+    Builtin { name: "term_cursor", min_args: 1, max_args: 1, signature: "term_cursor(visible)", doc: "Shows or hides the terminal cursor." },
+    Builtin { name: "progress_start", min_args: 1, max_args: 2, signature: "progress_start(total[, label])", doc: "Starts a progress bar on stderr; a total of 0 shows a spinner." },
+    Builtin { name: "progress_tick", min_args: 1, max_args: 2, signature: "progress_tick(progress[, amount])", doc: "Advances a progress bar by amount, or by 1." },
+    Builtin { name: "progress_finish", min_args: 1, max_args: 1, signature: "progress_finish(progress)", doc: "Prints the final count and elapsed time." },
+    Builtin { name: "confirm", min_args: 1, max_args: 2, signature: "confirm(question[, default])", doc: "Asks a yes or no question and returns the answer." },
+    Builtin { name: "select", min_args: 2, max_args: 2, signature: "select(prompt, options)", doc: "Asks the user to pick one of the options and returns it." },
+    Builtin { name: "password", min_args: 1, max_args: 1, signature: "password(prompt)", doc: "Reads a line without showing what is typed." },
+    Builtin { name: "mean", min_args: 1, max_args: 1, signature: "mean(numbers)", doc: "Returns the average of an array of numbers." },
+    Builtin { name: "median", min_args: 1, max_args: 1, signature: "median(numbers)", doc: "Returns the middle value of an array of numbers." },
+    Builtin { name: "stddev", min_args: 1, max_args: 1, signature: "stddev(numbers)", doc: "Returns the population standard deviation of an array of numbers." },
+    Builtin { name: "percentile", min_args: 2, max_args: 2, signature: "percentile(numbers, p)", doc: "Returns the p-th percentile, interpolating between values." },
+    Builtin { name: "big", min_args: 1, max_args: 1, signature: "big(value)", doc: "Converts a number or string of digits to a big integer." },
+    Builtin { name: "decimal", min_args: 1, max_args: 1, signature: "decimal(value)", doc: "Converts a string, number or float to an exact decimal." },
+    Builtin { name: "decimal_round", min_args: 2, max_args: 3, signature: "decimal_round(value, places[, mode])", doc: "Rounds a decimal; modes are half_even (default), half_up, down, up, floor and ceiling." },
+    Builtin { name: "uuid", min_args: 0, max_args: 0, signature: "uuid()", doc: "Returns a random version 4 UUID." },
+    Builtin { name: "random_hex", min_args: 1, max_args: 1, signature: "random_hex(bytes)", doc: "Returns that many secure random bytes as hex digits." },
+    Builtin { name: "random_token", min_args: 1, max_args: 1, signature: "random_token(length)", doc: "Returns a secure random string of URL-safe characters." },
+    Builtin { name: "sha256", min_args: 1, max_args: 1, signature: "sha256(text)", doc: "Returns the SHA-256 digest of text in hex." },
+    Builtin { name: "hmac_sha256", min_args: 2, max_args: 2, signature: "hmac_sha256(key, data)", doc: "Returns the HMAC-SHA256 of data signed with key, in hex." },
+    Builtin { name: "secure_equals", min_args: 2, max_args: 2, signature: "secure_equals(a, b)", doc: "Compares two strings in constant time." },
+    Builtin { name: "eval", min_args: 1, max_args: 2, signature: "eval(source[, mode])", doc: "Runs code from a string, \"shared\" (default) or \"isolated\", and returns its value." },
+    Builtin { name: "vars", min_args: 0, max_args: 0, signature: "vars()", doc: "Returns a map of every variable to its value." },
+    Builtin { name: "functions", min_args: 0, max_args: 0, signature: "functions()", doc: "Returns each builtin's name with its argument counts." },
+    Builtin { name: "defined", min_args: 1, max_args: 1, signature: "defined(name)", doc: "Returns whether a variable or builtin with that name exists." },
+    Builtin { name: "help", min_args: 0, max_args: 1, signature: "help([name])", doc: "Prints the signature and description of a builtin, or lists them all." },
+    Builtin { name: "render", min_args: 2, max_args: 2, signature: "render(template, map)", doc: "Fills {{name}}, {{#each}} and {{#if}} tags in a template from a map." },
+    Builtin { name: "retry", min_args: 3, max_args: 4, signature: "retry(times, delay_ms, expression[, backoff])", doc: "Evaluates expression until it succeeds, up to times attempts, waiting between them." },
+    Builtin { name: "cache_file", min_args: 3, max_args: 3, signature: "cache_file(key, ttl_seconds, expression)", doc: "Returns the value saved under key if still fresh, else evaluates expression and saves it." },
+    Builtin { name: "time", min_args: 1, max_args: 1, signature: "time(expression)", doc: "Evaluates expression and returns how long it took in milliseconds." },
+    Builtin { name: "benchmark", min_args: 2, max_args: 2, signature: "benchmark(expression, iterations)", doc: "Evaluates expression iterations times and returns a map of min, mean and max milliseconds." },
+    Builtin { name: "spawn_process", min_args: 1, max_args: 2, signature: "spawn_process(command[, args])", doc: "Starts a program with piped input and output and returns a process." },
+    Builtin { name: "proc_read_line", min_args: 1, max_args: 1, signature: "proc_read_line(process)", doc: "Reads the next line of a process's output, or \"\" once it has closed it." },
+    Builtin { name: "proc_write", min_args: 2, max_args: 2, signature: "proc_write(process, text)", doc: "Writes text to a process's input." },
+    Builtin { name: "proc_wait", min_args: 1, max_args: 1, signature: "proc_wait(process)", doc: "Closes a process's input, waits for it to exit and returns its exit code." },
+    Builtin { name: "pipe", min_args: 1, max_args: 2, signature: "pipe(commands[, input])", doc: "Runs commands as a pipeline fed with input and returns the last one's output." },
+    Builtin { name: "clipboard_get", min_args: 0, max_args: 0, signature: "clipboard_get()", doc: "Returns the text on the system clipboard." },
+    Builtin { name: "clipboard_set", min_args: 1, max_args: 1, signature: "clipboard_set(text)", doc: "Puts text on the system clipboard." },
+    Builtin { name: "platform", min_args: 0, max_args: 0, signature: "platform()", doc: "Returns the operating system, such as \"linux\", \"macos\" or \"windows\"." },
+    Builtin { name: "hostname", min_args: 0, max_args: 0, signature: "hostname()", doc: "Returns the name of this machine." },
+    Builtin { name: "cpu_count", min_args: 0, max_args: 0, signature: "cpu_count()", doc: "Returns how many CPUs the script can use." },
+    Builtin { name: "home_dir", min_args: 0, max_args: 0, signature: "home_dir()", doc: "Returns the current user's home directory." },
+    Builtin { name: "current_dir", min_args: 0, max_args: 0, signature: "current_dir()", doc: "Returns the working directory." },
+    Builtin { name: "set_current_dir", min_args: 1, max_args: 1, signature: "set_current_dir(path)", doc: "Changes the working directory." },
+    Builtin { name: "edit_distance", min_args: 2, max_args: 2, signature: "edit_distance(a, b)", doc: "Returns how many single-character edits turn a into b." },
+    Builtin { name: "fuzzy_match", min_args: 2, max_args: 2, signature: "fuzzy_match(query, candidates)", doc: "Returns the candidates that loosely match query, closest first." },
+    Builtin { name: "sort", min_args: 1, max_args: 2, signature: "sort(items[, order])", doc: "Returns items in ascending order; order \"natural\" sorts \"file2\" before \"file10\"." },
+    Builtin { name: "sort_by", min_args: 2, max_args: 3, signature: "sort_by(items, keys[, order])", doc: "Returns items ordered by the key at the same position in keys." },
+    Builtin { name: "group_by", min_args: 2, max_args: 2, signature: "group_by(items, keys)", doc: "Returns a map from each key to the items with that key." },
+    Builtin { name: "partition", min_args: 2, max_args: 2, signature: "partition(items, flags)", doc: "Returns [matching, rest]: the items whose flag is true and the others." },
+    Builtin { name: "chunk", min_args: 2, max_args: 2, signature: "chunk(items, size)", doc: "Splits items into arrays of size items; the last may be shorter." },
+    Builtin { name: "windows", min_args: 2, max_args: 2, signature: "windows(items, size)", doc: "Returns every run of size neighbouring items." },
+    Builtin { name: "binary_search", min_args: 2, max_args: 2, signature: "binary_search(sorted, value)", doc: "Returns the position of value in a sorted array, or -1." },
+    Builtin { name: "insert_sorted", min_args: 2, max_args: 2, signature: "insert_sorted(sorted, value)", doc: "Returns a copy of a sorted array with value inserted in order." },
+    Builtin { name: "heap_new", min_args: 0, max_args: 1, signature: "heap_new([order])", doc: "Makes an empty heap that pops its smallest item first, or its largest with order \"max\"." },
+    Builtin { name: "heap_push", min_args: 2, max_args: 2, signature: "heap_push(heap, value)", doc: "Adds value to a heap." },
+    Builtin { name: "heap_pop", min_args: 1, max_args: 1, signature: "heap_pop(heap)", doc: "Removes and returns the first item of a heap." },
+    Builtin { name: "deque", min_args: 0, max_args: 1, signature: "deque([items])", doc: "Makes a double-ended queue, optionally holding the items of an array." },
+    Builtin { name: "shift", min_args: 1, max_args: 1, signature: "shift(deque)", doc: "Removes and returns the first item of a deque." },
+    Builtin { name: "unshift", min_args: 2, max_args: 2, signature: "unshift(deque, value)", doc: "Adds value to the front of a deque." },
+];
+
+pub fn find(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+impl Builtin {
+    // The parameter names in the signature, in order.
+    pub fn parameters(&self) -> Vec<&'static str> {
+        let signature = self.signature;
+        let inside = &signature[signature.find('(').map_or(0, |open| open + 1)..signature.rfind(')').unwrap_or(signature.len())];
+        inside.split(',').map(|name| name.trim_matches(|c: char| c == '[' || c == ']' || c.is_whitespace())).filter(|name| !name.is_empty()).collect()
+    }
+}
+
+// The kinds of value `check` can tell apart from a literal argument.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Type {
+    Any,
+    String,
+    Number,
+    Boolean,
+    Array,
+    Map,
+}
+
+impl Type {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Type::Any => "a value",
+            Type::String => "a string",
+            Type::Number => "a number",
+            Type::Boolean => "a boolean",
+            Type::Array => "an array",
+            Type::Map => "a map",
+        }
+    }
+}
+
+// The one type each argument must have, by position, for builtins whose
+// arguments are not checked any other way. `check` compares literal
+// arguments against it so obvious mistakes show up before a script runs.
+// Arguments left out, or `Any`, accept more than one type.
+const ARGUMENT_TYPES: &[(&str, &[Type])] = &[
+    ("join", &[Type::String, Type::Array]),
+    ("split", &[Type::String]),
+    ("uppercase", &[Type::String]),
+    ("lowercase", &[Type::String]),
+    ("trim", &[Type::String]),
+    ("replace", &[Type::String, Type::String, Type::String, Type::Number]),
+    ("push", &[Type::Array]),
+    ("pop", &[Type::Array]),
+    ("first", &[Type::Array]),
+    ("last", &[Type::Array]),
+    ("read_file", &[Type::String]),
+    ("write_file", &[Type::String, Type::String]),
+    ("regex", &[Type::String]),
+    ("split_lines", &[Type::String]),
+    ("split_whitespace", &[Type::String]),
+    ("builder_new", &[Type::String]),
+    ("entries", &[Type::Map]),
+    ("error", &[Type::String, Type::String]),
+    ("open", &[Type::String, Type::String]),
+    ("lines", &[Type::String]),
+    ("glob", &[Type::String]),
+    ("fnmatch", &[Type::String, Type::String]),
+    ("walk_dir", &[Type::String, Type::Number, Type::Boolean]),
+    ("temp_file", &[Type::String]),
+    ("temp_dir", &[Type::String]),
+    ("copy_file", &[Type::String, Type::String]),
+    ("move_file", &[Type::String, Type::String]),
+    ("mkdir", &[Type::String]),
+    ("mkdirs", &[Type::String]),
+    ("chmod", &[Type::String, Type::String]),
+    ("touch", &[Type::String]),
+    ("tcp_connect", &[Type::String]),
+    ("tcp_listen", &[Type::Number, Type::String]),
+    ("url_parse", &[Type::String]),
+    ("url_encode", &[Type::String]),
+    ("url_decode", &[Type::String]),
+    ("query_string", &[Type::Map]),
+    ("store_open", &[Type::String]),
+    ("load_env", &[Type::String]),
+    ("env", &[Type::String]),
+    ("term_move", &[Type::Number, Type::Number]),
+    ("term_cursor", &[Type::Boolean]),
+    ("progress_start", &[Type::Number]),
+    ("confirm", &[Type::Any, Type::Boolean]),
+    ("select", &[Type::Any, Type::Array]),
+    ("mean", &[Type::Array]),
+    ("median", &[Type::Array]),
+    ("stddev", &[Type::Array]),
+    ("percentile", &[Type::Array, Type::Number]),
+    ("random_hex", &[Type::Number]),
+    ("random_token", &[Type::Number]),
+    ("sha256", &[Type::String]),
+    ("hmac_sha256", &[Type::String, Type::String]),
+    ("secure_equals", &[Type::String, Type::String]),
+    ("eval", &[Type::String, Type::String]),
+    ("defined", &[Type::String]),
+    ("help", &[Type::String]),
+    ("render", &[Type::String, Type::Map]),
+    ("retry", &[Type::Number, Type::Number, Type::Any, Type::Number]),
+    ("cache_file", &[Type::String, Type::Number]),
+    ("benchmark", &[Type::Any, Type::Number]),
+    ("spawn_process", &[Type::String, Type::Array]),
+    ("proc_write", &[Type::Any, Type::String]),
+    ("pipe", &[Type::Array, Type::String]),
+    ("clipboard_set", &[Type::String]),
+    ("set_current_dir", &[Type::String]),
+    ("edit_distance", &[Type::String, Type::String]),
+    ("fuzzy_match", &[Type::String, Type::Array]),
+    ("sort", &[Type::Array, Type::String]),
+    ("sort_by", &[Type::Array, Type::Array, Type::String]),
+    ("group_by", &[Type::Array, Type::Array]),
+    ("partition", &[Type::Array, Type::Array]),
+    ("chunk", &[Type::Array, Type::Number]),
+    ("windows", &[Type::Array, Type::Number]),
+    ("binary_search", &[Type::Array]),
+    ("insert_sorted", &[Type::Array]),
+    ("heap_new", &[Type::String]),
+    ("deque", &[Type::Array]),
+];
+
+pub fn argument_type(name: &str, index: usize) -> Type {
+    ARGUMENT_TYPES
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .and_then(|(_, types)| types.get(index).copied())
+        .unwrap_or(Type::Any)
+}
@@ -0,0 +1,115 @@
+use crate::evaluator::Value;
+use crate::json::{self, Json};
+use crate::sha256;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime};
+
+// The files behind `cache_file`. Each key gets its own file in the user's
+// cache directory, named by the key's SHA-256 so any string can be a key.
+// The directory is only accessible to its owner, and entries are written to
+// a fresh file that is then renamed into place, so a link planted where an
+// entry goes is replaced rather than written through. A file holds one value
+// as JSON, with every value tagged by its type so numbers and floats come
+// back as they went in:
+//
+//   ["m", {"name": ["s", "Ann"], "visits": ["n", "3"], "scores": ["a", [["f", 1.5]]]}]
+//
+// Whole numbers are written as strings to keep all 64 bits. A file older
+// than the time to live is stale, and its age is taken from when it was
+// last written.
+
+pub fn path(key: &str) -> Result<PathBuf, String> {
+    let name = sha256::to_hex(&sha256::digest(key.as_bytes()));
+    Ok(directory()?.join(format!("{}.json", name)))
+}
+
+// %LOCALAPPDATA% on Windows; elsewhere $XDG_CACHE_HOME, or ~/.cache when
+// that is unset.
+fn directory() -> Result<PathBuf, String> {
+    let set = |name: &str| env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let base = if cfg!(windows) {
+        set("LOCALAPPDATA").ok_or("Cannot find the cache directory: LOCALAPPDATA is not set")?
+    } else {
+        match set("XDG_CACHE_HOME").filter(|path| path.is_absolute()) {
+            Some(path) => path,
+            None => set("HOME").ok_or("Cannot find the cache directory: neither XDG_CACHE_HOME nor HOME is set")?.join(".cache"),
+        }
+    };
+    Ok(base.join("nabeelscript"))
+}
+
+// The cached value, or `None` when there is none or it is older than `ttl`.
+pub fn load(key: &str, ttl: Duration) -> Result<Option<Value>, String> {
+    let path = path(key)?;
+    let modified = match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Cannot read cache entry {}: {}", path.display(), e)),
+    };
+    if SystemTime::now().duration_since(modified).unwrap_or_default() >= ttl {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(&path).map_err(|e| format!("Cannot read cache entry {}: {}", path.display(), e))?;
+    let corrupt = || format!("Cache entry {} is corrupt", path.display());
+    let json = json::parse(&text).map_err(|_| corrupt())?;
+    decode(&json).map(Some).ok_or_else(corrupt)
+}
+
+pub fn save(key: &str, json: &Json) -> Result<(), String> {
+    let path = path(key)?;
+    write(&path, &json.to_string()).map_err(|e| format!("Cannot write cache entry {}: {}", path.display(), e))
+}
+
+fn write(path: &Path, text: &str) -> io::Result<()> {
+    let directory = path.parent().unwrap_or(path);
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(directory)?;
+    let temporary = path.with_extension(format!("{}.tmp", process::id()));
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let written = options.open(&temporary).and_then(|mut file| file.write_all(text.as_bytes())).and_then(|_| fs::rename(&temporary, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    written
+}
+
+// `None` when the value, or anything inside it, cannot be written.
+pub fn encode(value: &Value) -> Option<Json> {
+    let (tag, json) = match value {
+        Value::String(text) => ("s", Json::String(text.clone())),
+        Value::Number(n) => ("n", Json::String(n.to_string())),
+        Value::Float(n) => ("f", Json::Number(*n)),
+        Value::Boolean(b) => ("b", Json::Bool(*b)),
+        Value::Array(items) => ("a", Json::Array(items.iter().map(encode).collect::<Option<_>>()?)),
+        Value::Map(map) => ("m", Json::Object(map.iter().map(|(key, value)| Some((key.clone(), encode(value)?))).collect::<Option<_>>()?)),
+        Value::Frozen(inner, _) => return encode(inner),
+        _ => return None,
+    };
+    Some(Json::Array(vec![Json::string(tag), json]))
+}
+
+fn decode(json: &Json) -> Option<Value> {
+    let Json::Array(pair) = json else {
+        return None;
+    };
+    match (pair.first()?.as_str()?, pair.get(1)?) {
+        ("s", Json::String(text)) => Some(Value::String(text.clone())),
+        ("n", Json::String(n)) => n.parse().ok().map(Value::Number),
+        ("f", Json::Number(n)) => Some(Value::Float(*n)),
+        ("b", Json::Bool(b)) => Some(Value::Boolean(*b)),
+        ("a", Json::Array(items)) => items.iter().map(decode).collect::<Option<_>>().map(Value::Array),
+        ("m", Json::Object(entries)) => entries.iter().map(|(key, value)| Some((key.clone(), decode(value)?))).collect::<Option<BTreeMap<_, _>>>().map(Value::Map),
+        _ => None,
+    }
+}
@@ -0,0 +1,174 @@
+use crate::evaluator::EvaluatorConfig;
+use crate::diagnostic::ErrorFormat;
+use crate::manifest;
+use crate::permissions::Permissions;
+
+// Command-line parsing. Flags may appear anywhere on the command line; the
+// first other argument picks the subcommand, and a file name in its place
+// means `run`, so `nabeelscript script.nabeel` keeps working. Without
+// files, `run`, `check`, `watch` and `test` use the project's nabeel.toml.
+
+pub const VERSION: &str = "0.1.0";
+
+pub const USAGE: &str = "Usage: nabeelscript [options] [command] <file.nabeel>...
+
+Commands:
+    run <file.nabeel>...       Run the files in order (the default)
+    check <file.nabeel>...     Parse and check the files without running them
+    watch <file.nabeel>...     Run the files again whenever one of them changes
+    test <file.nabeel>...      Run each file and compare its output with its // expect: comments
+    highlight <file.nabeel>... Print the files as syntax-highlighted HTML
+    repl                       Start an interactive session
+    notebook                   Run notebook cells sent as JSON-RPC requests on stdin
+    help                       Show this message
+
+Options:
+    --ast                      Print the parsed syntax tree instead of running
+    --emit=ast-json            Print the parsed syntax tree as JSON instead of running
+    --tokens                   Print each token's position and highlighting kind as JSON
+    --trace                    Print each statement's line to stderr as it runs
+    --max-depth <n>            Limit how deeply evaluation may nest
+    --max-iterations <n>       Limit the total number of loop iterations
+    --max-string-length <n>    Limit the length of any string, in bytes
+    --max-array-length <n>     Limit the length of any array
+    --max-memory <bytes>       Limit the approximate memory held by variables
+    --stats                    Print counts, peak memory and timing to stderr at exit
+    --error-format=<format>    Print errors as text (the default) or as json, one object per line
+    --seed <n>                 Make uuid, random_hex and random_token repeat the same results every run
+    --dry-run                  Report file changes on stderr instead of making them
+    --audit <file>             Append each file, network, environment and process access to <file> as JSON lines
+    --sandbox                  Deny file, network, environment and process access not allowed below
+    --allow-read[=<paths>]     Allow reading files, or only those under the comma-separated paths
+    --allow-write[=<paths>]    Allow writing files, or only those under the paths
+    --allow-net[=<hosts>]      Allow network access, or only to the hosts (host or host:port)
+    --allow-env                Allow reading and setting environment variables
+    --allow-run[=<programs>]   Allow starting processes, or only the named programs
+    -h, --help                 Show this message
+    -V, --version              Show the interpreter version";
+
+pub enum Command {
+    Run,
+    Check,
+    Watch,
+    Test,
+    Highlight,
+    Repl,
+    Notebook,
+    Help,
+    Version,
+}
+
+pub struct Options {
+    pub command: Command,
+    pub files: Vec<String>,
+    pub ast: bool,
+    pub ast_json: bool,
+    pub tokens: bool,
+    pub trace: bool,
+    pub stats: bool,
+    pub audit: Option<String>,
+    pub error_format: ErrorFormat,
+    pub limits: EvaluatorConfig,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        command: Command::Run,
+        files: Vec::new(),
+        ast: false,
+        ast_json: false,
+        tokens: false,
+        trace: false,
+        stats: false,
+        audit: None,
+        error_format: ErrorFormat::Text,
+        limits: EvaluatorConfig::default(),
+    };
+    let mut command = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ast" | "--emit=ast" => options.ast = true,
+            "--emit=ast-json" => options.ast_json = true,
+            "--tokens" => options.tokens = true,
+            "--max-depth" => options.limits.max_depth = Some(limit_value(arg, args.next())?),
+            "--max-iterations" => options.limits.max_loop_iterations = Some(limit_value(arg, args.next())?),
+            "--max-string-length" => options.limits.max_string_length = Some(limit_value(arg, args.next())?),
+            "--max-array-length" => options.limits.max_array_length = Some(limit_value(arg, args.next())?),
+            "--max-memory" => options.limits.max_memory = Some(limit_value(arg, args.next())?),
+            "--trace" => options.trace = true,
+            "--stats" => options.stats = true,
+            "--seed" => options.limits.seed = Some(limit_value(arg, args.next())?),
+            "--error-format=text" => options.error_format = ErrorFormat::Text,
+            "--error-format=json" => options.error_format = ErrorFormat::Json,
+            "--dry-run" => options.limits.dry_run = true,
+            "--audit" => options.audit = Some(args.next().ok_or("--audit requires a file name")?.to_string()),
+            "--sandbox" => {
+                options.limits.permissions.get_or_insert_with(Permissions::default);
+            }
+            flag if flag.starts_with("--allow-") => {
+                let (name, list) = match flag.split_once('=') {
+                    Some((name, list)) => (name, Some(list)),
+                    None => (flag, None),
+                };
+                let permissions = options.limits.permissions.get_or_insert_with(Permissions::default);
+                match name {
+                    "--allow-read" => permissions.read.add_paths(list),
+                    "--allow-write" => permissions.write.add_paths(list),
+                    "--allow-net" => permissions.net.add(list),
+                    "--allow-run" => permissions.run.add(list),
+                    "--allow-env" if list.is_none() => permissions.env = true,
+                    _ => return Err(format!("Unknown option: {}", flag)),
+                }
+            }
+            "-h" | "--help" => return Ok(Options { command: Command::Help, ..options }),
+            "-V" | "--version" => return Ok(Options { command: Command::Version, ..options }),
+            // Kept from before subcommands existed.
+            "--check" => command = command.or(Some(Command::Check)),
+            flag if flag.starts_with('-') && flag != "-" => return Err(format!("Unknown option: {}", flag)),
+            name if command.is_none() && options.files.is_empty() => {
+                command = Some(match name {
+                    "run" => Command::Run,
+                    "check" => Command::Check,
+                    "watch" => Command::Watch,
+                    "test" => Command::Test,
+                    "highlight" => Command::Highlight,
+                    "repl" => Command::Repl,
+                    "notebook" => Command::Notebook,
+                    "help" => Command::Help,
+                    _ => {
+                        options.files.push(name.to_string());
+                        Command::Run
+                    }
+                });
+            }
+            file => options.files.push(file.to_string()),
+        }
+    }
+    options.command = command.unwrap_or(Command::Help);
+    match options.command {
+        Command::Run | Command::Check | Command::Watch | Command::Test if options.files.is_empty() => match manifest::find() {
+            Some(path) => {
+                let manifest = manifest::load(path)?;
+                if !manifest.dependencies.is_empty() {
+                    eprintln!("Warning: {} lists dependencies, but imports are not supported yet; ignoring them", path);
+                }
+                options.files.push(manifest.entry);
+                manifest::merge_limits(&mut options.limits, &manifest.limits);
+                Ok(options)
+            }
+            None => Err(format!("No input files, and no {} in this directory", manifest::FILE_NAME)),
+        },
+        Command::Highlight if options.files.is_empty() => Err("No input files".to_string()),
+        Command::Repl if !options.files.is_empty() => Err("The repl command does not take files".to_string()),
+        Command::Notebook if !options.files.is_empty() => Err("The notebook command does not take files".to_string()),
+        _ => Ok(options),
+    }
+}
+
+fn limit_value<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    match value {
+        Some(value) => value.parse().map_err(|_| format!("Invalid value for {}: {}", flag, value)),
+        None => Err(format!("Missing value for {}", flag)),
+    }
+}
@@ -0,0 +1,189 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+// Exact decimal numbers for the `decimal` builtin, stored as an integer
+// count of units together with how many of its digits fall after the
+// decimal point: 19.99 is 1999 units at scale 2. Addition, subtraction and
+// multiplication are exact; division and `decimal_round` round explicitly.
+// Results that do not fit in 128 bits report an overflow instead.
+
+// Digits kept after the point when a division does not come out exactly.
+const DIVISION_SCALE: u32 = 18;
+
+#[derive(Clone, Copy)]
+pub struct Decimal {
+    units: i128,
+    scale: u32,
+}
+
+#[derive(Clone, Copy)]
+pub enum Rounding {
+    HalfEven,
+    HalfUp,
+    Down,
+    Up,
+    Floor,
+    Ceiling,
+}
+
+impl Rounding {
+    pub fn parse(name: &str) -> Option<Rounding> {
+        match name {
+            "half_even" => Some(Rounding::HalfEven),
+            "half_up" => Some(Rounding::HalfUp),
+            "down" => Some(Rounding::Down),
+            "up" => Some(Rounding::Up),
+            "floor" => Some(Rounding::Floor),
+            "ceiling" => Some(Rounding::Ceiling),
+            _ => None,
+        }
+    }
+}
+
+const OVERFLOW: &str = "Decimal result is too large";
+
+impl Decimal {
+    pub fn from_i64(n: i64) -> Decimal {
+        Decimal { units: n as i128, scale: 0 }
+    }
+
+    // Accepts an optional sign, digits and an optional fractional part, as
+    // in "-12.50". The number of fractional digits given is kept.
+    pub fn parse(text: &str) -> Result<Decimal, String> {
+        let invalid = || format!("Invalid decimal: {:?}", text);
+        let (negative, rest) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (whole, fraction) = rest.split_once('.').unwrap_or((rest, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        let mut units: i128 = 0;
+        for digit in whole.bytes().chain(fraction.bytes()) {
+            if !digit.is_ascii_digit() {
+                return Err(invalid());
+            }
+            units = units.checked_mul(10).and_then(|u| u.checked_add((digit - b'0') as i128)).ok_or(OVERFLOW)?;
+        }
+        Ok(Decimal { units: if negative { -units } else { units }, scale: fraction.len() as u32 })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.units == 0
+    }
+
+    pub fn add(&self, other: &Decimal) -> Result<Decimal, String> {
+        let (a, b, scale) = aligned(self, other)?;
+        Ok(Decimal { units: a.checked_add(b).ok_or(OVERFLOW)?, scale })
+    }
+
+    pub fn sub(&self, other: &Decimal) -> Result<Decimal, String> {
+        let (a, b, scale) = aligned(self, other)?;
+        Ok(Decimal { units: a.checked_sub(b).ok_or(OVERFLOW)?, scale })
+    }
+
+    pub fn mul(&self, other: &Decimal) -> Result<Decimal, String> {
+        let units = self.units.checked_mul(other.units).ok_or(OVERFLOW)?;
+        Ok(Decimal { units, scale: self.scale + other.scale })
+    }
+
+    // Rounds half to even at DIVISION_SCALE places, then drops trailing
+    // zeros beyond the larger of the two operands' scales, so 10.00 / 4
+    // gives 2.50. The caller checks for a zero divisor.
+    pub fn div(&self, other: &Decimal) -> Result<Decimal, String> {
+        let numerator = self.units.checked_mul(pow10(DIVISION_SCALE + other.scale)?).ok_or(OVERFLOW)?;
+        let units = divide_rounded(numerator, other.units, Rounding::HalfEven);
+        let quotient = Decimal { units, scale: self.scale + DIVISION_SCALE };
+        Ok(quotient.trimmed(self.scale.max(other.scale)))
+    }
+
+    pub fn round(&self, places: u32, mode: Rounding) -> Result<Decimal, String> {
+        if places >= self.scale {
+            let units = self.units.checked_mul(pow10(places - self.scale)?).ok_or(OVERFLOW)?;
+            return Ok(Decimal { units, scale: places });
+        }
+        let units = divide_rounded(self.units, pow10(self.scale - places)?, mode);
+        Ok(Decimal { units, scale: places })
+    }
+
+    // Removes trailing fractional zeros, keeping at least `min_scale` digits.
+    fn trimmed(mut self, min_scale: u32) -> Decimal {
+        while self.scale > min_scale && self.units % 10 == 0 {
+            self.units /= 10;
+            self.scale -= 1;
+        }
+        self
+    }
+
+    pub fn compare(&self, other: &Decimal) -> Ordering {
+        // Comparing at a common scale can only overflow for values far
+        // outside any practical range; the sign decides those.
+        match aligned(self, other) {
+            Ok((a, b, _)) => a.cmp(&b),
+            Err(_) => self.units.signum().cmp(&other.units.signum()),
+        }
+    }
+}
+
+fn pow10(exponent: u32) -> Result<i128, String> {
+    10i128.checked_pow(exponent).ok_or_else(|| OVERFLOW.to_string())
+}
+
+fn aligned(a: &Decimal, b: &Decimal) -> Result<(i128, i128, u32), String> {
+    let scale = a.scale.max(b.scale);
+    let a_units = a.units.checked_mul(pow10(scale - a.scale)?).ok_or(OVERFLOW)?;
+    let b_units = b.units.checked_mul(pow10(scale - b.scale)?).ok_or(OVERFLOW)?;
+    Ok((a_units, b_units, scale))
+}
+
+fn divide_rounded(numerator: i128, divisor: i128, mode: Rounding) -> i128 {
+    let quotient = numerator / divisor;
+    let remainder = numerator % divisor;
+    if remainder == 0 {
+        return quotient;
+    }
+    let positive = (numerator < 0) == (divisor < 0);
+    let away = if positive { 1 } else { -1 };
+    let twice_remainder = remainder.unsigned_abs() * 2;
+    let divisor_abs = divisor.unsigned_abs();
+    let round_away = match mode {
+        Rounding::Down => false,
+        Rounding::Up => true,
+        Rounding::Floor => !positive,
+        Rounding::Ceiling => positive,
+        Rounding::HalfUp => twice_remainder >= divisor_abs,
+        Rounding::HalfEven => twice_remainder > divisor_abs || (twice_remainder == divisor_abs && quotient % 2 != 0),
+    };
+    if round_away {
+        quotient + away
+    } else {
+        quotient
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.compare(other) == Ordering::Equal
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.units.unsigned_abs().to_string();
+        let sign = if self.units < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
+
+impl fmt::Debug for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Decimal({})", self)
+    }
+}
@@ -0,0 +1,131 @@
+use crate::json::Json;
+
+// Errors reported about a script: unreadable files, syntax errors, problems
+// found by `check` and runtime errors. By default they are printed for
+// people; `--error-format=json` prints each as one JSON object per line on
+// stderr instead, for editors and wrapper tools:
+//
+//   {"severity":"error","code":"syntax","message":"Expected an expression, found `;` while parsing assignment to `x`","file":"main.nabeel","span":{"line":3,"column":8,"length":1}}
+//
+// "code" is "io", "syntax", one of the `check` problems "undefined_variable",
+// "arity" and "argument_type" or, for runtime errors, the same kind
+// `error_kind` returns. Lines count from 1 and columns from 0, in
+// characters. Runtime errors only know their line, so their column and
+// length are null, and the span is null when nothing is known. As text, a
+// syntax error also shows the line it is on with the token underlined.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+pub struct Span {
+    pub line: usize,
+    pub column: Option<usize>,
+    pub length: Option<usize>,
+}
+
+impl Span {
+    fn line(line: usize) -> Span {
+        Span { line, column: None, length: None }
+    }
+
+    pub fn at(line: usize, column: usize, length: usize) -> Span {
+        Span { line, column: Some(column), length: Some(length) }
+    }
+}
+
+// The step that failed decides how the error reads as text.
+#[derive(Clone, Copy)]
+enum Stage {
+    Read,
+    Parse,
+    Check,
+    Run,
+}
+
+pub struct Diagnostic {
+    stage: Stage,
+    code: String,
+    message: String,
+    file: String,
+    span: Option<Span>,
+    source_line: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn read(file: &str, message: String) -> Diagnostic {
+        Diagnostic { stage: Stage::Read, code: "io".to_string(), message, file: file.to_string(), span: None, source_line: None }
+    }
+
+    pub fn syntax(file: &str, message: String, span: Option<Span>) -> Diagnostic {
+        Diagnostic { stage: Stage::Parse, code: "syntax".to_string(), message, file: file.to_string(), span, source_line: None }
+    }
+
+    pub fn check(file: &str, code: &str, message: String, line: usize) -> Diagnostic {
+        Diagnostic { stage: Stage::Check, code: code.to_string(), message, file: file.to_string(), span: Some(Span::line(line)), source_line: None }
+    }
+
+    pub fn runtime(file: &str, kind: String, message: String, line: usize) -> Diagnostic {
+        Diagnostic { stage: Stage::Run, code: kind, message, file: file.to_string(), span: Some(Span::line(line)), source_line: None }
+    }
+
+    // The text of the line the error is on, shown under the message.
+    pub fn with_source_line(mut self, text: String) -> Diagnostic {
+        self.source_line = Some(text);
+        self
+    }
+
+    pub fn report(&self, format: ErrorFormat) {
+        match format {
+            ErrorFormat::Text => eprintln!("{}", self.text()),
+            ErrorFormat::Json => eprintln!("{}", self.to_json()),
+        }
+    }
+
+    fn text(&self) -> String {
+        let heading = match self.stage {
+            Stage::Read => format!("Error reading file {}: {}", self.file, self.message),
+            Stage::Parse => return format!("Error parsing {}: {}{}", self.file, self.message, self.snippet()),
+            Stage::Check => format!("Error checking {}: {}", self.file, self.message),
+            Stage::Run => format!("Error evaluating AST: {}", self.message),
+        };
+        match &self.span {
+            Some(span) => format!("{}\n    at line {} of {}", heading, span.line, self.file),
+            None => heading,
+        }
+    }
+
+    //     3 | x = join(", " items);
+    //       |                ^^^^^
+    fn snippet(&self) -> String {
+        let (Some(text), Some(span)) = (&self.source_line, &self.span) else {
+            return String::new();
+        };
+        if text.trim().is_empty() {
+            return String::new();
+        }
+        let number = span.line.to_string();
+        // Tabs are kept under the line so the marker lines up however they
+        // are displayed.
+        let indent: String = text.chars().take(span.column.unwrap_or(0)).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let marker = "^".repeat(span.length.unwrap_or(1).max(1));
+        let gutter = " ".repeat(number.len());
+        format!("\n    {} | {}\n    {} | {}{}", number, text, gutter, indent, marker)
+    }
+
+    pub fn to_json(&self) -> Json {
+        let span = self.span.as_ref().map_or(Json::Null, |span| {
+            let optional = |value: Option<usize>| value.map_or(Json::Null, |n| Json::Number(n as f64));
+            Json::object(vec![("line", Json::Number(span.line as f64)), ("column", optional(span.column)), ("length", optional(span.length))])
+        });
+        Json::object(vec![
+            ("severity", Json::string("error")),
+            ("code", Json::String(self.code.clone())),
+            ("message", Json::String(self.message.clone())),
+            ("file", Json::String(self.file.clone())),
+            ("span", span),
+        ])
+    }
+}
@@ -1,29 +1,525 @@
-use crate::parser::{ASTNode};
+use crate::parser::{ASTNode, Parser};
+use crate::lexer::Lexer;
 use crate::lexer::Token;
-use std::collections::HashMap;
+use crate::regex::Regex;
+use crate::sha256;
+use crate::bigint::BigInt;
+use crate::cache;
+use crate::builtins::{self, BUILTINS};
+use crate::decimal::{Decimal, Rounding};
+use crate::fuzzy;
+use crate::glob;
+use crate::audit::AuditLog;
+use crate::permissions::Permissions;
+use crate::progress::Progress;
+use crate::random;
+use crate::store::{Store, Stored};
+use crate::template;
+use crate::url;
+use crate::walk::DirWalker;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Limits for running untrusted scripts. Every limit is off by default;
+// exceeding one raises an error of kind "limit".
+#[derive(Clone, Default)]
+pub struct EvaluatorConfig {
+    // How deeply expressions and statements may nest while evaluating.
+    pub max_depth: Option<usize>,
+    // Total loop iterations across the whole run.
+    pub max_loop_iterations: Option<u64>,
+    // Longest string, in bytes, and longest array a script may build.
+    pub max_string_length: Option<usize>,
+    pub max_array_length: Option<usize>,
+    // Approximate bytes held by all variables together; see `value_size`.
+    pub max_memory: Option<usize>,
+    // Set when the sandbox is on; I/O builtins then need a matching grant.
+    pub permissions: Option<Permissions>,
+    // With `--dry-run`, builtins that change files report what they would
+    // do on stderr and succeed without doing it.
+    pub dry_run: bool,
+    // With `--seed`, the random builtins repeat the same results every run.
+    pub seed: Option<u64>,
+}
+
+// Counters reported by `--stats`.
+#[derive(Clone, Default)]
+pub struct Stats {
+    pub statements: u64,
+    pub function_calls: u64,
+    // Strings and collections produced while evaluating, including copies.
+    pub allocations: u64,
+    pub peak_variables: usize,
+    pub peak_memory: usize,
+}
+
+enum Control {
+    Break(Option<String>),
+    Continue(Option<String>),
+}
+
+// What a loop does after its body has run.
+enum Flow {
+    Next,
+    Break,
+}
 
 pub struct Evaluator {
     variables: HashMap<String, Value>,
+    current_line: usize,
+    // Errors travel through the evaluator as plain messages. When a builtin
+    // knows more about a failure, such as its kind, it leaves the structured
+    // error here for `catch` to pick up.
+    raised_error: Option<Value>,
+    // Set by `break` and `continue`, which travel up to their loop as errors
+    // that `catch` lets through. The parser makes sure a loop is there.
+    control: Option<Control>,
+    // The label of the loop about to start, set by `ASTNode::Labeled`.
+    loop_label: Option<String>,
+    // Paths made by temp_file and temp_dir, removed when the evaluator is dropped.
+    temp_paths: Vec<PathBuf>,
+    // When set, each statement's line is written to stderr before it runs.
+    trace: bool,
+    config: EvaluatorConfig,
+    depth: usize,
+    loop_iterations: u64,
+    memory_used: usize,
+    // The size each variable was counted at when it was last set. Shared
+    // values such as builders can grow in place afterwards, so the size
+    // removed on reassignment is this one rather than the current size.
+    charged: HashMap<String, usize>,
+    stats: Stats,
+    // Lines printed so far, when `capture_output` is on.
+    captured: Option<Vec<String>>,
+    // Where side-effecting builtin calls are logged, with `--audit`.
+    audit: Option<AuditLog>,
+    random: random::Source,
 }
 
+// The longest `retry` waits between attempts, however far backoff has grown.
+const MAX_RETRY_DELAY_MS: f64 = 3_600_000.0;
+
+// Aliasing rules: numbers, strings, booleans, arrays, sets and maps behave as
+// plain values, so assigning or passing them produces an independent copy and
+// builtins like `push` return a new value instead of changing their argument.
+// Builders, heaps, deques, iterators, file handles, sockets, processes,
+// stores and progress displays are the exception: every copy
+// refers to the same underlying buffer or position, so `builder_push`,
+// consuming an iterator or reading from a file are visible through all aliases. `copy` breaks that sharing for the value
+// itself and `deep_copy` also for builders nested inside collections.
 #[derive(Clone, Debug)]
 pub enum Value {
     Number(i64),
+    Float(f64),
+    // Made by `big`; arithmetic with a whole number stays big.
+    BigInt(BigInt),
+    // Made by `decimal`; exact, and never mixed with floats.
+    Decimal(Decimal),
     String(String),
     Boolean(bool),
     Array(Vec<Value>),
+    Regex(Rc<Regex>),
+    Iterator(LazyIter),
+    Set(ValueSet),
+    // Shared so that `builder_push` appends in place and accumulating a large
+    // string stays linear instead of copying the whole string on every step.
+    Builder(Rc<RefCell<String>>),
+    Heap(Rc<RefCell<Heap>>),
+    // Made by `deque`. Shared, so `shift`, `unshift`, `push` and `pop`
+    // change it in place, each in constant time.
+    Deque(Rc<RefCell<VecDeque<Value>>>),
+    Map(BTreeMap<String, Value>),
+    // Produced by `freeze` together with the line it was called on. Only
+    // assignments and mutating builtins see this wrapper; every other use of
+    // the value goes through `eval`, which unwraps it.
+    Frozen(Box<Value>, usize),
+    Error { kind: String, message: String, line: usize },
+    File(Rc<RefCell<FileHandle>>),
+    Socket(Rc<RefCell<SocketHandle>>),
+    Process(Rc<RefCell<ProcessHandle>>),
+    Store(Rc<RefCell<Store>>),
+    Progress(Rc<RefCell<Progress>>),
+}
+
+// An open file returned by `open`. Reads go through a buffer so `read_line`
+// does not hit the disk for every line; `file` is `None` once closed.
+pub struct FileHandle {
+    path: String,
+    file: Option<BufReader<File>>,
+}
+
+impl fmt::Debug for FileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = if self.file.is_some() { "open" } else { "closed" };
+        write!(f, "<file {:?} ({})>", self.path, state)
+    }
+}
+
+// A TCP connection from `tcp_connect` or `accept`, or a listening socket
+// from `tcp_listen`; `socket` is `None` once closed.
+pub struct SocketHandle {
+    address: String,
+    socket: Option<Socket>,
+}
+
+enum Socket {
+    Stream(BufReader<TcpStream>),
+    Listener(TcpListener),
+}
+
+impl fmt::Debug for SocketHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.socket {
+            Some(Socket::Stream(_)) => write!(f, "<connection {}>", self.address),
+            Some(Socket::Listener(_)) => write!(f, "<listener {}>", self.address),
+            None => write!(f, "<socket {} (closed)>", self.address),
+        }
+    }
+}
+
+impl SocketHandle {
+    fn stream(&mut self, name: &str) -> Result<&mut BufReader<TcpStream>, String> {
+        match self.socket.as_mut() {
+            Some(Socket::Stream(stream)) => Ok(stream),
+            Some(Socket::Listener(_)) => Err(format!("Cannot {} on a listening socket; accept a connection first", name)),
+            None => Err(format!("Socket {} is closed", self.address)),
+        }
+    }
+}
+
+// The items of a set in the order they were added, indexed by `set_key` so
+// adding an item or looking one up does not scan the whole set. Items in a
+// bucket can still differ, so each is checked with `values_equal`.
+#[derive(Clone, Default)]
+pub struct ValueSet {
+    items: Vec<Value>,
+    index: HashMap<String, Vec<usize>>,
+}
+
+impl fmt::Debug for ValueSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(&self.items).finish()
+    }
+}
+
+impl ValueSet {
+    fn contains(&self, value: &Value) -> bool {
+        self.index.get(&set_key(value)).is_some_and(|bucket| bucket.iter().any(|&i| values_equal(&self.items[i], value)))
+    }
+
+    fn insert(&mut self, value: Value) {
+        if !self.contains(&value) {
+            self.index.entry(set_key(&value)).or_default().push(self.items.len());
+            self.items.push(value);
+        }
+    }
+
+    fn retain(&mut self, keep: impl FnMut(&Value) -> bool) {
+        self.items.retain(keep);
+        self.index.clear();
+        for (i, item) in self.items.iter().enumerate() {
+            self.index.entry(set_key(item)).or_default().push(i);
+        }
+    }
+}
+
+impl std::iter::FromIterator<Value> for ValueSet {
+    fn from_iter<I: IntoIterator<Item = Value>>(items: I) -> ValueSet {
+        let mut set = ValueSet::default();
+        items.into_iter().for_each(|item| set.insert(item));
+        set
+    }
+}
+
+// A priority queue made by `heap_new`, stored as a binary heap so pushing
+// and popping take logarithmic time. The root is the item `sort` would put
+// first, or last when `max` is set.
+pub struct Heap {
+    items: Vec<Value>,
+    max: bool,
+}
+
+impl fmt::Debug for Heap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{} heap ({} items)>", if self.max { "max" } else { "min" }, self.items.len())
+    }
+}
+
+impl Heap {
+    // Whether `a` belongs nearer the root than `b`.
+    fn before(&self, a: &Value, b: &Value) -> bool {
+        match compare_values(a, b, false) {
+            Some(ordering) if self.max => ordering.is_gt(),
+            Some(ordering) => ordering.is_lt(),
+            None => false,
+        }
+    }
+
+    fn push(&mut self, value: Value) -> Result<(), String> {
+        if let Some(root) = self.items.first() {
+            if compare_values(&value, root, false).is_none() {
+                return Err(format!("heap_push cannot order {} and {} values", type_name(&value), type_name(root)));
+            }
+        }
+        self.items.push(value);
+        let mut child = self.items.len() - 1;
+        while child > 0 {
+            let parent = (child - 1) / 2;
+            if !self.before(&self.items[child], &self.items[parent]) {
+                break;
+            }
+            self.items.swap(child, parent);
+            child = parent;
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Value> {
+        if self.items.is_empty() {
+            return None;
+        }
+        let root = self.items.swap_remove(0);
+        let mut parent = 0;
+        loop {
+            let mut first = parent;
+            for child in [2 * parent + 1, 2 * parent + 2] {
+                if child < self.items.len() && self.before(&self.items[child], &self.items[first]) {
+                    first = child;
+                }
+            }
+            if first == parent {
+                return Some(root);
+            }
+            self.items.swap(parent, first);
+            parent = first;
+        }
+    }
+}
+
+// A program started by `spawn_process`. The script writes to its standard
+// input and reads its standard output through pipes, while its standard
+// error goes to the terminal; `stdin` is `None` once closed.
+pub struct ProcessHandle {
+    command: String,
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl fmt::Debug for ProcessHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<process {:?} (pid {})>", self.command, self.child.id())
+    }
+}
+
+impl FileHandle {
+    fn reader(&mut self) -> Result<&mut BufReader<File>, String> {
+        match self.file.as_mut() {
+            Some(reader) => Ok(reader),
+            None => Err(format!("File {} is closed", self.path)),
+        }
+    }
+}
+
+// A lazily produced sequence of values, consumed by `for ... in` and
+// `collect`. Clones share the same position, so an iterator that has been
+// partially consumed stays consumed through every variable holding it.
+#[derive(Clone)]
+pub struct LazyIter(Rc<RefCell<ValueStream>>);
+
+type ValueStream = Box<dyn Iterator<Item = Result<Value, String>>>;
+
+impl LazyIter {
+    pub fn new<I: Iterator<Item = Result<Value, String>> + 'static>(iter: I) -> Self {
+        LazyIter(Rc::new(RefCell::new(Box::new(iter))))
+    }
+
+    // The borrow only lasts for one step so loop bodies may use the iterator too.
+    fn next_value(&self) -> Option<Result<Value, String>> {
+        self.0.borrow_mut().next()
+    }
+}
+
+impl fmt::Debug for LazyIter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<iterator>")
+    }
+}
+
+// Renders values the way `join` shows them: strings are inserted as-is at the
+// top level but quoted inside arrays, so nested structure stays readable.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{:?}", n),
+            Value::BigInt(n) => write!(f, "{}", n),
+            Value::Decimal(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Array(arr) => {
+                write!(f, "[")?;
+                for (i, value) in arr.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match value {
+                        Value::String(s) => write!(f, "{:?}", s)?,
+                        other => write!(f, "{}", other)?,
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Regex(re) => write!(f, "{:?}", re),
+            Value::Iterator(iter) => write!(f, "{:?}", iter),
+            Value::Set(set) => write!(f, "set({})", Value::Array(set.items.clone())),
+            Value::Builder(builder) => write!(f, "{}", builder.borrow()),
+            Value::Heap(heap) => write!(f, "{:?}", heap.borrow()),
+            Value::Deque(items) => write!(f, "Deque({:?})", items.borrow()),
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match value {
+                        Value::String(s) => write!(f, "{:?}: {:?}", key, s)?,
+                        other => write!(f, "{:?}: {}", key, other)?,
+                    }
+                }
+                write!(f, "}}")
+            }
+            Value::Frozen(value, _) => write!(f, "{}", value),
+            Value::Error { kind, message, line } => write!(f, "{} error at line {}: {}", kind, line, message),
+            Value::File(handle) => write!(f, "{:?}", handle.borrow()),
+            Value::Socket(handle) => write!(f, "{:?}", handle.borrow()),
+            Value::Process(handle) => write!(f, "{:?}", handle.borrow()),
+            Value::Store(store) => write!(f, "{:?}", store.borrow()),
+            Value::Progress(progress) => write!(f, "{:?}", progress.borrow()),
+        }
+    }
+}
+
+impl Drop for Evaluator {
+    fn drop(&mut self) {
+        for path in self.temp_paths.drain(..).rev() {
+            let _ = if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+        }
+    }
 }
 
 impl Evaluator {
-    pub fn new() -> Self {
+    pub fn with_config(config: EvaluatorConfig) -> Self {
         Evaluator {
             variables: HashMap::new(),
+            current_line: 0,
+            raised_error: None,
+            control: None,
+            loop_label: None,
+            temp_paths: Vec::new(),
+            trace: false,
+            depth: 0,
+            loop_iterations: 0,
+            memory_used: 0,
+            charged: HashMap::new(),
+            stats: Stats::default(),
+            captured: None,
+            audit: None,
+            random: random::Source::new(config.seed),
+            config,
+        }
+    }
+
+    // Makes `print` collect its output lines instead of writing them to
+    // stdout, for the test runner.
+    pub fn capture_output(&mut self) {
+        self.captured = Some(Vec::new());
+    }
+
+    // Prints a line, or collects it when output is being captured.
+    fn write_output(&mut self, text: &str) {
+        match &mut self.captured {
+            Some(output) => output.extend(text.split('\n').map(str::to_string)),
+            None => println!("{}", text),
         }
     }
 
+    // Whoever captures the output, the test runner or a notebook frontend,
+    // also owns stdin, so nothing in the script may wait on it.
+    fn stdin_captured(&mut self) -> String {
+        self.raise("io", "Cannot read stdin while output is captured".to_string())
+    }
+
+    // Returns the lines captured since the last call and starts afresh.
+    pub fn take_output(&mut self) -> Vec<String> {
+        self.captured.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    pub fn set_audit(&mut self, audit: AuditLog) {
+        self.audit = Some(audit);
+    }
+
     pub fn eval(&mut self, node: Rc<RefCell<ASTNode>>) -> Result<Option<Value>, String> {
+        match self.eval_raw(node)? {
+            Some(Value::Frozen(value, _)) => Ok(Some(*value)),
+            other => Ok(other),
+        }
+    }
+
+    // For operands and arguments, which need a value: a builtin such as
+    // `close` that gives none raises an error there instead.
+    fn eval_value(&mut self, node: Rc<RefCell<ASTNode>>) -> Result<Value, String> {
+        match self.eval(Rc::clone(&node))? {
+            Some(value) => Ok(value),
+            None => Err(self.no_value(&node)),
+        }
+    }
+
+    fn no_value(&mut self, node: &Rc<RefCell<ASTNode>>) -> String {
+        let message = match &*node.borrow() {
+            ASTNode::FunctionCall(name, _) => format!("{} does not return a value", name),
+            _ => "Expression does not have a value".to_string(),
+        };
+        self.raise("value", message)
+    }
+
+    fn eval_raw(&mut self, node: Rc<RefCell<ASTNode>>) -> Result<Option<Value>, String> {
+        if let Some(max) = self.config.max_depth {
+            if self.depth >= max {
+                return Err(self.raise("limit", format!("Maximum nesting depth of {} exceeded", max)));
+            }
+        }
+        self.depth += 1;
+        let result = self.eval_node(node);
+        self.depth -= 1;
+        let value = result?;
+        match &value {
+            Some(Value::String(s)) => self.check_string_length(s.len())?,
+            Some(Value::Array(arr)) => self.check_array_length(arr.len())?,
+            _ => {}
+        }
+        if let Some(Value::String(_) | Value::Array(_) | Value::Set(_) | Value::Map(_)) = value {
+            self.stats.allocations += 1;
+        }
+        Ok(value)
+    }
+
+    fn eval_node(&mut self, node: Rc<RefCell<ASTNode>>) -> Result<Option<Value>, String> {
         match &*node.borrow() {
             ASTNode::Program(statements) => {
                 let mut last_result = None;
@@ -33,11 +529,12 @@ impl Evaluator {
                 Ok(last_result)
             }
             ASTNode::Number(value) => Ok(Some(Value::Number(*value))),
+            ASTNode::Float(value) => Ok(Some(Value::Float(*value))),
             ASTNode::StringLiteral(value) => Ok(Some(Value::String(value.clone()))),
             ASTNode::Boolean(value) => Ok(Some(Value::Boolean(*value))),
             ASTNode::BinaryOp(left, op, right) => {
-                let left_val = self.eval(Rc::clone(left))?.unwrap();
-                let right_val = self.eval(Rc::clone(right))?.unwrap();
+                let left_val = self.eval_value(Rc::clone(left))?;
+                let right_val = self.eval_value(Rc::clone(right))?;
                 let op_clone = op.clone();
                 
                 // Clone the values before the match statement
@@ -45,33 +542,121 @@ impl Evaluator {
                 let right_clone = right_val.clone();
                 
                 match (left_val, op_clone, right_val) {
-                    (Value::Number(l), Token::Plus, Value::Number(r)) => Ok(Some(Value::Number(l + r))),
-                    (Value::Number(l), Token::Minus, Value::Number(r)) => Ok(Some(Value::Number(l - r))),
-                    (Value::Number(l), Token::Star, Value::Number(r)) => Ok(Some(Value::Number(l * r))),
-                    (Value::Number(l), Token::Slash, Value::Number(r)) => Ok(Some(Value::Number(l / r))),
+                    (Value::Number(l), operator, Value::Number(r)) if is_arithmetic(&operator) => {
+                        let result = match operator {
+                            Token::Plus => l.checked_add(r),
+                            Token::Minus => l.checked_sub(r),
+                            Token::Star => l.checked_mul(r),
+                            _ if r == 0 => return Err(self.raise("division_by_zero", format!("Division by zero: {} / 0", l))),
+                            _ => l.checked_div(r),
+                        };
+                        match result {
+                            Some(n) => Ok(Some(Value::Number(n))),
+                            None => Err(self.raise("overflow", format!("Integer overflow in {} {} {}; use big() for larger numbers", l, operator_symbol(&operator), r))),
+                        }
+                    }
+                    (l, operator, r) if is_arithmetic(&operator) && (is_big(&l) || is_big(&r)) => match (as_big(&l), as_big(&r)) {
+                        (Some(l), Some(r)) => match operator {
+                            Token::Plus => Ok(Some(Value::BigInt(l.add(&r)))),
+                            Token::Minus => Ok(Some(Value::BigInt(l.sub(&r)))),
+                            Token::Star => Ok(Some(Value::BigInt(l.mul(&r)))),
+                            _ if r.is_zero() => Err(self.raise("division_by_zero", format!("Division by zero: {} / 0", l))),
+                            _ => Ok(Some(Value::BigInt(l.div(&r)))),
+                        },
+                        _ => Err(format!("Invalid operation: {:?} {:?} {:?}", left_clone, op, right_clone)),
+                    },
+                    // Whole numbers widen to decimals; floats are refused so
+                    // an inexact value never slips into a decimal result.
+                    (l, operator, r) if is_arithmetic(&operator) && (is_decimal(&l) || is_decimal(&r)) => match (as_decimal(&l), as_decimal(&r)) {
+                        (Some(l), Some(r)) => {
+                            let result = match operator {
+                                Token::Plus => l.add(&r),
+                                Token::Minus => l.sub(&r),
+                                Token::Star => l.mul(&r),
+                                _ if r.is_zero() => return Err(self.raise("division_by_zero", format!("Division by zero: {} / 0", l))),
+                                _ => l.div(&r),
+                            };
+                            result.map(|n| Some(Value::Decimal(n))).map_err(|e| self.raise("overflow", e))
+                        }
+                        _ if is_float(&left_clone) || is_float(&right_clone) => Err("Cannot mix a decimal with a float; convert the float with decimal()".to_string()),
+                        _ => Err(format!("Invalid operation: {:?} {:?} {:?}", left_clone, op, right_clone)),
+                    },
+                    // Mixing a float with a number gives a float.
+                    (l, operator, r) if is_float(&l) || is_float(&r) => match (as_float(&l), operator, as_float(&r)) {
+                        (Some(l), Token::Plus, Some(r)) => Ok(Some(Value::Float(l + r))),
+                        (Some(l), Token::Minus, Some(r)) => Ok(Some(Value::Float(l - r))),
+                        (Some(l), Token::Star, Some(r)) => Ok(Some(Value::Float(l * r))),
+                        (Some(l), Token::Slash, Some(r)) => Ok(Some(Value::Float(l / r))),
+                        _ => Err(format!("Invalid operation: {:?} {:?} {:?}", left_clone, op, right_clone)),
+                    },
+                    (Value::String(s), Token::Star, Value::Number(n)) => {
+                        let count = repetition_count(s.len(), 1, n).map_err(|e| self.raise("overflow", e))?;
+                        self.check_string_length(s.len() * count)?;
+                        let mut repeated = String::new();
+                        if repeated.try_reserve_exact(s.len() * count).is_err() {
+                            return Err(self.raise("overflow", format!("Not enough memory to repeat a string of length {} {} times", s.len(), count)));
+                        }
+                        repeated.extend(std::iter::repeat_n(s.as_str(), count));
+                        Ok(Some(Value::String(repeated)))
+                    }
+                    (Value::Array(arr), Token::Star, Value::Number(n)) => {
+                        let count = repetition_count(arr.len(), std::mem::size_of::<Value>(), n).map_err(|e| self.raise("overflow", e))?;
+                        self.check_array_length(arr.len() * count)?;
+                        let mut repeated = Vec::new();
+                        if repeated.try_reserve_exact(arr.len() * count).is_err() {
+                            return Err(self.raise("overflow", format!("Not enough memory to repeat an array of length {} {} times", arr.len(), count)));
+                        }
+                        repeated.extend(arr.iter().cycle().take(arr.len() * count).cloned());
+                        Ok(Some(Value::Array(repeated)))
+                    }
                     _ => Err(format!("Invalid operation: {:?} {:?} {:?}", left_clone, op, right_clone)),
                 }
             }
             ASTNode::Identifier(name) => {
-                Ok(Some(self.variables.get(name).unwrap_or_else(|| panic!("Undefined variable: {}", name)).clone()))
+                // An error rather than a panic, so the REPL survives a typo.
+                match self.variables.get(name) {
+                    Some(value) => Ok(Some(value.clone())),
+                    None => Err(self.undefined(name)),
+                }
             }
             ASTNode::Assign(name, expr) => {
-                let value = self.eval(Rc::clone(expr))?.unwrap();
-                self.variables.insert(name.clone(), value.clone());
+                let Some(value) = self.eval_raw(Rc::clone(expr))? else {
+                    return Err(self.no_value(expr));
+                };
+                self.set_variable(name, value.clone())?;
                 Ok(Some(value))
             }
+            // The container is updated in a copy, so a failed assignment
+            // leaves the variable as it was.
+            ASTNode::IndexAssign(name, indexes, value) => {
+                let mut keys = Vec::new();
+                for index in indexes {
+                    keys.push(self.eval_value(Rc::clone(index))?);
+                }
+                let value = self.eval_value(Rc::clone(value))?;
+                let mut target = match self.variables.get(name) {
+                    Some(target) => target.clone(),
+                    None => return Err(self.undefined(name)),
+                };
+                assign_at(&mut target, &keys, value)?;
+                self.set_variable(name, target)?;
+                Ok(None)
+            }
             ASTNode::Print(expr) => {
                 if let Some(value) = self.eval(Rc::clone(expr))? {
-                    match value {
-                        Value::Number(n) => println!("{}", n),
-                        Value::String(s) => println!("{}", s),
-                        Value::Boolean(b) => println!("{}", b),
-                        Value::Array(arr) => println!("{:?}", arr),
-                    }
+                    self.write_output(&print_format(&value));
                 }
                 Ok(None)
             }
             ASTNode::FunctionCall(name, args) => {
+                self.stats.function_calls += 1;
+                let spread;
+                let args = if args.iter().any(|arg| matches!(*arg.borrow(), ASTNode::Spread(_))) {
+                    spread = self.spread_arguments(args)?;
+                    &spread
+                } else {
+                    args
+                };
                 match name.as_str() {
                     "join" => self.join_function(args),
                     "split" => self.split_function(args),
@@ -87,12 +672,138 @@ impl Evaluator {
                     "last" => self.last_function(args),
                     "read_file" => self.read_file_function(args),
                     "write_file" => self.write_file_function(args),
+                    "regex" => self.regex_function(args),
+                    "split_lines" => self.split_lines_function(args),
+                    "split_whitespace" => self.split_whitespace_function(args),
+                    "regex_replace" => self.regex_replace_function(args),
+                    "range" => self.range_function(args),
+                    "collect" => self.collect_function(args),
+                    "set" => self.set_function(args),
+                    "add" => self.add_function(args),
+                    "has" => self.has_function(args),
+                    "remove" => self.remove_function(args),
+                    "union" => self.union_function(args),
+                    "intersect" => self.intersect_function(args),
+                    "builder_new" => self.builder_new_function(args),
+                    "builder_push" => self.builder_push_function(args),
+                    "builder_build" => self.builder_build_function(args),
+                    "merge" => self.merge_function(args),
+                    "get" => self.get_function(args),
+                    "entries" => self.entries_function(args),
+                    "from_entries" => self.from_entries_function(args),
+                    "copy" => self.copy_function(args),
+                    "deep_copy" => self.deep_copy_function(args),
+                    "freeze" => self.freeze_function(args),
+                    "error" => self.error_function(args),
+                    "error_kind" => self.error_kind_function(args),
+                    "error_message" => self.error_message_function(args),
+                    "open" => self.open_function(args),
+                    "read_line" => self.read_line_function(args),
+                    "write" => self.write_function(args),
+                    "seek" => self.seek_function(args),
+                    "eof" => self.eof_function(args),
+                    "close" => self.close_function(args),
+                    "lines" => self.lines_function(args),
+                    "stdin_lines" => self.stdin_lines_function(args),
+                    "glob" => self.glob_function(args),
+                    "fnmatch" => self.fnmatch_function(args),
+                    "walk_dir" => self.walk_dir_function(args),
+                    "temp_file" => self.temp_file_function(args),
+                    "temp_dir" => self.temp_dir_function(args),
+                    "copy_file" => self.copy_file_function(args),
+                    "move_file" => self.move_file_function(args),
+                    "mkdir" => self.mkdir_function(args),
+                    "mkdirs" => self.mkdirs_function(args),
+                    "chmod" => self.chmod_function(args),
+                    "touch" => self.touch_function(args),
+                    "memory_used" => self.memory_used_function(args),
+                    "tcp_connect" => self.tcp_connect_function(args),
+                    "tcp_listen" => self.tcp_listen_function(args),
+                    "accept" => self.accept_function(args),
+                    "socket_port" => self.socket_port_function(args),
+                    "send" => self.send_function(args),
+                    "recv" => self.recv_function(args),
+                    "url_parse" => self.url_parse_function(args),
+                    "url_encode" => self.url_encode_function(args),
+                    "url_decode" => self.url_decode_function(args),
+                    "query_string" => self.query_string_function(args),
+                    "store_open" => self.store_open_function(args),
+                    "store_get" => self.store_get_function(args),
+                    "store_set" => self.store_set_function(args),
+                    "store_delete" => self.store_delete_function(args),
+                    "load_env" => self.load_env_function(args),
+                    "env" => self.env_function(args),
+                    "color" => self.color_function(args),
+                    "bold" => self.bold_function(args),
+                    "is_tty" => self.is_tty_function(args),
+                    "term_clear" => self.term_clear_function(args),
+                    "term_size" => self.term_size_function(args),
+                    "term_move" => self.term_move_function(args),
+                    "term_cursor" => self.term_cursor_function(args),
+                    "progress_start" => self.progress_start_function(args),
+                    "progress_tick" => self.progress_tick_function(args),
+                    "progress_finish" => self.progress_finish_function(args),
+                    "confirm" => self.confirm_function(args),
+                    "select" => self.select_function(args),
+                    "password" => self.password_function(args),
+                    "mean" => self.mean_function(args),
+                    "median" => self.median_function(args),
+                    "stddev" => self.stddev_function(args),
+                    "percentile" => self.percentile_function(args),
+                    "big" => self.big_function(args),
+                    "decimal" => self.decimal_function(args),
+                    "decimal_round" => self.decimal_round_function(args),
+                    "uuid" => self.uuid_function(args),
+                    "random_hex" => self.random_hex_function(args),
+                    "random_token" => self.random_token_function(args),
+                    "sha256" => self.sha256_function(args),
+                    "hmac_sha256" => self.hmac_sha256_function(args),
+                    "secure_equals" => self.secure_equals_function(args),
+                    "eval" => self.eval_function(args),
+                    "vars" => self.vars_function(args),
+                    "functions" => self.functions_function(args),
+                    "defined" => self.defined_function(args),
+                    "help" => self.help_function(args),
+                    "render" => self.render_function(args),
+                    "retry" => self.retry_function(args),
+                    "cache_file" => self.cache_file_function(args),
+                    "time" => self.time_function(args),
+                    "benchmark" => self.benchmark_function(args),
+                    "spawn_process" => self.spawn_process_function(args),
+                    "proc_read_line" => self.proc_read_line_function(args),
+                    "proc_write" => self.proc_write_function(args),
+                    "proc_wait" => self.proc_wait_function(args),
+                    "pipe" => self.pipe_function(args),
+                    "clipboard_get" => self.clipboard_get_function(args),
+                    "clipboard_set" => self.clipboard_set_function(args),
+                    "platform" => self.platform_function(args),
+                    "hostname" => self.hostname_function(args),
+                    "cpu_count" => self.cpu_count_function(args),
+                    "home_dir" => self.home_dir_function(args),
+                    "current_dir" => self.current_dir_function(args),
+                    "set_current_dir" => self.set_current_dir_function(args),
+                    "edit_distance" => self.edit_distance_function(args),
+                    "fuzzy_match" => self.fuzzy_match_function(args),
+                    "sort" => self.sort_function(args),
+                    "sort_by" => self.sort_by_function(args),
+                    "group_by" => self.group_by_function(args),
+                    "partition" => self.partition_function(args),
+                    "chunk" => self.chunk_function(args),
+                    "windows" => self.windows_function(args),
+                    "binary_search" => self.binary_search_function(args),
+                    "insert_sorted" => self.insert_sorted_function(args),
+                    "heap_new" => self.heap_new_function(args),
+                    "heap_push" => self.heap_push_function(args),
+                    "heap_pop" => self.heap_pop_function(args),
+                    "deque" => self.deque_function(args),
+                    "shift" => self.shift_function(args),
+                    "unshift" => self.unshift_function(args),
                     _ => Err(format!("Unknown function: {}", name)),
                 }
             }
             ASTNode::Comparison(left, op, right) => {
-                let left_val = self.eval(Rc::clone(left))?.unwrap();
-                let right_val = self.eval(Rc::clone(right))?.unwrap();
+                let left_val = self.eval_value(Rc::clone(left))?;
+                let right_val = self.eval_value(Rc::clone(right))?;
                 let op_clone = op.clone();
                 
                 // Clone the values before the match statement
@@ -106,16 +817,74 @@ impl Evaluator {
                     (Value::Number(l), Token::Gt, Value::Number(r)) => l > r,
                     (Value::Number(l), Token::LtEq, Value::Number(r)) => l <= r,
                     (Value::Number(l), Token::GtEq, Value::Number(r)) => l >= r,
+                    (l, operator, r) if (is_big(&l) || is_big(&r)) && as_big(&l).is_some() && as_big(&r).is_some() => {
+                        let ordering = as_big(&l).unwrap().cmp(&as_big(&r).unwrap());
+                        match operator {
+                            Token::Eq => ordering.is_eq(),
+                            Token::NotEq => ordering.is_ne(),
+                            Token::Lt => ordering.is_lt(),
+                            Token::Gt => ordering.is_gt(),
+                            Token::LtEq => ordering.is_le(),
+                            _ => ordering.is_ge(),
+                        }
+                    }
+                    (l, operator, r) if (is_decimal(&l) || is_decimal(&r)) && as_decimal(&l).is_some() && as_decimal(&r).is_some() => {
+                        let ordering = as_decimal(&l).unwrap().compare(&as_decimal(&r).unwrap());
+                        match operator {
+                            Token::Eq => ordering.is_eq(),
+                            Token::NotEq => ordering.is_ne(),
+                            Token::Lt => ordering.is_lt(),
+                            Token::Gt => ordering.is_gt(),
+                            Token::LtEq => ordering.is_le(),
+                            _ => ordering.is_ge(),
+                        }
+                    }
+                    (l, operator, r) if (is_float(&l) || is_float(&r)) && as_float(&l).is_some() && as_float(&r).is_some() => {
+                        let (l, r) = (as_float(&l).unwrap(), as_float(&r).unwrap());
+                        match operator {
+                            Token::Eq => l == r,
+                            Token::NotEq => l != r,
+                            Token::Lt => l < r,
+                            Token::Gt => l > r,
+                            Token::LtEq => l <= r,
+                            _ => l >= r,
+                        }
+                    }
                     (Value::String(l), Token::Eq, Value::String(r)) => l == r,
                     (Value::String(l), Token::NotEq, Value::String(r)) => l != r,
+                    (Value::String(l), Token::Lt, Value::String(r)) => l < r,
+                    (Value::String(l), Token::Gt, Value::String(r)) => l > r,
+                    (Value::String(l), Token::LtEq, Value::String(r)) => l <= r,
+                    (Value::String(l), Token::GtEq, Value::String(r)) => l >= r,
                     (Value::Boolean(l), Token::Eq, Value::Boolean(r)) => l == r,
                     (Value::Boolean(l), Token::NotEq, Value::Boolean(r)) => l != r,
+                    (Value::Array(l), Token::Eq, Value::Array(r)) => arrays_equal(&l, &r),
+                    (Value::Array(l), Token::NotEq, Value::Array(r)) => !arrays_equal(&l, &r),
+                    (l @ Value::Set(_), Token::Eq, r @ Value::Set(_)) => values_equal(&l, &r),
+                    (l @ Value::Set(_), Token::NotEq, r @ Value::Set(_)) => !values_equal(&l, &r),
+                    (l @ Value::Map(_), Token::Eq, r @ Value::Map(_)) => values_equal(&l, &r),
+                    (l @ Value::Map(_), Token::NotEq, r @ Value::Map(_)) => !values_equal(&l, &r),
                     _ => return Err(format!("Invalid comparison: {:?} {:?} {:?}", left_clone, op_clone, right_clone)),
                 };
                 Ok(Some(Value::Boolean(result)))
             }
+            // Each comparison runs on values already computed, so an operand
+            // with side effects, such as `shift(queue)`, runs once.
+            ASTNode::ChainedComparison(operands, operators) => {
+                let mut left = self.eval_value(Rc::clone(&operands[0]))?;
+                for (operand, operator) in operands[1..].iter().zip(operators) {
+                    let right = self.eval_value(Rc::clone(operand))?;
+                    let evaluated = |value: &Value| Rc::new(RefCell::new(ASTNode::Evaluated(value.clone())));
+                    let comparison = ASTNode::Comparison(evaluated(&left), operator.clone(), evaluated(&right));
+                    if let Some(Value::Boolean(false)) = self.eval(Rc::new(RefCell::new(comparison)))? {
+                        return Ok(Some(Value::Boolean(false)));
+                    }
+                    left = right;
+                }
+                Ok(Some(Value::Boolean(true)))
+            }
             ASTNode::LogicalOp(left, op, right) => {
-                let left_val = self.eval(Rc::clone(left))?.unwrap();
+                let left_val = self.eval_value(Rc::clone(left))?;
                 let op_clone = op.clone();
                 
                 // Clone the value before the match statement
@@ -130,7 +899,7 @@ impl Evaluator {
                 }
             }
             ASTNode::Not(expr) => {
-                let val = self.eval(Rc::clone(expr))?.unwrap();
+                let val = self.eval_value(Rc::clone(expr))?;
                 match val {
                     Value::Boolean(b) => Ok(Some(Value::Boolean(!b))),
                     _ => Err(format!("Cannot apply 'not' to non-boolean value: {:?}", val)),
@@ -139,26 +908,79 @@ impl Evaluator {
             ASTNode::Array(elements) => {
                 let mut array_values = Vec::new();
                 for element in elements {
+                    if let ASTNode::Spread(items) = &*element.borrow() {
+                        let iter = into_iter(self.eval_value(Rc::clone(items))?)?;
+                        while let Some(item) = iter.next_value() {
+                            array_values.push(item?);
+                        }
+                        continue;
+                    }
                     if let Some(value) = self.eval(Rc::clone(element))? {
                         array_values.push(value);
                     }
                 }
                 Ok(Some(Value::Array(array_values)))
             }
+            ASTNode::Map(entries) => {
+                let mut map = BTreeMap::new();
+                for (key, value) in entries {
+                    let key = match self.eval_value(Rc::clone(key))? {
+                        Value::String(s) => s,
+                        other => return Err(format!("Map keys must be strings, found {:?}", other)),
+                    };
+                    let value = self.eval_value(Rc::clone(value))?;
+                    map.insert(key, value);
+                }
+                Ok(Some(Value::Map(map)))
+            }
             ASTNode::IndexAccess(array, index) => {
-                let array_value = self.eval(Rc::clone(array))?.unwrap();
-                let index_value = self.eval(Rc::clone(index))?.unwrap();
+                let array_value = self.eval_value(Rc::clone(array))?;
+                let index_value = self.eval_value(Rc::clone(index))?;
                 match (array_value, index_value) {
-                    (Value::Array(arr), Value::Number(idx)) => {
-                        if idx < 0 || idx >= arr.len() as i64 {
-                            Err(format!("Index out of bounds: {}", idx))
-                        } else {
-                            Ok(Some(arr[idx as usize].clone()))
+                    (Value::Array(arr), Value::Number(idx)) => match index_position(arr.len(), idx) {
+                        Some(position) => Ok(Some(arr[position].clone())),
+                        None => Err(format!("Index out of bounds: {}", idx)),
+                    },
+                    // A string's elements are its characters, each a string of one.
+                    (Value::String(text), Value::Number(idx)) => {
+                        let chars: Vec<char> = text.chars().collect();
+                        match index_position(chars.len(), idx) {
+                            Some(position) => Ok(Some(Value::String(chars[position].to_string()))),
+                            None => Err(format!("Index out of bounds: {}", idx)),
                         }
                     }
+                    (Value::Map(map), Value::String(key)) => match map.get(&key) {
+                        Some(value) => Ok(Some(value.clone())),
+                        None => Err(format!("Key not found: {}", key)),
+                    },
                     _ => Err(format!("Invalid index access")),
                 }
             }
+            // Slices copy, so changing one leaves the original alone.
+            ASTNode::Slice(target, start, end) => {
+                let target = self.eval_value(Rc::clone(target))?;
+                let mut bounds = [None, None];
+                for (bound, node) in bounds.iter_mut().zip([start, end]) {
+                    if let Some(node) = node {
+                        match self.eval_value(Rc::clone(node))? {
+                            Value::Number(n) => *bound = Some(n),
+                            other => return Err(format!("Slice bounds must be numbers, found {}", type_name(&other))),
+                        }
+                    }
+                }
+                match target {
+                    Value::Array(items) => {
+                        let (start, end) = slice_range(items.len(), bounds[0], bounds[1]);
+                        Ok(Some(Value::Array(items[start..end].to_vec())))
+                    }
+                    Value::String(text) => {
+                        let chars: Vec<char> = text.chars().collect();
+                        let (start, end) = slice_range(chars.len(), bounds[0], bounds[1]);
+                        Ok(Some(Value::String(chars[start..end].iter().collect())))
+                    }
+                    other => Err(format!("Cannot slice a {}", type_name(&other))),
+                }
+            }
             ASTNode::If(condition, if_block, else_if_blocks, else_block) => {
                 if self.eval_boolean_expression(Rc::clone(condition))? {
                     self.eval_block(if_block)
@@ -176,22 +998,288 @@ impl Evaluator {
                 }
             }
             ASTNode::While(condition, block) => {
+                let label = self.loop_label.take();
                 while self.eval_boolean_expression(Rc::clone(condition))? {
-                    self.eval_block(block)?;
+                    self.count_loop_iteration()?;
+                    let result = self.eval_block(block);
+                    if let Flow::Break = self.loop_flow(&label, result)? {
+                        break;
+                    }
                 }
                 Ok(None)
             }
             ASTNode::For(init, condition, update, block) => {
+                let label = self.loop_label.take();
                 self.eval(Rc::clone(init))?;
                 while self.eval_boolean_expression(Rc::clone(condition))? {
-                    self.eval_block(block)?;
+                    self.count_loop_iteration()?;
+                    let result = self.eval_block(block);
+                    if let Flow::Break = self.loop_flow(&label, result)? {
+                        break;
+                    }
                     self.eval(Rc::clone(update))?;
                 }
                 Ok(None)
             }
+            ASTNode::ForIn(name, iterable, block) => {
+                let label = self.loop_label.take();
+                let iter = into_iter(self.eval_value(Rc::clone(iterable))?)?;
+                while let Some(item) = iter.next_value() {
+                    self.count_loop_iteration()?;
+                    self.set_variable(name, item?)?;
+                    let result = self.eval_block(block);
+                    if let Flow::Break = self.loop_flow(&label, result)? {
+                        break;
+                    }
+                }
+                Ok(None)
+            }
+            ASTNode::Repeat(count, block) => {
+                let label = self.loop_label.take();
+                let count = match self.eval_value(Rc::clone(count))? {
+                    Value::Number(count) if count >= 0 => count,
+                    other => return Err(format!("repeat needs a count of zero or more, found {}", print_format(&other))),
+                };
+                for _ in 0..count {
+                    self.count_loop_iteration()?;
+                    let result = self.eval_block(block);
+                    if let Flow::Break = self.loop_flow(&label, result)? {
+                        break;
+                    }
+                }
+                Ok(None)
+            }
+            ASTNode::Labeled(label, body) => {
+                self.loop_label = Some(label.clone());
+                self.eval(Rc::clone(body))
+            }
+            ASTNode::Break(label) => {
+                self.control = Some(Control::Break(label.clone()));
+                Err("`break` outside a loop".to_string())
+            }
+            ASTNode::Continue(label) => {
+                self.control = Some(Control::Continue(label.clone()));
+                Err("`continue` outside a loop".to_string())
+            }
+            ASTNode::Spread(_) => Err("`...` can only be used in an array literal or a function's arguments".to_string()),
+            ASTNode::Evaluated(value) => Ok(Some(value.clone())),
+            // The variable is set as in a `for` loop and keeps its last value.
+            ASTNode::Comprehension(element, name, iterable, condition) => {
+                let iter = into_iter(self.eval_value(Rc::clone(iterable))?)?;
+                let mut values = Vec::new();
+                while let Some(item) = iter.next_value() {
+                    self.count_loop_iteration()?;
+                    self.set_variable(name, item?)?;
+                    if let Some(condition) = condition {
+                        if !self.eval_boolean_expression(Rc::clone(condition))? {
+                            continue;
+                        }
+                    }
+                    if let Some(value) = self.eval(Rc::clone(element))? {
+                        values.push(value);
+                    }
+                }
+                Ok(Some(Value::Array(values)))
+            }
+            ASTNode::TryCatch(try_block, name, catch_block) => match self.eval_block(try_block) {
+                Ok(result) => Ok(result),
+                Err(message) if self.control.is_some() => Err(message),
+                Err(message) => {
+                    let error = self.caught_error(message);
+                    self.set_variable(name, error)?;
+                    self.eval_block(catch_block)
+                }
+            },
+            ASTNode::Statement(line, statement) => {
+                // On success the enclosing statement's line is restored, so an
+                // error in a loop condition is reported on the loop itself. On
+                // failure the line of the innermost failing statement is kept.
+                let outer_line = self.current_line;
+                self.current_line = *line;
+                self.stats.statements += 1;
+                if self.trace {
+                    eprintln!("[trace] line {}", line);
+                }
+                let result = self.eval_raw(Rc::clone(statement))?;
+                self.current_line = outer_line;
+                Ok(result)
+            }
+        }
+    }
+
+    // Decides how a loop labeled `label` goes on after its body gave
+    // `result`, taking any `break` or `continue` meant for it. Errors, and
+    // signals meant for an outer loop, are passed on.
+    fn loop_flow(&mut self, label: &Option<String>, result: Result<Option<Value>, String>) -> Result<Flow, String> {
+        let Err(message) = result else {
+            return Ok(Flow::Next);
+        };
+        match self.control.take() {
+            Some(Control::Break(target)) if target.is_none() || target == *label => Ok(Flow::Break),
+            Some(Control::Continue(target)) if target.is_none() || target == *label => Ok(Flow::Next),
+            control => {
+                self.control = control;
+                Err(message)
+            }
+        }
+    }
+
+    // Builtins evaluate their own arguments, so spread arguments are
+    // evaluated first and each item is passed on as an already computed
+    // argument.
+    fn spread_arguments(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Vec<Rc<RefCell<ASTNode>>>, String> {
+        let mut expanded = Vec::new();
+        for arg in args {
+            if let ASTNode::Spread(items) = &*arg.borrow() {
+                let iter = into_iter(self.eval_value(Rc::clone(items))?)?;
+                while let Some(item) = iter.next_value() {
+                    expanded.push(Rc::new(RefCell::new(ASTNode::Evaluated(item?))));
+                }
+                continue;
+            }
+            expanded.push(Rc::clone(arg));
+        }
+        Ok(expanded)
+    }
+
+    // The line of the statement being executed, or of the statement that
+    // failed once `eval` has returned an error.
+    pub fn current_line(&self) -> usize {
+        self.current_line
+    }
+
+    // Evaluates the collection a mutating builtin is about to change,
+    // refusing values that went through `freeze`.
+    fn eval_unfrozen(&mut self, node: &Rc<RefCell<ASTNode>>, action: &str) -> Result<Value, String> {
+        match self.eval_raw(Rc::clone(node))?.ok_or_else(|| self.no_value(node))? {
+            Value::Frozen(value, line) => Err(format!("Cannot {} a frozen {} (frozen at line {})", action, type_name(&value), line)),
+            value => Ok(value),
+        }
+    }
+
+    // Turns a failure into the error value a script sees, keeping the kind
+    // recorded by `raise` when the message matches.
+    pub fn caught_error(&mut self, message: String) -> Value {
+        match self.raised_error.take() {
+            Some(error @ Value::Error { .. }) if error_message_of(&error) == message => error,
+            _ => Value::Error { kind: "runtime".to_string(), message, line: self.current_line },
+        }
+    }
+
+    // Records an error with a specific kind so `catch` can bind it, and
+    // returns its message for the usual error path.
+    fn raise(&mut self, kind: &str, message: String) -> String {
+        self.raised_error = Some(Value::Error { kind: kind.to_string(), message: message.clone(), line: self.current_line });
+        message
+    }
+
+    // Every I/O builtin announces what it is about to touch through these.
+    // They log the call when auditing, then pass when the sandbox is off and
+    // otherwise raise a "permission_denied" error for anything not granted.
+    fn check_read(&mut self, builtin: &str, path: &str) -> Result<(), String> {
+        let checked = self.config.permissions.as_ref().map_or(Ok(()), |permissions| permissions.check_read(path));
+        self.checked(checked, builtin, "read", path)
+    }
+
+    fn check_write(&mut self, builtin: &str, path: &str) -> Result<(), String> {
+        let checked = self.config.permissions.as_ref().map_or(Ok(()), |permissions| permissions.check_write(path));
+        self.checked(checked, builtin, "write", path)
+    }
+
+    fn check_net(&mut self, builtin: &str, action: &str, host: &str, port: u16) -> Result<(), String> {
+        let checked = self.config.permissions.as_ref().map_or(Ok(()), |permissions| permissions.check_net(host, port));
+        self.checked(checked, builtin, action, &format!("{}:{}", host, port))
+    }
+
+    fn check_run(&mut self, builtin: &str, program: &str) -> Result<(), String> {
+        let checked = self.config.permissions.as_ref().map_or(Ok(()), |permissions| permissions.check_run(program));
+        self.checked(checked, builtin, "run", program)
+    }
+
+    // `name` is the variable read or set, or "*" for the whole environment.
+    fn check_env(&mut self, builtin: &str, name: &str) -> Result<(), String> {
+        let checked = self.config.permissions.as_ref().map_or(Ok(()), |permissions| permissions.check_env());
+        self.checked(checked, builtin, "env", name)
+    }
+
+    fn would(&self, action: String) {
+        eprintln!("dry run, line {}: would {}", self.current_line, action);
+    }
+
+    fn checked(&mut self, checked: Result<(), String>, builtin: &str, action: &str, target: &str) -> Result<(), String> {
+        if let Some(audit) = &mut self.audit {
+            audit.record(self.current_line, builtin, action, target, checked.is_ok());
+        }
+        checked.map_err(|e| self.raise("permission_denied", e))
+    }
+
+    fn undefined(&self, name: &str) -> String {
+        let mut names: Vec<&str> = self.variables.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        match fuzzy::suggest(name, names) {
+            Some(similar) => format!("Undefined variable: {}; did you mean {}?", name, similar),
+            None => format!("Undefined variable: {}", name),
+        }
+    }
+
+    // All variable writes go through here to keep the memory count current.
+    fn set_variable(&mut self, name: &str, value: Value) -> Result<(), String> {
+        let old_size = self.charged.get(name).copied().unwrap_or(0);
+        let new_size = value_size(&value);
+        let used = self.memory_used - old_size + new_size;
+        if let Some(max) = self.config.max_memory {
+            if used > max {
+                return Err(self.raise("limit", format!("Memory use of {} bytes would exceed the maximum of {}", used, max)));
+            }
+        }
+        self.memory_used = used;
+        self.stats.peak_memory = self.stats.peak_memory.max(used);
+        self.charged.insert(name.to_string(), new_size);
+        self.variables.insert(name.to_string(), value);
+        self.stats.peak_variables = self.stats.peak_variables.max(self.variables.len());
+        Ok(())
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    fn count_loop_iteration(&mut self) -> Result<(), String> {
+        self.loop_iterations += 1;
+        match self.config.max_loop_iterations {
+            Some(max) if self.loop_iterations > max => Err(self.raise("limit", format!("Maximum of {} loop iterations exceeded", max))),
+            _ => Ok(()),
+        }
+    }
+
+    // Also called before building a repeated string or array, so an
+    // oversized result is refused before the memory is allocated.
+    fn check_string_length(&mut self, len: usize) -> Result<(), String> {
+        match self.config.max_string_length {
+            Some(max) if len > max => Err(self.raise("limit", format!("String of length {} exceeds the maximum of {}", len, max))),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_array_length(&mut self, len: usize) -> Result<(), String> {
+        match self.config.max_array_length {
+            Some(max) if len > max => Err(self.raise("limit", format!("Array of length {} exceeds the maximum of {}", len, max))),
+            _ => Ok(()),
         }
     }
 
+    fn io_error(&mut self, error: std::io::Error) -> String {
+        let kind = match error.kind() {
+            std::io::ErrorKind::NotFound => "not_found",
+            std::io::ErrorKind::PermissionDenied => "permission_denied",
+            std::io::ErrorKind::AlreadyExists => "already_exists",
+            std::io::ErrorKind::ConnectionRefused => "connection_refused",
+            std::io::ErrorKind::TimedOut => "timeout",
+            _ => "io",
+        };
+        self.raise(kind, error.to_string())
+    }
+
     fn eval_boolean_expression(&mut self, node: Rc<RefCell<ASTNode>>) -> Result<bool, String> {
         match self.eval(node)? {
             Some(Value::Boolean(b)) => Ok(b),
@@ -211,21 +1299,16 @@ impl Evaluator {
         if args.len() != 2 {
             return Err("join function requires 2 arguments".to_string());
         }
-        let separator = match self.eval(Rc::clone(&args[0]))?.unwrap() {
+        let separator = match self.eval_value(Rc::clone(&args[0]))? {
             Value::String(s) => s,
             _ => return Err("First argument of join must be a string".to_string()),
         };
-        let elements = match self.eval(Rc::clone(&args[1]))?.unwrap() {
+        let elements = match self.eval_value(Rc::clone(&args[1]))? {
             Value::Array(arr) => arr,
             _ => return Err("Second argument of join must be an array".to_string()),
         };
         
-        let joined_string = elements.iter().map(|value| match value {
-            Value::String(s) => s.clone(),
-            Value::Number(n) => n.to_string(),
-            Value::Boolean(b) => b.to_string(),
-            Value::Array(_) => "[array]".to_string(), // You might want to handle nested arrays differently
-        }).collect::<Vec<String>>().join(&separator);
+        let joined_string = elements.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(&separator);
         
         Ok(Some(Value::String(joined_string)))
     }
@@ -234,42 +1317,2227 @@ impl Evaluator {
         if args.len() != 2 {
             return Err("split function requires 2 arguments".to_string());
         }
-        let string = match self.eval(Rc::clone(&args[0]))?.unwrap() {
+        let string = match self.eval_value(Rc::clone(&args[0]))? {
             Value::String(s) => s,
             _ => return Err("First argument of split must be a string".to_string()),
         };
-        let separator = match self.eval(Rc::clone(&args[1]))?.unwrap() {
-            Value::String(s) => s,
-            _ => return Err("Second argument of split must be a string".to_string()),
+        let pieces: Vec<String> = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::String(separator) => string.split(&separator).map(|s| s.to_string()).collect(),
+            Value::Regex(re) => re.split(&string),
+            _ => return Err("Second argument of split must be a string or a regex".to_string()),
         };
-        let result: Vec<Value> = string.split(&separator)
-            .map(|s| Value::String(s.to_string()))
-            .collect();
+        let result = pieces.into_iter().map(Value::String).collect();
         Ok(Some(Value::Array(result)))
     }
 
-    fn count_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
-        if args.len() != 2 {
-            return Err("count function requires 2 arguments".to_string());
+    fn range_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 3 {
+            return Err("range function requires 1 to 3 arguments".to_string());
         }
-        let first_arg = self.eval(Rc::clone(&args[0]))?.unwrap();
-        let second_arg = self.eval(Rc::clone(&args[1]))?.unwrap();
-
-        match (first_arg, second_arg) {
-            (Value::String(s), Value::String(substr)) => {
-                Ok(Some(Value::Number(s.matches(&substr).count() as i64)))
+        let mut bounds = Vec::new();
+        for arg in args {
+            match self.eval_value(Rc::clone(arg))? {
+                Value::Number(n) => bounds.push(n),
+                _ => return Err("range function arguments must be numbers".to_string()),
             }
-            (Value::Array(arr), Value::String(substr)) => {
-                let count = arr.iter().filter(|&v| {
-                    if let Value::String(s) = v {
-                        s == &substr
-                    } else {
-                        false
+        }
+        let (start, end, step) = match bounds[..] {
+            [end] => (0, end, 1),
+            [start, end] => (start, end, 1),
+            [start, end, step] => (start, end, step),
+            _ => unreachable!(),
+        };
+        if step == 0 {
+            return Err("range step cannot be zero".to_string());
+        }
+        let mut current = start;
+        let iter = std::iter::from_fn(move || {
+            let in_range = if step > 0 { current < end } else { current > end };
+            if !in_range {
+                return None;
+            }
+            let value = current;
+            current = current.checked_add(step).unwrap_or(end);
+            Some(Ok(Value::Number(value)))
+        });
+        Ok(Some(Value::Iterator(LazyIter::new(iter))))
+    }
+
+    fn collect_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("collect function requires 1 argument".to_string());
+        }
+        let iter = into_iter(self.eval_value(Rc::clone(&args[0]))?)?;
+        let mut values = Vec::new();
+        while let Some(item) = iter.next_value() {
+            values.push(item?);
+        }
+        Ok(Some(Value::Array(values)))
+    }
+
+    fn set_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() > 1 {
+            return Err("set function requires 0 or 1 arguments".to_string());
+        }
+        let mut set = ValueSet::default();
+        if let Some(arg) = args.first() {
+            let iter = into_iter(self.eval_value(Rc::clone(arg))?)?;
+            while let Some(item) = iter.next_value() {
+                set.insert(item?);
+            }
+        }
+        Ok(Some(Value::Set(set)))
+    }
+
+    fn add_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("add function requires 2 arguments".to_string());
+        }
+        let set = self.eval_unfrozen(&args[0], "add to")?;
+        let element = self.eval_value(Rc::clone(&args[1]))?;
+        match set {
+            Value::Set(mut set) => {
+                set.insert(element);
+                Ok(Some(Value::Set(set)))
+            }
+            _ => Err("First argument of add must be a set".to_string()),
+        }
+    }
+
+    fn has_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("has function requires 2 arguments".to_string());
+        }
+        let set = self.eval_value(Rc::clone(&args[0]))?;
+        let element = self.eval_value(Rc::clone(&args[1]))?;
+        match (set, element) {
+            (Value::Set(set), element) => Ok(Some(Value::Boolean(set.contains(&element)))),
+            (Value::Map(map), Value::String(key)) => Ok(Some(Value::Boolean(map.contains_key(&key)))),
+            (Value::Map(_), _) => Err("Second argument of has must be a string when checking a map".to_string()),
+            _ => Err("First argument of has must be a set or a map".to_string()),
+        }
+    }
+
+    fn remove_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("remove function requires 2 arguments".to_string());
+        }
+        let set = self.eval_unfrozen(&args[0], "remove from")?;
+        let element = self.eval_value(Rc::clone(&args[1]))?;
+        match set {
+            Value::Set(mut set) => {
+                set.retain(|item| !values_equal(item, &element));
+                Ok(Some(Value::Set(set)))
+            }
+            _ => Err("First argument of remove must be a set".to_string()),
+        }
+    }
+
+    fn union_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("union function requires 2 arguments".to_string());
+        }
+        let left = self.eval_value(Rc::clone(&args[0]))?;
+        let right = self.eval_value(Rc::clone(&args[1]))?;
+        match (left, right) {
+            (Value::Set(mut set), Value::Set(other)) => {
+                for item in other.items {
+                    set.insert(item);
+                }
+                Ok(Some(Value::Set(set)))
+            }
+            _ => Err("union function arguments must be sets".to_string()),
+        }
+    }
+
+    fn intersect_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("intersect function requires 2 arguments".to_string());
+        }
+        let left = self.eval_value(Rc::clone(&args[0]))?;
+        let right = self.eval_value(Rc::clone(&args[1]))?;
+        match (left, right) {
+            (Value::Set(mut set), Value::Set(other)) => {
+                set.retain(|item| other.contains(item));
+                Ok(Some(Value::Set(set)))
+            }
+            _ => Err("intersect function arguments must be sets".to_string()),
+        }
+    }
+
+    fn builder_new_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() > 1 {
+            return Err("builder_new function requires 0 or 1 arguments".to_string());
+        }
+        let initial = match args.first() {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(s) => s,
+                _ => return Err("Argument of builder_new must be a string".to_string()),
+            },
+            None => String::new(),
+        };
+        Ok(Some(Value::Builder(Rc::new(RefCell::new(initial)))))
+    }
+
+    fn builder_push_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("builder_push function requires 2 arguments".to_string());
+        }
+        let builder = self.eval_value(Rc::clone(&args[0]))?;
+        let piece = self.eval_value(Rc::clone(&args[1]))?;
+        match builder {
+            Value::Builder(builder) => {
+                match piece {
+                    Value::String(s) => builder.borrow_mut().push_str(&s),
+                    other => builder.borrow_mut().push_str(&other.to_string()),
+                }
+                Ok(Some(Value::Builder(builder)))
+            }
+            _ => Err("First argument of builder_push must be a string builder".to_string()),
+        }
+    }
+
+    fn builder_build_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("builder_build function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Builder(builder) => Ok(Some(Value::String(builder.borrow().clone()))),
+            _ => Err("Argument of builder_build must be a string builder".to_string()),
+        }
+    }
+
+    fn merge_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("merge function requires 2 arguments".to_string());
+        }
+        let base = self.eval_value(Rc::clone(&args[0]))?;
+        let overrides = self.eval_value(Rc::clone(&args[1]))?;
+        match (base, overrides) {
+            (Value::Map(mut map), Value::Map(other)) => {
+                map.extend(other);
+                Ok(Some(Value::Map(map)))
+            }
+            _ => Err("merge function arguments must be maps".to_string()),
+        }
+    }
+
+    fn get_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 && args.len() != 3 {
+            return Err("get function requires 2 or 3 arguments".to_string());
+        }
+        let map = self.eval_value(Rc::clone(&args[0]))?;
+        let key = self.eval_value(Rc::clone(&args[1]))?;
+        match (map, key) {
+            (Value::Map(map), Value::String(key)) => match map.get(&key) {
+                Some(value) => Ok(Some(value.clone())),
+                None if args.len() == 3 => self.eval(Rc::clone(&args[2])),
+                None => Err(format!("Key not found: {}", key)),
+            },
+            _ => Err("get function arguments must be a map and a string key".to_string()),
+        }
+    }
+
+    fn entries_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("entries function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Map(map) => {
+                let pairs = map.into_iter().map(|(key, value)| Value::Array(vec![Value::String(key), value])).collect();
+                Ok(Some(Value::Array(pairs)))
+            }
+            _ => Err("Argument of entries must be a map".to_string()),
+        }
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn from_entries_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("from_entries function requires 1 argument".to_string());
+        }
+        let pairs = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Array(pairs) => pairs,
+            _ => return Err("Argument of from_entries must be an array of [key, value] pairs".to_string()),
+        };
+        let mut map = BTreeMap::new();
+        for pair in pairs {
+            match pair {
+                Value::Array(mut pair) if pair.len() == 2 => {
+                    let value = pair.pop().unwrap();
+                    match pair.pop().unwrap() {
+                        Value::String(key) => map.insert(key, value),
+                        other => return Err(format!("Map keys must be strings, found {:?}", other)),
+                    };
+                }
+                other => return Err(format!("from_entries expects [key, value] pairs, found {:?}", other)),
+            }
+        }
+        Ok(Some(Value::Map(map)))
+    }
+
+    fn copy_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("copy function requires 1 argument".to_string());
+        }
+        let value = self.eval_value(Rc::clone(&args[0]))?;
+        copy_value(&value, false).map(Some)
+    }
+
+    fn deep_copy_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("deep_copy function requires 1 argument".to_string());
+        }
+        let value = self.eval_value(Rc::clone(&args[0]))?;
+        copy_value(&value, true).map(Some)
+    }
+
+    fn freeze_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("freeze function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            value @ (Value::Array(_) | Value::Map(_) | Value::Set(_)) => Ok(Some(Value::Frozen(Box::new(value), self.current_line))),
+            other => Err(format!("Only arrays, maps and sets can be frozen, found {:?}", other)),
+        }
+    }
+
+    fn error_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("error function requires 1 or 2 arguments".to_string());
+        }
+        let message = self.eval_value(Rc::clone(&args[0]))?;
+        let kind = match args.get(1) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(kind) => kind,
+                _ => return Err("Second argument of error must be a string".to_string()),
+            },
+            None => "user".to_string(),
+        };
+        match message {
+            // Passing a caught error back to `error` rethrows it unchanged.
+            error @ Value::Error { .. } => {
+                let message = error_message_of(&error);
+                self.raised_error = Some(error);
+                Err(message)
+            }
+            Value::String(message) => Err(self.raise(&kind, message)),
+            _ => Err("First argument of error must be a string or an error".to_string()),
+        }
+    }
+
+    fn error_kind_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("error_kind function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Error { kind, .. } => Ok(Some(Value::String(kind))),
+            _ => Err("Argument of error_kind must be an error".to_string()),
+        }
+    }
+
+    fn error_message_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("error_message function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Error { message, .. } => Ok(Some(Value::String(message))),
+            _ => Err("Argument of error_message must be an error".to_string()),
+        }
+    }
+
+    fn open_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("open function requires 1 or 2 arguments".to_string());
+        }
+        let path = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(path) => path,
+            _ => return Err("First argument of open must be a string".to_string()),
+        };
+        let mode = match args.get(1) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(mode) => mode,
+                _ => return Err("Second argument of open must be a string".to_string()),
+            },
+            None => "r".to_string(),
+        };
+        let mut options = OpenOptions::new();
+        match mode.as_str() {
+            "r" => options.read(true),
+            "w" => options.write(true).create(true).truncate(true),
+            "a" => options.append(true).create(true),
+            "r+" => options.read(true).write(true),
+            "w+" => options.read(true).write(true).create(true).truncate(true),
+            "a+" => options.read(true).append(true).create(true),
+            _ => return Err(format!("Unknown file mode {:?}, expected r, w, a, r+, w+ or a+", mode)),
+        };
+        if mode.starts_with('r') || mode.ends_with('+') {
+            self.check_read("open", &path)?;
+        }
+        if mode != "r" {
+            self.check_write("open", &path)?;
+        }
+        // A dry run writes to a scratch copy instead, so reads and seeks
+        // still see what the script wrote.
+        let opened = if self.config.dry_run && mode != "r" {
+            self.would(format!("open {} for writing", path));
+            let scratch = std::env::temp_dir().join(format!("nabeel-dry-run-{}-{}", std::process::id(), self.temp_paths.len()));
+            self.temp_paths.push(scratch.clone());
+            let seeded = if mode.starts_with('w') || !Path::new(&path).exists() { Ok(0) } else { fs::copy(&path, &scratch) };
+            seeded.and_then(|_| options.create(true).open(&scratch))
+        } else {
+            options.open(&path)
+        };
+        let file = opened.map_err(|e| self.io_error(e))?;
+        let handle = FileHandle { path, file: Some(BufReader::new(file)) };
+        Ok(Some(Value::File(Rc::new(RefCell::new(handle)))))
+    }
+
+    fn read_line_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("read_line function requires 1 argument".to_string());
+        }
+        let handle = self.eval_file(&args[0], "read_line")?;
+        let mut line = String::new();
+        let result = handle.borrow_mut().reader()?.read_line(&mut line);
+        result.map_err(|e| self.io_error(e))?;
+        // The line ending is dropped; at the end of the file the result is "".
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(Value::String(line)))
+    }
+
+    fn write_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("write function requires 2 arguments".to_string());
+        }
+        let handle = self.eval_file(&args[0], "write")?;
+        let data = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::String(s) => s,
+            other => other.to_string(),
+        };
+        let result = handle.borrow_mut().reader().map(|reader| {
+            // Seeking to the logical position drops any read-ahead buffer so
+            // the bytes land where the script expects them.
+            let position = reader.stream_position()?;
+            reader.seek(SeekFrom::Start(position))?;
+            reader.get_mut().write_all(data.as_bytes())
+        })?;
+        result.map_err(|e| self.io_error(e))?;
+        Ok(Some(Value::Number(data.len() as i64)))
+    }
+
+    fn seek_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 && args.len() != 3 {
+            return Err("seek function requires 2 or 3 arguments".to_string());
+        }
+        let handle = self.eval_file(&args[0], "seek")?;
+        let offset = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::Number(n) => n,
+            _ => return Err("Second argument of seek must be a number".to_string()),
+        };
+        let whence = match args.get(2) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(whence) => whence,
+                _ => return Err("Third argument of seek must be a string".to_string()),
+            },
+            None => "start".to_string(),
+        };
+        let target = match whence.as_str() {
+            "start" if offset >= 0 => SeekFrom::Start(offset as u64),
+            "start" => return Err(format!("Cannot seek to a negative position: {}", offset)),
+            "current" => SeekFrom::Current(offset),
+            "end" => SeekFrom::End(offset),
+            _ => return Err(format!("Unknown seek origin {:?}, expected start, current or end", whence)),
+        };
+        let result = handle.borrow_mut().reader()?.seek(target);
+        let position = result.map_err(|e| self.io_error(e))?;
+        Ok(Some(Value::Number(position as i64)))
+    }
+
+    fn eof_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("eof function requires 1 argument".to_string());
+        }
+        let handle = self.eval_file(&args[0], "eof")?;
+        let result = handle.borrow_mut().reader()?.fill_buf().map(|buffer| buffer.is_empty());
+        let at_end = result.map_err(|e| self.io_error(e))?;
+        Ok(Some(Value::Boolean(at_end)))
+    }
+
+    fn close_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("close function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::File(handle) => handle.borrow_mut().file = None,
+            Value::Socket(handle) => handle.borrow_mut().socket = None,
+            // The program sees the end of its input; it keeps running until it exits.
+            Value::Process(handle) => handle.borrow_mut().stdin = None,
+            _ => return Err("Argument of close must be a file, socket or process".to_string()),
+        }
+        Ok(None)
+    }
+
+    fn tcp_connect_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("tcp_connect function requires 2 arguments".to_string());
+        }
+        let host = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(host) => host,
+            _ => return Err("First argument of tcp_connect must be a string".to_string()),
+        };
+        let port = self.eval_port(&args[1], "Second argument of tcp_connect")?;
+        self.check_net("tcp_connect", "connect", &host, port)?;
+        let stream = TcpStream::connect((host.as_str(), port)).map_err(|e| self.io_error(e))?;
+        let address = stream.peer_addr().map_or_else(|_| format!("{}:{}", host, port), |addr| addr.to_string());
+        let handle = SocketHandle { address, socket: Some(Socket::Stream(BufReader::new(stream))) };
+        Ok(Some(Value::Socket(Rc::new(RefCell::new(handle)))))
+    }
+
+    fn tcp_listen_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("tcp_listen function requires 1 or 2 arguments".to_string());
+        }
+        let port = self.eval_port(&args[0], "First argument of tcp_listen")?;
+        // Only local connections are accepted unless a host such as "0.0.0.0" is given.
+        let host = match args.get(1) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(host) => host,
+                _ => return Err("Second argument of tcp_listen must be a string".to_string()),
+            },
+            None => "127.0.0.1".to_string(),
+        };
+        self.check_net("tcp_listen", "listen", &host, port)?;
+        let listener = TcpListener::bind((host.as_str(), port)).map_err(|e| self.io_error(e))?;
+        let address = listener.local_addr().map_or_else(|_| format!("{}:{}", host, port), |addr| addr.to_string());
+        let handle = SocketHandle { address, socket: Some(Socket::Listener(listener)) };
+        Ok(Some(Value::Socket(Rc::new(RefCell::new(handle)))))
+    }
+
+    fn accept_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("accept function requires 1 argument".to_string());
+        }
+        let handle = self.eval_socket(&args[0], "accept")?;
+        let accepted = match handle.borrow().socket.as_ref() {
+            Some(Socket::Listener(listener)) => listener.accept(),
+            Some(Socket::Stream(_)) => return Err("accept requires a listening socket".to_string()),
+            None => return Err(format!("Socket {} is closed", handle.borrow().address)),
+        };
+        let (stream, address) = accepted.map_err(|e| self.io_error(e))?;
+        let handle = SocketHandle { address: address.to_string(), socket: Some(Socket::Stream(BufReader::new(stream))) };
+        Ok(Some(Value::Socket(Rc::new(RefCell::new(handle)))))
+    }
+
+    fn socket_port_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("socket_port function requires 1 argument".to_string());
+        }
+        let handle = self.eval_socket(&args[0], "socket_port")?;
+        let address = match handle.borrow().socket.as_ref() {
+            Some(Socket::Listener(listener)) => listener.local_addr(),
+            Some(Socket::Stream(stream)) => stream.get_ref().local_addr(),
+            None => return Err(format!("Socket {} is closed", handle.borrow().address)),
+        };
+        let address = address.map_err(|e| self.io_error(e))?;
+        Ok(Some(Value::Number(address.port() as i64)))
+    }
+
+    fn send_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("send function requires 2 arguments".to_string());
+        }
+        let handle = self.eval_socket(&args[0], "send")?;
+        let data = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::String(data) => data,
+            _ => return Err("Second argument of send must be a string".to_string()),
+        };
+        let result = handle.borrow_mut().stream("send")?.get_mut().write_all(data.as_bytes());
+        result.map_err(|e| self.io_error(e))?;
+        Ok(Some(Value::Number(data.len() as i64)))
+    }
+
+    // Returns whatever has arrived, up to `max_bytes` (4096 by default),
+    // waiting only if nothing has. An empty string means the peer closed
+    // the connection.
+    fn recv_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("recv function requires 1 or 2 arguments".to_string());
+        }
+        let handle = self.eval_socket(&args[0], "recv")?;
+        let max_bytes = match args.get(1) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::Number(n) if n > 0 => n as usize,
+                Value::Number(n) => return Err(format!("recv size must be positive: {}", n)),
+                _ => return Err("Second argument of recv must be a number".to_string()),
+            },
+            None => 4096,
+        };
+        let mut handle = handle.borrow_mut();
+        let stream = handle.stream("recv")?;
+        // A character split across two reads is left in the buffer for the
+        // next call, or read to the end when nothing comes before it, so no
+        // read returns half of one.
+        let mut received = Vec::new();
+        loop {
+            let buffer = stream.fill_buf().map_err(|e| self.io_error(e))?;
+            if buffer.is_empty() {
+                break;
+            }
+            let take = buffer.len().min(max_bytes.saturating_sub(received.len()).max(1));
+            received.extend_from_slice(&buffer[..take]);
+            let tail = incomplete_tail(&received);
+            if tail == 0 {
+                stream.consume(take);
+                break;
+            }
+            if tail < received.len() && tail <= take {
+                stream.consume(take - tail);
+                received.truncate(received.len() - tail);
+                break;
+            }
+            stream.consume(take);
+        }
+        Ok(Some(Value::String(String::from_utf8_lossy(&received).into_owned())))
+    }
+
+    fn url_parse_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("url_parse function requires 1 argument".to_string());
+        }
+        let text = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(text) => text,
+            _ => return Err("Argument of url_parse must be a string".to_string()),
+        };
+        let parsed = url::parse(&text).map_err(|e| self.raise("value", e))?;
+        // Without an explicit port the scheme's usual one is filled in, or 0 if it has none.
+        let port = parsed.port.unwrap_or(match parsed.scheme.as_str() {
+            "http" | "ws" => 80,
+            "https" | "wss" => 443,
+            "ftp" => 21,
+            "ssh" => 22,
+            _ => 0,
+        });
+        let query = parsed.query.into_iter().map(|(key, value)| (key, Value::String(value))).collect();
+        let mut map = BTreeMap::new();
+        map.insert("scheme".to_string(), Value::String(parsed.scheme));
+        map.insert("user".to_string(), Value::String(parsed.user));
+        map.insert("host".to_string(), Value::String(parsed.host));
+        map.insert("port".to_string(), Value::Number(port as i64));
+        map.insert("path".to_string(), Value::String(parsed.path));
+        map.insert("query".to_string(), Value::Map(query));
+        map.insert("fragment".to_string(), Value::String(parsed.fragment));
+        Ok(Some(Value::Map(map)))
+    }
+
+    fn url_encode_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("url_encode function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(text) => Ok(Some(Value::String(url::encode(&text)))),
+            _ => Err("Argument of url_encode must be a string".to_string()),
+        }
+    }
+
+    fn url_decode_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("url_decode function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(text) => url::decode(&text).map(|text| Some(Value::String(text))).map_err(|e| self.raise("value", e)),
+            _ => Err("Argument of url_decode must be a string".to_string()),
+        }
+    }
+
+    // Keys come out in sorted order. An array value repeats its key once per
+    // element, as in "tag=a&tag=b".
+    fn query_string_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("query_string function requires 1 argument".to_string());
+        }
+        let map = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Map(map) => map,
+            _ => return Err("Argument of query_string must be a map".to_string()),
+        };
+        let mut pairs = Vec::new();
+        for (key, value) in &map {
+            let values = match value {
+                Value::Array(items) => items.iter().collect(),
+                value => vec![value],
+            };
+            for value in values {
+                match value {
+                    Value::String(_) | Value::Number(_) | Value::Boolean(_) => {
+                        pairs.push(format!("{}={}", url::encode(key), url::encode(&value.to_string())))
+                    }
+                    other => return Err(format!("Cannot put a {} in a query string", type_name(other))),
+                }
+            }
+        }
+        Ok(Some(Value::String(pairs.join("&"))))
+    }
+
+    fn store_open_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("store_open function requires 1 argument".to_string());
+        }
+        let path = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(path) => path,
+            _ => return Err("Argument of store_open must be a string".to_string()),
+        };
+        self.check_read("store_open", &path)?;
+        self.check_write("store_open", &path)?;
+        let store = Store::open(&path).map_err(|e| self.raise("io", e))?;
+        Ok(Some(Value::Store(Rc::new(RefCell::new(store)))))
+    }
+
+    fn store_get_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 && args.len() != 3 {
+            return Err("store_get function requires 2 or 3 arguments".to_string());
+        }
+        let (store, key) = self.eval_store_key(args, "store_get")?;
+        let value = match store.borrow().entries.get(&key) {
+            Some(Stored::String(s)) => Value::String(s.clone()),
+            Some(Stored::Number(n)) => Value::Number(*n),
+            Some(Stored::Boolean(b)) => Value::Boolean(*b),
+            None if args.len() == 3 => return self.eval(Rc::clone(&args[2])),
+            None => return Err(format!("Key not found: {}", key)),
+        };
+        Ok(Some(value))
+    }
+
+    // Every change is written to disk straight away.
+    fn store_set_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 3 {
+            return Err("store_set function requires 3 arguments".to_string());
+        }
+        let (store, key) = self.eval_store_key(args, "store_set")?;
+        let value = match self.eval_value(Rc::clone(&args[2]))? {
+            Value::String(s) => Stored::String(s),
+            Value::Number(n) => Stored::Number(n),
+            Value::Boolean(b) => Stored::Boolean(b),
+            other => return Err(format!("A store can only hold strings, numbers and booleans, got {}", type_name(&other))),
+        };
+        store.borrow_mut().entries.insert(key.clone(), value);
+        self.save_store(&store, format!("set {:?} in", key)).map(|_| None)
+    }
+
+    // Returns whether the key was there.
+    fn store_delete_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("store_delete function requires 2 arguments".to_string());
+        }
+        let (store, key) = self.eval_store_key(args, "store_delete")?;
+        let removed = store.borrow_mut().entries.remove(&key).is_some();
+        if removed {
+            self.save_store(&store, format!("delete {:?} from", key))?;
+        }
+        Ok(Some(Value::Boolean(removed)))
+    }
+
+    // A dry run keeps the change in memory only, so later reads in the same
+    // run still see it.
+    fn save_store(&mut self, store: &Rc<RefCell<Store>>, change: String) -> Result<(), String> {
+        if self.config.dry_run {
+            self.would(format!("{} store {}", change, store.borrow().path));
+            return Ok(());
+        }
+        let saved = store.borrow().save();
+        saved.map_err(|e| self.io_error(e))
+    }
+
+    fn eval_store_key(&mut self, args: &[Rc<RefCell<ASTNode>>], name: &str) -> Result<(Rc<RefCell<Store>>, String), String> {
+        let store = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Store(store) => store,
+            _ => return Err(format!("First argument of {} must be a store", name)),
+        };
+        match self.eval_value(Rc::clone(&args[1]))? {
+            Value::String(key) => Ok((store, key)),
+            _ => Err(format!("Second argument of {} must be a string", name)),
+        }
+    }
+
+    // Reads KEY=VALUE lines into the process environment, where env() and
+    // child processes see them. Variables that are already set win, so the
+    // real environment can override the file. Returns everything the file
+    // defines as a map.
+    fn load_env_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() > 1 {
+            return Err("load_env function requires 0 or 1 arguments".to_string());
+        }
+        let path = match args.first() {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(path) => path,
+                _ => return Err("Argument of load_env must be a string".to_string()),
+            },
+            None => ".env".to_string(),
+        };
+        self.check_read("load_env", &path)?;
+        self.check_env("load_env", "*")?;
+        let text = fs::read_to_string(&path).map_err(|e| self.io_error(e))?;
+        let mut loaded = BTreeMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) if !key.trim().is_empty() => (key.trim(), parse_env_value(value.trim())),
+                _ => return Err(self.raise("value", format!("Expected KEY=VALUE in {} at line {}", path, number + 1))),
+            };
+            if env::var_os(key).is_none() {
+                env::set_var(key, &value);
+            }
+            loaded.insert(key.to_string(), Value::String(value));
+        }
+        Ok(Some(Value::Map(loaded)))
+    }
+
+    // env() returns every variable as a map; env(name) returns one of them,
+    // or the default given as a second argument when it is not set.
+    fn env_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() > 2 {
+            return Err("env function requires 0 to 2 arguments".to_string());
+        }
+        let name = match args.first() {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(name) => name,
+                _ => return Err("First argument of env must be a string".to_string()),
+            },
+            None => {
+                self.check_env("env", "*")?;
+                let vars = env::vars_os().map(|(key, value)| (key.to_string_lossy().into_owned(), Value::String(value.to_string_lossy().into_owned())));
+                return Ok(Some(Value::Map(vars.collect())));
+            }
+        };
+        self.check_env("env", &name)?;
+        match env::var(&name) {
+            Ok(value) => Ok(Some(Value::String(value))),
+            Err(_) if args.len() == 2 => self.eval(Rc::clone(&args[1])),
+            Err(_) => Err(self.raise("not_found", format!("Environment variable not set: {}", name))),
+        }
+    }
+
+    // "linux", "macos", "windows" and so on, as Rust names them.
+    fn platform_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("platform function takes no arguments".to_string());
+        }
+        Ok(Some(Value::String(env::consts::OS.to_string())))
+    }
+
+    fn hostname_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("hostname function takes no arguments".to_string());
+        }
+        match host_name() {
+            Some(name) => Ok(Some(Value::String(name))),
+            None => Err(self.raise("not_found", "Cannot determine the host name".to_string())),
+        }
+    }
+
+    // The number of CPUs this process may use, which can be fewer than the
+    // machine has.
+    fn cpu_count_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("cpu_count function takes no arguments".to_string());
+        }
+        let count = thread::available_parallelism().map_or(1, |count| count.get());
+        Ok(Some(Value::Number(count as i64)))
+    }
+
+    // Read from HOME, or USERPROFILE on Windows, so it needs --allow-env in
+    // the sandbox like env does.
+    fn home_dir_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("home_dir function takes no arguments".to_string());
+        }
+        let name = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+        self.check_env("home_dir", name)?;
+        match env::var(name) {
+            Ok(home) if !home.is_empty() => Ok(Some(Value::String(home))),
+            _ => Err(self.raise("not_found", format!("Cannot determine the home directory: {} is not set", name))),
+        }
+    }
+
+    fn current_dir_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("current_dir function takes no arguments".to_string());
+        }
+        let dir = env::current_dir().map_err(|e| self.io_error(e))?;
+        Ok(Some(Value::String(dir.to_string_lossy().into_owned())))
+    }
+
+    // Relative paths used afterwards, by this script and by the programs it
+    // starts, are resolved from the new directory. In the sandbox the
+    // directory must be readable; grants stay where they were given.
+    fn set_current_dir_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("set_current_dir function requires 1 argument".to_string());
+        }
+        let path = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(path) => path,
+            _ => return Err("Argument of set_current_dir must be a string".to_string()),
+        };
+        self.check_read("set_current_dir", &path)?;
+        env::set_current_dir(&path).map_err(|e| self.io_error(e))?;
+        Ok(None)
+    }
+
+    fn color_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("color function requires 2 arguments".to_string());
+        }
+        let (text, color) = match (self.eval_value(Rc::clone(&args[0]))?, self.eval_value(Rc::clone(&args[1]))?) {
+            (text, Value::String(color)) => (text.to_string(), color),
+            _ => return Err("Second argument of color must be a string".to_string()),
+        };
+        let code = match color.as_str() {
+            "black" => "30",
+            "red" => "31",
+            "green" => "32",
+            "yellow" => "33",
+            "blue" => "34",
+            "magenta" => "35",
+            "cyan" => "36",
+            "white" => "37",
+            "gray" | "grey" => "90",
+            _ => return Err(self.raise("value", format!("Unknown color: {}", color))),
+        };
+        Ok(Some(Value::String(styled(&text, code))))
+    }
+
+    fn bold_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("bold function requires 1 argument".to_string());
+        }
+        let text = self.eval_value(Rc::clone(&args[0]))?.to_string();
+        Ok(Some(Value::String(styled(&text, "1"))))
+    }
+
+    fn is_tty_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("is_tty function takes no arguments".to_string());
+        }
+        Ok(Some(Value::Boolean(std::io::stdout().is_terminal())))
+    }
+
+    fn term_clear_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("term_clear function takes no arguments".to_string());
+        }
+        terminal_control("\x1b[2J\x1b[H");
+        Ok(None)
+    }
+
+    // Returns a map with "columns" and "rows", falling back to 80 by 24 when
+    // the size cannot be found, as when output is piped.
+    fn term_size_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("term_size function takes no arguments".to_string());
+        }
+        let (columns, rows) = terminal_size().unwrap_or((80, 24));
+        let mut map = BTreeMap::new();
+        map.insert("columns".to_string(), Value::Number(columns));
+        map.insert("rows".to_string(), Value::Number(rows));
+        Ok(Some(Value::Map(map)))
+    }
+
+    // Rows and columns count from 1 at the top left corner.
+    fn term_move_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("term_move function requires 2 arguments".to_string());
+        }
+        match (self.eval_value(Rc::clone(&args[0]))?, self.eval_value(Rc::clone(&args[1]))?) {
+            (Value::Number(row), Value::Number(column)) if row >= 1 && column >= 1 => {
+                terminal_control(&format!("\x1b[{};{}H", row, column));
+                Ok(None)
+            }
+            (Value::Number(row), Value::Number(column)) => Err(format!("Cursor position must be at least 1, 1: {}, {}", row, column)),
+            _ => Err("term_move function arguments must be numbers".to_string()),
+        }
+    }
+
+    fn term_cursor_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("term_cursor function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Boolean(visible) => {
+                terminal_control(if visible { "\x1b[?25h" } else { "\x1b[?25l" });
+                Ok(None)
+            }
+            _ => Err("Argument of term_cursor must be a boolean".to_string()),
+        }
+    }
+
+    // A total of 0 shows a spinner for work of unknown size.
+    fn progress_start_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("progress_start function requires 1 or 2 arguments".to_string());
+        }
+        let total = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Number(total) if total >= 0 => total as u64,
+            Value::Number(total) => return Err(format!("Progress total cannot be negative: {}", total)),
+            _ => return Err("First argument of progress_start must be a number".to_string()),
+        };
+        let label = match args.get(1) {
+            Some(arg) => self.eval_value(Rc::clone(arg))?.to_string(),
+            None => String::new(),
+        };
+        Ok(Some(Value::Progress(Rc::new(RefCell::new(Progress::new(label, total))))))
+    }
+
+    fn progress_tick_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("progress_tick function requires 1 or 2 arguments".to_string());
+        }
+        let progress = self.eval_progress(&args[0], "progress_tick")?;
+        let amount = match args.get(1) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::Number(amount) if amount >= 0 => amount as u64,
+                Value::Number(amount) => return Err(format!("Progress cannot go backwards: {}", amount)),
+                _ => return Err("Second argument of progress_tick must be a number".to_string()),
+            },
+            None => 1,
+        };
+        let ticked = progress.borrow_mut().tick(amount);
+        ticked.map(|_| None)
+    }
+
+    fn progress_finish_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("progress_finish function requires 1 argument".to_string());
+        }
+        self.eval_progress(&args[0], "progress_finish")?.borrow_mut().finish();
+        Ok(None)
+    }
+
+    fn eval_progress(&mut self, node: &Rc<RefCell<ASTNode>>, name: &str) -> Result<Rc<RefCell<Progress>>, String> {
+        match self.eval_value(Rc::clone(node))? {
+            Value::Progress(progress) => Ok(progress),
+            _ => Err(format!("First argument of {} must be a progress display", name)),
+        }
+    }
+
+    // Asks until the answer is y/yes or n/no. An empty answer picks the
+    // default when one is given as the second argument.
+    fn confirm_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("confirm function requires 1 or 2 arguments".to_string());
+        }
+        let prompt = self.eval_value(Rc::clone(&args[0]))?.to_string();
+        let default = match args.get(1) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::Boolean(default) => Some(default),
+                _ => return Err("Second argument of confirm must be a boolean".to_string()),
+            },
+            None => None,
+        };
+        let hint = match default {
+            Some(true) => "[Y/n]",
+            Some(false) => "[y/N]",
+            None => "[y/n]",
+        };
+        loop {
+            let answer = self.read_answer(&format!("{} {} ", prompt, hint))?;
+            match (answer.trim().to_lowercase().as_str(), default) {
+                ("y" | "yes", _) => return Ok(Some(Value::Boolean(true))),
+                ("n" | "no", _) => return Ok(Some(Value::Boolean(false))),
+                ("", Some(default)) => return Ok(Some(Value::Boolean(default))),
+                _ => self.write_output("Please answer y or n."),
+            }
+        }
+    }
+
+    // Lists the options with numbers and returns the one picked.
+    fn select_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("select function requires 2 arguments".to_string());
+        }
+        let prompt = self.eval_value(Rc::clone(&args[0]))?.to_string();
+        let options = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::Array(options) if !options.is_empty() => options,
+            Value::Array(_) => return Err("select needs at least one option".to_string()),
+            _ => return Err("Second argument of select must be an array".to_string()),
+        };
+        self.write_output(&prompt);
+        for (i, option) in options.iter().enumerate() {
+            self.write_output(&format!("  {}) {}", i + 1, option));
+        }
+        loop {
+            let answer = self.read_answer(&format!("Choice [1-{}]: ", options.len()))?;
+            match answer.trim().parse::<usize>() {
+                Ok(choice) if (1..=options.len()).contains(&choice) => return Ok(Some(options[choice - 1].clone())),
+                _ => self.write_output(&format!("Please enter a number from 1 to {}.", options.len())),
+            }
+        }
+    }
+
+    // Typing is hidden when stdin is a terminal.
+    fn password_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("password function requires 1 argument".to_string());
+        }
+        let prompt = self.eval_value(Rc::clone(&args[0]))?.to_string();
+        let hide = self.captured.is_none() && std::io::stdin().is_terminal();
+        if hide {
+            set_echo(false);
+        }
+        let answer = self.read_answer(&prompt);
+        if hide {
+            set_echo(true);
+            println!();
+        }
+        Ok(Some(Value::String(answer?)))
+    }
+
+    fn read_answer(&mut self, prompt: &str) -> Result<String, String> {
+        if self.captured.is_some() {
+            self.write_output(prompt);
+            return Err(self.stdin_captured());
+        }
+        print!("{}", prompt);
+        let _ = std::io::stdout().flush();
+        let mut answer = String::new();
+        match std::io::stdin().read_line(&mut answer) {
+            Ok(0) => Err(self.raise("io", "No input left to answer the prompt".to_string())),
+            Ok(_) => Ok(answer.trim_end_matches(['\n', '\r']).to_string()),
+            Err(e) => Err(self.io_error(e)),
+        }
+    }
+
+    fn mean_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let numbers = self.eval_numbers(args, "mean")?;
+        Ok(Some(Value::Float(mean(&numbers))))
+    }
+
+    fn median_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let numbers = self.eval_numbers(args, "median")?;
+        Ok(Some(Value::Float(percentile(numbers, 50.0))))
+    }
+
+    // The population standard deviation, dividing by the count rather than
+    // by one less.
+    fn stddev_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let numbers = self.eval_numbers(args, "stddev")?;
+        let mean = mean(&numbers);
+        let variance = numbers.iter().map(|n| (n - mean) * (n - mean)).sum::<f64>() / numbers.len() as f64;
+        Ok(Some(Value::Float(variance.sqrt())))
+    }
+
+    fn percentile_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("percentile function requires 2 arguments".to_string());
+        }
+        let numbers = self.eval_numbers(&args[..1], "percentile")?;
+        let p = match as_float(&self.eval_value(Rc::clone(&args[1]))?) {
+            Some(p) if (0.0..=100.0).contains(&p) => p,
+            Some(p) => return Err(format!("Percentile must be between 0 and 100: {}", p)),
+            None => return Err("Second argument of percentile must be a number".to_string()),
+        };
+        Ok(Some(Value::Float(percentile(numbers, p))))
+    }
+
+    fn eval_numbers(&mut self, args: &[Rc<RefCell<ASTNode>>], name: &str) -> Result<Vec<f64>, String> {
+        if args.len() != 1 {
+            return Err(format!("{} function requires 1 argument", name));
+        }
+        let items = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Array(items) if !items.is_empty() => items,
+            Value::Array(_) => return Err(self.raise("value", format!("{} of an empty array is undefined", name))),
+            _ => return Err(format!("First argument of {} must be an array", name)),
+        };
+        items
+            .iter()
+            .map(|item| as_float(item).ok_or_else(|| format!("{} needs an array of numbers, found a {}", name, type_name(item))))
+            .collect()
+    }
+
+    // Converts a whole number or a string of digits to a big integer.
+    fn big_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("big function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Number(n) => Ok(Some(Value::BigInt(BigInt::from_i64(n)))),
+            Value::BigInt(n) => Ok(Some(Value::BigInt(n))),
+            Value::String(text) => BigInt::parse(text.trim()).map(|n| Some(Value::BigInt(n))).map_err(|e| self.raise("value", e)),
+            _ => Err("Argument of big must be a number or a string".to_string()),
+        }
+    }
+
+    // Converts a string such as "19.99", a whole number or a float to a
+    // decimal. Strings keep the digits they were written with, so
+    // decimal("1.50") prints as 1.50.
+    fn decimal_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("decimal function requires 1 argument".to_string());
+        }
+        let result = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Number(n) => Ok(Decimal::from_i64(n)),
+            Value::Decimal(n) => Ok(n),
+            Value::Float(n) if n.is_finite() => Decimal::parse(&format!("{}", n)),
+            Value::String(text) => Decimal::parse(text.trim()),
+            _ => return Err("Argument of decimal must be a number, a float or a string".to_string()),
+        };
+        result.map(|n| Some(Value::Decimal(n))).map_err(|e| self.raise("value", e))
+    }
+
+    // decimal_round(d, places, mode) with mode one of half_even (the
+    // default), half_up, down, up, floor or ceiling.
+    fn decimal_round_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 && args.len() != 3 {
+            return Err("decimal_round function requires 2 or 3 arguments".to_string());
+        }
+        let value = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Decimal(n) => n,
+            Value::Number(n) => Decimal::from_i64(n),
+            _ => return Err("First argument of decimal_round must be a decimal".to_string()),
+        };
+        let places = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::Number(n) if (0..=38).contains(&n) => n as u32,
+            Value::Number(n) => return Err(self.raise("value", format!("decimal_round places must be between 0 and 38, got {}", n))),
+            _ => return Err("Second argument of decimal_round must be a number".to_string()),
+        };
+        let mode = match args.get(2) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(name) => match Rounding::parse(&name) {
+                    Some(mode) => mode,
+                    None => return Err(self.raise("value", format!("Unknown rounding mode: {} (expected half_even, half_up, down, up, floor or ceiling)", name))),
+                },
+                _ => return Err("Third argument of decimal_round must be a string".to_string()),
+            },
+            None => Rounding::HalfEven,
+        };
+        value.round(places, mode).map(|n| Some(Value::Decimal(n))).map_err(|e| self.raise("overflow", e))
+    }
+
+    fn uuid_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("uuid function takes no arguments".to_string());
+        }
+        self.random.uuid().map(|id| Some(Value::String(id))).map_err(|e| self.io_error(e))
+    }
+
+    // random_hex(n) gives n random bytes as 2n hex digits.
+    fn random_hex_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let count = self.random_length(args, "random_hex")?;
+        self.random.hex(count).map(|text| Some(Value::String(text))).map_err(|e| self.io_error(e))
+    }
+
+    // random_token(n) gives n URL-safe characters.
+    fn random_token_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let length = self.random_length(args, "random_token")?;
+        self.random.token(length).map(|text| Some(Value::String(text))).map_err(|e| self.io_error(e))
+    }
+
+    fn random_length(&mut self, args: &[Rc<RefCell<ASTNode>>], name: &str) -> Result<usize, String> {
+        if args.len() != 1 {
+            return Err(format!("{} function requires 1 argument", name));
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Number(n) if n > random::MAX_LENGTH as i64 => Err(self.raise("limit", format!("Length of {} must be at most {}, got {}", name, random::MAX_LENGTH, n))),
+            Value::Number(n) if n > 0 => {
+                self.check_string_length(n as usize)?;
+                Ok(n as usize)
+            }
+            Value::Number(n) => Err(self.raise("value", format!("Length of {} must be positive, got {}", name, n))),
+            _ => Err(format!("Argument of {} must be a number", name)),
+        }
+    }
+
+    // Returns the digest as 64 lowercase hex digits.
+    fn sha256_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("sha256 function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(data) => Ok(Some(Value::String(sha256::to_hex(&sha256::digest(data.as_bytes()))))),
+            _ => Err("Argument of sha256 must be a string".to_string()),
+        }
+    }
+
+    fn hmac_sha256_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("hmac_sha256 function requires 2 arguments".to_string());
+        }
+        match (self.eval_value(Rc::clone(&args[0]))?, self.eval_value(Rc::clone(&args[1]))?) {
+            (Value::String(key), Value::String(data)) => Ok(Some(Value::String(sha256::to_hex(&sha256::hmac(key.as_bytes(), data.as_bytes()))))),
+            _ => Err("Arguments of hmac_sha256 must be strings".to_string()),
+        }
+    }
+
+    // For checking signatures and tokens, where `==` could leak through
+    // timing how many leading characters matched.
+    fn secure_equals_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("secure_equals function requires 2 arguments".to_string());
+        }
+        match (self.eval_value(Rc::clone(&args[0]))?, self.eval_value(Rc::clone(&args[1]))?) {
+            (Value::String(a), Value::String(b)) => Ok(Some(Value::Boolean(sha256::constant_time_equals(a.as_bytes(), b.as_bytes())))),
+            _ => Err("Arguments of secure_equals must be strings".to_string()),
+        }
+    }
+
+    // eval(source) runs the code with the caller's variables, so it can read
+    // and change them. eval(source, "isolated") runs it in a fresh evaluator
+    // with the same limits and no access to the caller's variables. Either
+    // way it returns the value of the last statement (true if it has none),
+    // or an error value if the code fails to parse or run. Runtime errors
+    // give their line within the source; syntax errors the line of the call.
+    fn eval_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("eval function requires 1 or 2 arguments".to_string());
+        }
+        let mut source = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(source) => source,
+            _ => return Err("First argument of eval must be a string".to_string()),
+        };
+        let isolated = match args.get(1) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(mode) if mode == "isolated" => true,
+                Value::String(mode) if mode == "shared" => false,
+                Value::String(mode) => return Err(self.raise("value", format!("Invalid eval mode: {} (expected shared or isolated)", mode))),
+                _ => return Err("Second argument of eval must be a string".to_string()),
+            },
+            None => false,
+        };
+        // A lone expression such as "price > 10" needs no closing semicolon.
+        if !source.trim_end().ends_with([';', '}']) {
+            source.push(';');
+        }
+        let program = match Parser::new(Lexer::new(source)).and_then(|mut parser| parser.parse()) {
+            Ok(program) => program,
+            Err(message) => return Ok(Some(Value::Error { kind: "syntax".to_string(), message, line: self.current_line })),
+        };
+        if isolated {
+            let mut sandbox = Evaluator::with_config(self.config.clone());
+            sandbox.set_trace(self.trace);
+            // Isolation is about variables; the code's I/O is still audited
+            // and captured, and it runs within the caller's limits. Its
+            // variables go away with it, so only its iterations are kept.
+            sandbox.audit = self.audit.take();
+            sandbox.captured = self.captured.take();
+            sandbox.temp_paths = std::mem::take(&mut self.temp_paths);
+            sandbox.depth = self.depth;
+            sandbox.loop_iterations = self.loop_iterations;
+            sandbox.memory_used = self.memory_used;
+            let result = match sandbox.eval(program) {
+                Ok(result) => result.unwrap_or(Value::Boolean(true)),
+                Err(message) => sandbox.caught_error(message),
+            };
+            self.audit = sandbox.audit.take();
+            self.captured = sandbox.captured.take();
+            // Temporary paths live as long as the caller, not the sandbox.
+            self.temp_paths = std::mem::take(&mut sandbox.temp_paths);
+            self.loop_iterations = sandbox.loop_iterations;
+            return Ok(Some(result));
+        }
+        let outer_line = self.current_line;
+        let result = match self.eval(program) {
+            Ok(result) => result.unwrap_or(Value::Boolean(true)),
+            Err(message) => self.caught_error(message),
+        };
+        self.current_line = outer_line;
+        Ok(Some(result))
+    }
+
+    // A map from each variable name to its current value.
+    fn vars_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("vars function takes no arguments".to_string());
+        }
+        let variables = self.variables.iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+        Ok(Some(Value::Map(variables)))
+    }
+
+    // A map from each builtin's name to the smallest and largest number of
+    // arguments it accepts, as {"min_args": 1, "max_args": 2}.
+    fn functions_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("functions function takes no arguments".to_string());
+        }
+        let mut functions = BTreeMap::new();
+        for builtin in BUILTINS {
+            let mut arity = BTreeMap::new();
+            arity.insert("min_args".to_string(), Value::Number(builtin.min_args as i64));
+            arity.insert("max_args".to_string(), Value::Number(builtin.max_args as i64));
+            functions.insert(builtin.name.to_string(), Value::Map(arity));
+        }
+        Ok(Some(Value::Map(functions)))
+    }
+
+    // True if the name is a variable that has been assigned or a builtin.
+    fn defined_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("defined function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(name) => Ok(Some(Value::Boolean(self.variables.contains_key(&name) || builtins::find(&name).is_some()))),
+            _ => Err("Argument of defined must be a string".to_string()),
+        }
+    }
+
+    // help(name) prints a builtin's signature and description; help() lists
+    // every builtin's signature.
+    fn help_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() > 1 {
+            return Err("help function requires 0 or 1 arguments".to_string());
+        }
+        let name = match args.first() {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(name) => name,
+                _ => return Err("Argument of help must be a string".to_string()),
+            },
+            None => {
+                for builtin in BUILTINS {
+                    self.write_output(builtin.signature);
+                }
+                return Ok(None);
+            }
+        };
+        let text = match (builtins::find(&name), self.variables.get(&name)) {
+            (Some(builtin), _) => format!("{}\n    {}", builtin.signature, builtin.doc),
+            (None, Some(value)) => format!("{} is a variable holding a {}", name, type_name(value)),
+            (None, None) => return Err(self.raise("not_found", format!("No builtin or variable named {}", name))),
+        };
+        self.write_output(&text);
+        Ok(None)
+    }
+
+    // Fills a template's {{placeholders}} from a map; see template.rs.
+    fn render_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("render function requires 2 arguments".to_string());
+        }
+        let template = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(template) => template,
+            _ => return Err("First argument of render must be a string".to_string()),
+        };
+        let data = match self.eval_value(Rc::clone(&args[1]))? {
+            data @ Value::Map(_) => data,
+            _ => return Err("Second argument of render must be a map".to_string()),
+        };
+        let output = template::render(&template, &data).map_err(|e| self.raise("value", e))?;
+        self.check_string_length(output.len())?;
+        Ok(Some(Value::String(output)))
+    }
+
+    // The expression in the third argument is evaluated again on each
+    // attempt, which is what makes the call retryable. After a failure the
+    // delay before the next attempt is multiplied by `backoff`, up to an
+    // hour.
+    fn retry_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() < 3 || args.len() > 4 {
+            return Err("retry function requires 3 or 4 arguments".to_string());
+        }
+        let times = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Number(times) if times > 0 => times,
+            _ => return Err("First argument of retry must be a positive number".to_string()),
+        };
+        let mut delay = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::Number(delay) if delay >= 0 => delay as f64,
+            _ => return Err("Second argument of retry must be a number of milliseconds".to_string()),
+        };
+        let backoff = match args.get(3) {
+            Some(arg) => match as_float(&self.eval_value(Rc::clone(arg))?) {
+                Some(backoff) if backoff >= 1.0 && backoff.is_finite() => backoff,
+                _ => return Err("Fourth argument of retry must be a finite number of at least 1".to_string()),
+            },
+            None => 1.0,
+        };
+        let mut attempt = 1;
+        loop {
+            match self.eval(Rc::clone(&args[2])) {
+                Err(_) if attempt < times => {
+                    self.raised_error = None;
+                    thread::sleep(Duration::from_millis(delay.min(MAX_RETRY_DELAY_MS) as u64));
+                    delay = (delay * backoff).min(MAX_RETRY_DELAY_MS);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // cache_file(key, ttl_seconds, expression) gives the value saved under key
+    // if it is younger than ttl_seconds, and otherwise evaluates expression
+    // and saves its value for next time, even across runs.
+    fn cache_file_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 3 {
+            return Err("cache_file function requires 3 arguments".to_string());
+        }
+        let key = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(key) => key,
+            _ => return Err("First argument of cache_file must be a string".to_string()),
+        };
+        let ttl = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::Number(ttl) if ttl >= 0 => Duration::from_secs(ttl as u64),
+            _ => return Err("Second argument of cache_file must be a number of seconds".to_string()),
+        };
+        let path = cache::path(&key).map_err(|e| self.raise("not_found", e))?.to_string_lossy().into_owned();
+        self.check_read("cache_file", &path)?;
+        if let Some(value) = cache::load(&key, ttl).map_err(|e| self.raise("io", e))? {
+            return Ok(Some(value));
+        }
+        let value = self.eval_value(Rc::clone(&args[2]))?;
+        let json = cache::encode(&value).ok_or("cache_file can only cache strings, numbers, floats, booleans, arrays and maps")?;
+        self.check_write("cache_file", &path)?;
+        if self.config.dry_run {
+            self.would(format!("write cache entry {}", path));
+        } else {
+            cache::save(&key, &json).map_err(|e| self.raise("io", e))?;
+        }
+        Ok(Some(value))
+    }
+
+    // time(expression) evaluates expression once and gives how long that
+    // took in milliseconds; its value is thrown away.
+    fn time_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("time function requires 1 argument".to_string());
+        }
+        let start = Instant::now();
+        self.eval(Rc::clone(&args[0]))?;
+        Ok(Some(Value::Float(start.elapsed().as_secs_f64() * 1000.0)))
+    }
+
+    // benchmark(expression, iterations) evaluates expression that many times
+    // and gives a map of the fastest, mean and slowest run in milliseconds.
+    fn benchmark_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("benchmark function requires 2 arguments".to_string());
+        }
+        let iterations = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::Number(iterations) if iterations > 0 => iterations,
+            _ => return Err("Second argument of benchmark must be a positive number".to_string()),
+        };
+        let mut runs = Vec::new();
+        for _ in 0..iterations {
+            let start = Instant::now();
+            self.eval(Rc::clone(&args[0]))?;
+            runs.push(start.elapsed().as_secs_f64() * 1000.0);
+        }
+        let min = runs.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = runs.iter().copied().fold(0.0, f64::max);
+        let mean = runs.iter().sum::<f64>() / runs.len() as f64;
+        let mut stats = BTreeMap::new();
+        stats.insert("iterations".to_string(), Value::Number(iterations));
+        stats.insert("min".to_string(), Value::Float(min));
+        stats.insert("mean".to_string(), Value::Float(mean));
+        stats.insert("max".to_string(), Value::Float(max));
+        Ok(Some(Value::Map(stats)))
+    }
+
+    // spawn_process(command[, args]) starts a program directly, without a
+    // shell, so arguments need no quoting.
+    fn spawn_process_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("spawn_process function requires 1 or 2 arguments".to_string());
+        }
+        let command = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(command) => command,
+            _ => return Err("First argument of spawn_process must be a string".to_string()),
+        };
+        let arguments = match args.get(1) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::Array(items) => items.iter().map(print_format).collect(),
+                _ => return Err("Second argument of spawn_process must be an array".to_string()),
+            },
+            None => Vec::new(),
+        };
+        self.check_run("spawn_process", &command)?;
+        let spawned = Command::new(&command).args(&arguments).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn();
+        let mut child = spawned.map_err(|e| self.spawn_error(&command, e))?;
+        let stdin = child.stdin.take();
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let handle = ProcessHandle { command, child, stdin, stdout };
+        Ok(Some(Value::Process(Rc::new(RefCell::new(handle)))))
+    }
+
+    // Waits for the next line of output; "" once the program has closed it.
+    fn proc_read_line_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("proc_read_line function requires 1 argument".to_string());
+        }
+        let handle = self.eval_process(&args[0], "proc_read_line")?;
+        let mut line = String::new();
+        let result = handle.borrow_mut().stdout.read_line(&mut line);
+        result.map_err(|e| self.io_error(e))?;
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(Value::String(line)))
+    }
+
+    fn proc_write_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("proc_write function requires 2 arguments".to_string());
+        }
+        let handle = self.eval_process(&args[0], "proc_write")?;
+        let data = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::String(data) => data,
+            _ => return Err("Second argument of proc_write must be a string".to_string()),
+        };
+        let mut process = handle.borrow_mut();
+        let command = process.command.clone();
+        let stdin = process.stdin.as_mut().ok_or_else(|| format!("Input of process {} is closed", command))?;
+        // Flushed at once so an interactive program sees each write.
+        let result = stdin.write_all(data.as_bytes()).and_then(|_| stdin.flush());
+        drop(process);
+        result.map_err(|e| self.io_error(e))?;
+        Ok(Some(Value::Number(data.len() as i64)))
+    }
+
+    // Closes the program's input, waits for it to exit and gives its exit
+    // code. Output not yet read is still there for proc_read_line.
+    fn proc_wait_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("proc_wait function requires 1 argument".to_string());
+        }
+        let handle = self.eval_process(&args[0], "proc_wait")?;
+        let mut process = handle.borrow_mut();
+        process.stdin = None;
+        let result = process.child.wait();
+        let command = process.command.clone();
+        drop(process);
+        let status = result.map_err(|e| self.io_error(e))?;
+        match status.code() {
+            Some(code) => Ok(Some(Value::Number(code as i64))),
+            None => Err(self.raise("io", format!("Process {} was stopped by a signal", command))),
+        }
+    }
+
+    // pipe(commands[, input]) runs the commands with each one's output
+    // feeding the next, like a shell pipeline, and gives the last one's
+    // output. A command is a string split at spaces, or an array of the
+    // program and its arguments when an argument has spaces in it. As in a
+    // shell, exit codes are not checked.
+    fn pipe_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("pipe function requires 1 or 2 arguments".to_string());
+        }
+        let commands = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Array(commands) if !commands.is_empty() => commands,
+            _ => return Err("First argument of pipe must be a non-empty array of commands".to_string()),
+        };
+        let input = match args.get(1) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(input) => Some(input),
+                _ => return Err("Second argument of pipe must be a string".to_string()),
+            },
+            None => None,
+        };
+        let mut stages = Vec::new();
+        for command in &commands {
+            let words: Vec<String> = match command {
+                Value::String(command) => command.split_whitespace().map(str::to_string).collect(),
+                Value::Array(words) => words.iter().map(print_format).collect(),
+                _ => return Err("Each command given to pipe must be a string or an array".to_string()),
+            };
+            if words.is_empty() {
+                return Err("Commands given to pipe cannot be empty".to_string());
+            }
+            self.check_run("pipe", &words[0])?;
+            stages.push(words);
+        }
+        let mut children: Vec<Child> = Vec::new();
+        for words in &stages {
+            let stdin = match children.last_mut() {
+                Some(previous) => Stdio::from(previous.stdout.take().expect("stdout is piped")),
+                None if input.is_some() => Stdio::piped(),
+                None => Stdio::null(),
+            };
+            let spawned = Command::new(&words[0]).args(&words[1..]).stdin(stdin).stdout(Stdio::piped()).spawn();
+            match spawned {
+                Ok(child) => children.push(child),
+                Err(e) => {
+                    // The stages already started would otherwise be left
+                    // running, possibly blocked on a full pipe, and never reaped.
+                    for child in &mut children {
+                        let _ = child.kill();
+                        let _ = child.wait();
                     }
-                }).count();
+                    return Err(self.spawn_error(&words[0], e));
+                }
+            }
+        }
+        // The input is written from another thread so a program that
+        // produces output before reading all of it cannot block the pipeline.
+        let writer = match (input, children[0].stdin.take()) {
+            (Some(input), Some(mut stdin)) => Some(thread::spawn(move || stdin.write_all(input.as_bytes()))),
+            _ => None,
+        };
+        let mut output = String::new();
+        let last = children.last_mut().expect("pipe has a command");
+        let read = last.stdout.take().expect("stdout is piped").read_to_string(&mut output);
+        for child in &mut children {
+            let _ = child.wait();
+        }
+        if let Some(writer) = writer {
+            // A program that stops reading early, such as `head`, closes
+            // the pipe; that is not an error.
+            let _ = writer.join();
+        }
+        read.map_err(|e| self.io_error(e))?;
+        Ok(Some(Value::String(output)))
+    }
+
+    // The clipboard is reached through the platform's own tools, so these
+    // need the tool installed and, in the sandbox, --allow-run for it.
+    fn clipboard_get_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("clipboard_get function takes no arguments".to_string());
+        }
+        let (paste, _) = clipboard_programs();
+        self.check_run("clipboard_get", paste[0])?;
+        let output = Command::new(paste[0]).args(&paste[1..]).stdin(Stdio::null()).output().map_err(|e| self.spawn_error(paste[0], e))?;
+        if !output.status.success() {
+            return Err(self.raise("io", format!("{} could not read the clipboard", paste[0])));
+        }
+        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+        // PowerShell ends what it prints with a line break of its own.
+        if cfg!(windows) && text.ends_with("\r\n") {
+            text.truncate(text.len() - 2);
+        }
+        Ok(Some(Value::String(text)))
+    }
+
+    fn clipboard_set_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("clipboard_set function requires 1 argument".to_string());
+        }
+        let text = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(text) => text,
+            _ => return Err("Argument of clipboard_set must be a string".to_string()),
+        };
+        let (_, copy) = clipboard_programs();
+        self.check_run("clipboard_set", copy[0])?;
+        let spawned = Command::new(copy[0]).args(&copy[1..]).stdin(Stdio::piped()).stdout(Stdio::null()).spawn();
+        let mut child = spawned.map_err(|e| self.spawn_error(copy[0], e))?;
+        let written = child.stdin.take().expect("stdin is piped").write_all(text.as_bytes());
+        let status = child.wait();
+        written.and(status).map_err(|e| self.io_error(e))?;
+        Ok(None)
+    }
+
+    fn edit_distance_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("edit_distance function requires 2 arguments".to_string());
+        }
+        let a = self.eval_value(Rc::clone(&args[0]))?;
+        let b = self.eval_value(Rc::clone(&args[1]))?;
+        match (a, b) {
+            (Value::String(a), Value::String(b)) => Ok(Some(Value::Number(fuzzy::distance(&a, &b) as i64))),
+            _ => Err("Arguments of edit_distance must be strings".to_string()),
+        }
+    }
+
+    // fuzzy_match(query, candidates) keeps the candidates that contain the
+    // query's letters in order or are a typo away from it, closest first.
+    fn fuzzy_match_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("fuzzy_match function requires 2 arguments".to_string());
+        }
+        let query = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(query) => query,
+            _ => return Err("First argument of fuzzy_match must be a string".to_string()),
+        };
+        let candidates = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::Array(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    Value::String(candidate) => Ok(candidate),
+                    _ => Err("Second argument of fuzzy_match must be an array of strings".to_string()),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => return Err("Second argument of fuzzy_match must be an array of strings".to_string()),
+        };
+        let candidates: Vec<&str> = candidates.iter().map(String::as_str).collect();
+        let ranked = fuzzy::rank(&query, &candidates).into_iter().map(|candidate| Value::String(candidate.to_string()));
+        Ok(Some(Value::Array(ranked.collect())))
+    }
+
+    // Names the program, since the system's message alone does not.
+    fn spawn_error(&mut self, program: &str, error: std::io::Error) -> String {
+        self.io_error(std::io::Error::new(error.kind(), format!("Cannot run {}: {}", program, error)))
+    }
+
+    // sort(items[, "natural"]) gives the items in ascending order; see
+    // compare_values. The sort is stable.
+    fn sort_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("sort function requires 1 or 2 arguments".to_string());
+        }
+        let items = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Array(items) => items,
+            _ => return Err("First argument of sort must be an array".to_string()),
+        };
+        let natural = self.sort_order(args.get(1), "Second argument of sort")?;
+        let keyed = items.clone().into_iter().zip(items).collect();
+        sort_keyed(keyed, natural, "sort").map(|sorted| Some(Value::Array(sorted)))
+    }
+
+    // sort_by(items, keys[, "natural"]) orders items by the key at the same
+    // position in keys, which is usually built with a comprehension. Arrays
+    // as keys sort by several keys at once.
+    fn sort_by_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() < 2 || args.len() > 3 {
+            return Err("sort_by function requires 2 or 3 arguments".to_string());
+        }
+        let (items, keys) = self.eval_keyed(args, "sort_by")?;
+        let natural = self.sort_order(args.get(2), "Third argument of sort_by")?;
+        sort_keyed(keys.into_iter().zip(items).collect(), natural, "sort_by").map(|sorted| Some(Value::Array(sorted)))
+    }
+
+    // group_by(items, keys) gathers the items into a map from each key, as
+    // text, to the items with that key, in their original order.
+    fn group_by_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("group_by function requires 2 arguments".to_string());
+        }
+        let (items, keys) = self.eval_keyed(args, "group_by")?;
+        let mut groups: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+        for (key, item) in keys.iter().zip(items) {
+            groups.entry(print_format(key)).or_default().push(item);
+        }
+        Ok(Some(Value::Map(groups.into_iter().map(|(key, group)| (key, Value::Array(group))).collect())))
+    }
+
+    // partition(items, flags) splits the items into those whose flag is true
+    // and the rest, as [matching, rest].
+    fn partition_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("partition function requires 2 arguments".to_string());
+        }
+        let (items, flags) = self.eval_keyed(args, "partition")?;
+        let (mut matching, mut rest) = (Vec::new(), Vec::new());
+        for (flag, item) in flags.iter().zip(items) {
+            match flag {
+                Value::Boolean(true) => matching.push(item),
+                Value::Boolean(false) => rest.push(item),
+                _ => return Err("Second argument of partition must be an array of booleans".to_string()),
+            }
+        }
+        Ok(Some(Value::Array(vec![Value::Array(matching), Value::Array(rest)])))
+    }
+
+    // The items and the array giving one key per item that sort_by,
+    // group_by and partition take.
+    fn eval_keyed(&mut self, args: &[Rc<RefCell<ASTNode>>], name: &str) -> Result<(Vec<Value>, Vec<Value>), String> {
+        let items = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Array(items) => items,
+            _ => return Err(format!("First argument of {} must be an array", name)),
+        };
+        match self.eval_value(Rc::clone(&args[1]))? {
+            Value::Array(keys) if keys.len() == items.len() => Ok((items, keys)),
+            Value::Array(keys) => Err(format!("{} was given {} keys for {} items", name, keys.len(), items.len())),
+            _ => Err(format!("Second argument of {} must be an array", name)),
+        }
+    }
+
+    // chunk(items, size) splits items into arrays of size items each; the
+    // last one holds whatever is left.
+    fn chunk_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let (items, size) = self.eval_items_and_size(args, "chunk")?;
+        let chunks = items.chunks(size).map(|chunk| Value::Array(chunk.to_vec()));
+        Ok(Some(Value::Array(chunks.collect())))
+    }
+
+    // windows(items, size) gives every run of size neighbouring items, so
+    // [1, 2, 3] with size 2 gives [[1, 2], [2, 3]]. Fewer items than size
+    // give no windows.
+    fn windows_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let (items, size) = self.eval_items_and_size(args, "windows")?;
+        let windows = items.windows(size).map(|window| Value::Array(window.to_vec()));
+        Ok(Some(Value::Array(windows.collect())))
+    }
+
+    fn eval_items_and_size(&mut self, args: &[Rc<RefCell<ASTNode>>], name: &str) -> Result<(Vec<Value>, usize), String> {
+        if args.len() != 2 {
+            return Err(format!("{} function requires 2 arguments", name));
+        }
+        let items = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Array(items) => items,
+            _ => return Err(format!("First argument of {} must be an array", name)),
+        };
+        match self.eval_value(Rc::clone(&args[1]))? {
+            Value::Number(size) if size > 0 => Ok((items, size as usize)),
+            _ => Err(format!("Second argument of {} must be a positive number", name)),
+        }
+    }
+
+    // binary_search(items, value) gives the position of value in items, which
+    // must already be in sort's order, or -1 if it is not there.
+    fn binary_search_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("binary_search function requires 2 arguments".to_string());
+        }
+        let items = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::Array(items) => items,
+            _ => return Err("First argument of binary_search must be an array".to_string()),
+        };
+        let value = self.eval_value(Rc::clone(&args[1]))?;
+        let position = search_sorted(&items, &value, "binary_search")?;
+        Ok(Some(Value::Number(position.map_or(-1, |position| position as i64))))
+    }
+
+    // insert_sorted(items, value) gives a copy of items, already in sort's
+    // order, with value added where it keeps them in order: after any equal
+    // items.
+    fn insert_sorted_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("insert_sorted function requires 2 arguments".to_string());
+        }
+        let mut items = match self.eval_unfrozen(&args[0], "insert into")? {
+            Value::Array(items) => items,
+            _ => return Err("First argument of insert_sorted must be an array".to_string()),
+        };
+        let value = self.eval_value(Rc::clone(&args[1]))?;
+        let mut failed = None;
+        let position = items.partition_point(|item| match compare_values(item, &value, false) {
+            Some(ordering) => ordering != Ordering::Greater,
+            None => {
+                failed.get_or_insert_with(|| format!("insert_sorted cannot order {} and {} values", type_name(item), type_name(&value)));
+                false
+            }
+        });
+        if let Some(message) = failed {
+            return Err(message);
+        }
+        items.insert(position, value);
+        Ok(Some(Value::Array(items)))
+    }
+
+    // heap_new(["max"]) makes an empty heap. heap_pop takes the smallest
+    // item first, or the largest from a "max" heap; pushing [priority, item]
+    // arrays orders the items by priority.
+    fn heap_new_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() > 1 {
+            return Err("heap_new function requires 0 or 1 arguments".to_string());
+        }
+        let max = match args.first() {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(order) if order == "min" => false,
+                Value::String(order) if order == "max" => true,
+                _ => return Err("Argument of heap_new must be \"min\" or \"max\"".to_string()),
+            },
+            None => false,
+        };
+        Ok(Some(Value::Heap(Rc::new(RefCell::new(Heap { items: Vec::new(), max })))))
+    }
+
+    fn heap_push_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("heap_push function requires 2 arguments".to_string());
+        }
+        let heap = self.eval_heap(&args[0], "heap_push")?;
+        let value = self.eval_value(Rc::clone(&args[1]))?;
+        heap.borrow_mut().push(value)?;
+        Ok(Some(Value::Heap(heap)))
+    }
+
+    fn heap_pop_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("heap_pop function requires 1 argument".to_string());
+        }
+        let heap = self.eval_heap(&args[0], "heap_pop")?;
+        let popped = heap.borrow_mut().pop();
+        popped.ok_or_else(|| "Cannot pop from an empty heap".to_string()).map(Some)
+    }
+
+    fn eval_heap(&mut self, node: &Rc<RefCell<ASTNode>>, name: &str) -> Result<Rc<RefCell<Heap>>, String> {
+        match self.eval_value(Rc::clone(node))? {
+            Value::Heap(heap) => Ok(heap),
+            _ => Err(format!("First argument of {} must be a heap", name)),
+        }
+    }
+
+    // deque([items]) makes a double-ended queue, for scripts that take items
+    // from the front, such as a breadth-first search. Arrays are copied when
+    // changed, so taking the first item of one each time is slow.
+    fn deque_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() > 1 {
+            return Err("deque function requires 0 or 1 arguments".to_string());
+        }
+        let items = match args.first() {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::Array(items) => items.into(),
+                _ => return Err("Argument of deque must be an array".to_string()),
+            },
+            None => VecDeque::new(),
+        };
+        Ok(Some(Value::Deque(Rc::new(RefCell::new(items)))))
+    }
+
+    // Removes and returns the first item.
+    fn shift_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("shift function requires 1 argument".to_string());
+        }
+        let items = self.eval_deque(&args[0], "shift")?;
+        let first = items.borrow_mut().pop_front();
+        first.ok_or_else(|| "Cannot shift from an empty deque".to_string()).map(Some)
+    }
+
+    fn unshift_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("unshift function requires 2 arguments".to_string());
+        }
+        let items = self.eval_deque(&args[0], "unshift")?;
+        let value = self.eval_value(Rc::clone(&args[1]))?;
+        items.borrow_mut().push_front(value);
+        Ok(Some(Value::Deque(items)))
+    }
+
+    fn eval_deque(&mut self, node: &Rc<RefCell<ASTNode>>, name: &str) -> Result<Rc<RefCell<VecDeque<Value>>>, String> {
+        match self.eval_unfrozen(node, name)? {
+            Value::Deque(items) => Ok(items),
+            _ => Err(format!("First argument of {} must be a deque; make one with deque(items)", name)),
+        }
+    }
+
+    fn sort_order(&mut self, arg: Option<&Rc<RefCell<ASTNode>>>, what: &str) -> Result<bool, String> {
+        let Some(arg) = arg else {
+            return Ok(false);
+        };
+        match self.eval_value(Rc::clone(arg))? {
+            Value::String(order) if order == "natural" => Ok(true),
+            _ => Err(format!("{} must be \"natural\"", what)),
+        }
+    }
+
+    fn eval_process(&mut self, node: &Rc<RefCell<ASTNode>>, name: &str) -> Result<Rc<RefCell<ProcessHandle>>, String> {
+        match self.eval_value(Rc::clone(node))? {
+            Value::Process(handle) => Ok(handle),
+            _ => Err(format!("First argument of {} must be a process", name)),
+        }
+    }
+
+    fn eval_port(&mut self, node: &Rc<RefCell<ASTNode>>, what: &str) -> Result<u16, String> {
+        match self.eval_value(Rc::clone(node))? {
+            Value::Number(port) if (0..=65535).contains(&port) => Ok(port as u16),
+            Value::Number(port) => Err(format!("Invalid port number: {}", port)),
+            _ => Err(format!("{} must be a port number", what)),
+        }
+    }
+
+    fn eval_socket(&mut self, node: &Rc<RefCell<ASTNode>>, name: &str) -> Result<Rc<RefCell<SocketHandle>>, String> {
+        match self.eval_value(Rc::clone(node))? {
+            Value::Socket(handle) => Ok(handle),
+            _ => Err(format!("First argument of {} must be a socket", name)),
+        }
+    }
+
+    fn lines_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("lines function requires 1 argument".to_string());
+        }
+        let path = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(path) => path,
+            _ => return Err("Argument of lines must be a string".to_string()),
+        };
+        self.check_read("lines", &path)?;
+        let file = File::open(&path).map_err(|e| self.io_error(e))?;
+        let lines = BufReader::new(file).lines().map(|line| line.map(Value::String).map_err(|e| e.to_string()));
+        Ok(Some(Value::Iterator(LazyIter::new(lines))))
+    }
+
+    fn stdin_lines_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("stdin_lines function takes no arguments".to_string());
+        }
+        if self.captured.is_some() {
+            return Err(self.stdin_captured());
+        }
+        let lines = std::io::stdin().lines().map(|line| line.map(Value::String).map_err(|e| e.to_string()));
+        Ok(Some(Value::Iterator(LazyIter::new(lines))))
+    }
+
+    fn glob_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("glob function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(pattern) => {
+                self.check_read("glob", glob_root(&pattern))?;
+                Ok(Some(Value::Array(glob::glob(&pattern).into_iter().map(Value::String).collect())))
+            }
+            _ => Err("Argument of glob must be a string".to_string()),
+        }
+    }
+
+    fn fnmatch_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("fnmatch function requires 2 arguments".to_string());
+        }
+        let pattern = self.eval_value(Rc::clone(&args[0]))?;
+        let name = self.eval_value(Rc::clone(&args[1]))?;
+        match (pattern, name) {
+            (Value::String(pattern), Value::String(name)) => Ok(Some(Value::Boolean(glob::fnmatch(&pattern, &name)))),
+            _ => Err("fnmatch function arguments must be strings".to_string()),
+        }
+    }
+
+    fn walk_dir_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.is_empty() || args.len() > 3 {
+            return Err("walk_dir function requires 1 to 3 arguments".to_string());
+        }
+        let root = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(root) => root,
+            _ => return Err("First argument of walk_dir must be a string".to_string()),
+        };
+        // A depth of 0 means no limit; 1 lists only the directory's own entries.
+        let max_depth = match args.get(1) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::Number(0) => None,
+                Value::Number(depth) if depth > 0 => Some(depth as usize),
+                Value::Number(depth) => return Err(format!("walk_dir depth cannot be negative: {}", depth)),
+                _ => return Err("Second argument of walk_dir must be a number".to_string()),
+            },
+            None => None,
+        };
+        let follow_links = match args.get(2) {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::Boolean(follow) => follow,
+                _ => return Err("Third argument of walk_dir must be a boolean".to_string()),
+            },
+            None => false,
+        };
+        self.check_read("walk_dir", &root)?;
+        let walker = DirWalker::new(&root, max_depth, follow_links).map_err(|e| self.io_error(e))?;
+        let entries = walker.map(|entry| {
+            entry.map(|entry| {
+                let mut map = BTreeMap::new();
+                map.insert("path".to_string(), Value::String(entry.path));
+                map.insert("is_dir".to_string(), Value::Boolean(entry.is_dir));
+                map.insert("is_symlink".to_string(), Value::Boolean(entry.is_symlink));
+                map.insert("size".to_string(), Value::Number(entry.size as i64));
+                map.insert("modified".to_string(), Value::Number(entry.modified));
+                Value::Map(map)
+            })
+        });
+        Ok(Some(Value::Iterator(LazyIter::new(entries))))
+    }
+
+    fn temp_file_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let prefix = self.temp_prefix(args, "temp_file")?;
+        let path = self.create_temp("temp_file", &prefix, |path| OpenOptions::new().write(true).create_new(true).open(path).map(|_| ()))?;
+        Ok(Some(Value::String(path)))
+    }
+
+    fn temp_dir_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let prefix = self.temp_prefix(args, "temp_dir")?;
+        let path = self.create_temp("temp_dir", &prefix, |path| fs::create_dir(path))?;
+        Ok(Some(Value::String(path)))
+    }
+
+    fn temp_prefix(&mut self, args: &[Rc<RefCell<ASTNode>>], name: &str) -> Result<String, String> {
+        if args.len() > 1 {
+            return Err(format!("{} function requires 0 or 1 arguments", name));
+        }
+        match args.first() {
+            Some(arg) => match self.eval_value(Rc::clone(arg))? {
+                Value::String(prefix) if !prefix.contains('/') => Ok(prefix),
+                Value::String(prefix) => Err(format!("Prefix of {} cannot contain '/': {:?}", name, prefix)),
+                _ => Err(format!("First argument of {} must be a string", name)),
+            },
+            None => Ok("nabeel".to_string()),
+        }
+    }
+
+    // Names mix the process id, the clock and a counter; `create` must fail
+    // if the path already exists so a clash just moves on to the next name.
+    fn create_temp<F: Fn(&PathBuf) -> std::io::Result<()>>(&mut self, builtin: &str, prefix: &str, create: F) -> Result<String, String> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.subsec_nanos());
+        for attempt in 0..100 {
+            let name = format!("{}-{}-{:08x}-{}", prefix, std::process::id(), nanos, self.temp_paths.len() + attempt);
+            let path = std::env::temp_dir().join(name);
+            self.check_write(builtin, &path.to_string_lossy())?;
+            match create(&path) {
+                Ok(()) => {
+                    self.temp_paths.push(path.clone());
+                    return Ok(path.to_string_lossy().into_owned());
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(self.io_error(e)),
+            }
+        }
+        Err(self.raise("io", "Could not find an unused temporary path".to_string()))
+    }
+
+    fn memory_used_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if !args.is_empty() {
+            return Err("memory_used function takes no arguments".to_string());
+        }
+        Ok(Some(Value::Number(self.memory_used as i64)))
+    }
+
+    fn eval_file(&mut self, node: &Rc<RefCell<ASTNode>>, name: &str) -> Result<Rc<RefCell<FileHandle>>, String> {
+        match self.eval_value(Rc::clone(node))? {
+            Value::File(handle) => Ok(handle),
+            _ => Err(format!("First argument of {} must be a file", name)),
+        }
+    }
+
+    fn regex_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("regex function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(pattern) => Ok(Some(Value::Regex(Rc::new(Regex::new(&pattern)?)))),
+            _ => Err("Argument of regex must be a string".to_string()),
+        }
+    }
+
+    fn split_lines_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("split_lines function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            // `lines` accepts both "\n" and "\r\n" endings and drops a trailing newline.
+            Value::String(s) => Ok(Some(Value::Array(s.lines().map(|line| Value::String(line.to_string())).collect()))),
+            _ => Err("Argument of split_lines must be a string".to_string()),
+        }
+    }
+
+    fn split_whitespace_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 1 {
+            return Err("split_whitespace function requires 1 argument".to_string());
+        }
+        match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(s) => Ok(Some(Value::Array(s.split_whitespace().map(|word| Value::String(word.to_string())).collect()))),
+            _ => Err("Argument of split_whitespace must be a string".to_string()),
+        }
+    }
+
+    fn count_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("count function requires 2 arguments".to_string());
+        }
+        let first_arg = self.eval_value(Rc::clone(&args[0]))?;
+        let second_arg = self.eval_value(Rc::clone(&args[1]))?;
+
+        match (first_arg, second_arg) {
+            (Value::String(s), Value::String(substr)) => {
+                Ok(Some(Value::Number(s.matches(&substr).count() as i64)))
+            }
+            (Value::Array(arr), needle) => {
+                let count = arr.iter().filter(|&v| values_equal(v, &needle)).count();
                 Ok(Some(Value::Number(count as i64)))
             }
-            _ => Err("count function arguments must be (string, string) or (array, string)".to_string()),
+            _ => Err("count function arguments must be (string, string) or (array, value)".to_string()),
         }
     }
 
@@ -277,11 +3545,15 @@ impl Evaluator {
         if args.len() != 1 {
             return Err("length function requires 1 argument".to_string());
         }
-        let arg = self.eval(Rc::clone(&args[0]))?.unwrap();
+        let arg = self.eval_value(Rc::clone(&args[0]))?;
         match arg {
             Value::String(s) => Ok(Some(Value::Number(s.len() as i64))),
             Value::Array(arr) => Ok(Some(Value::Number(arr.len() as i64))),
-            _ => Err("length function argument must be a string or an array".to_string()),
+            Value::Set(set) => Ok(Some(Value::Number(set.items.len() as i64))),
+            Value::Map(map) => Ok(Some(Value::Number(map.len() as i64))),
+            Value::Heap(heap) => Ok(Some(Value::Number(heap.borrow().items.len() as i64))),
+            Value::Deque(items) => Ok(Some(Value::Number(items.borrow().len() as i64))),
+            _ => Err("length function argument must be a string, an array, a set, a map, a heap or a deque".to_string()),
         }
     }
 
@@ -289,7 +3561,7 @@ impl Evaluator {
         if args.len() != 1 {
             return Err("uppercase function requires 1 argument".to_string());
         }
-        let arg = self.eval(Rc::clone(&args[0]))?.unwrap();
+        let arg = self.eval_value(Rc::clone(&args[0]))?;
         match arg {
             Value::String(s) => Ok(Some(Value::String(s.to_uppercase()))),
             _ => Err("uppercase function argument must be a string".to_string()),
@@ -300,7 +3572,7 @@ impl Evaluator {
         if args.len() != 1 {
             return Err("lowercase function requires 1 argument".to_string());
         }
-        let arg = self.eval(Rc::clone(&args[0]))?.unwrap();
+        let arg = self.eval_value(Rc::clone(&args[0]))?;
         match arg {
             Value::String(s) => Ok(Some(Value::String(s.to_lowercase()))),
             _ => Err("lowercase function argument must be a string".to_string()),
@@ -311,7 +3583,7 @@ impl Evaluator {
         if args.len() != 1 {
             return Err("trim function requires 1 argument".to_string());
         }
-        let arg = self.eval(Rc::clone(&args[0]))?.unwrap();
+        let arg = self.eval_value(Rc::clone(&args[0]))?;
         match arg {
             Value::String(s) => Ok(Some(Value::String(s.trim().to_string()))),
             _ => Err("trim function argument must be a string".to_string()),
@@ -319,32 +3591,69 @@ impl Evaluator {
     }
 
     fn replace_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
-        if args.len() != 3 {
-            return Err("replace function requires 3 arguments".to_string());
+        if args.len() != 3 && args.len() != 4 {
+            return Err("replace function requires 3 or 4 arguments".to_string());
         }
-        let string = self.eval(Rc::clone(&args[0]))?.unwrap();
-        let pattern = self.eval(Rc::clone(&args[1]))?.unwrap();
-        let replacement = self.eval(Rc::clone(&args[2]))?.unwrap();
+        let string = self.eval_value(Rc::clone(&args[0]))?;
+        let pattern = self.eval_value(Rc::clone(&args[1]))?;
+        let replacement = self.eval_value(Rc::clone(&args[2]))?;
+        let limit = self.replacement_limit(args, "replace")?;
         match (string, pattern, replacement) {
-            (Value::String(s), Value::String(p), Value::String(r)) => {
-                Ok(Some(Value::String(s.replace(&p, &r))))
-            }
+            (Value::String(s), Value::String(p), Value::String(r)) => match limit {
+                Some(n) => Ok(Some(Value::String(s.replacen(&p, &r, n)))),
+                None => Ok(Some(Value::String(s.replace(&p, &r)))),
+            },
             _ => Err("replace function arguments must be strings".to_string()),
         }
     }
 
+    fn regex_replace_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 3 && args.len() != 4 {
+            return Err("regex_replace function requires 3 or 4 arguments".to_string());
+        }
+        let string = self.eval_value(Rc::clone(&args[0]))?;
+        let pattern = self.eval_value(Rc::clone(&args[1]))?;
+        let replacement = self.eval_value(Rc::clone(&args[2]))?;
+        let limit = self.replacement_limit(args, "regex_replace")?;
+        let re = match pattern {
+            Value::Regex(re) => re,
+            Value::String(p) => Rc::new(Regex::new(&p)?),
+            _ => return Err("Second argument of regex_replace must be a regex or a string".to_string()),
+        };
+        match (string, replacement) {
+            (Value::String(s), Value::String(r)) => Ok(Some(Value::String(re.replace(&s, &r, limit)?))),
+            _ => Err("First and third arguments of regex_replace must be strings".to_string()),
+        }
+    }
+
+    // Reads the optional fourth argument shared by `replace` and `regex_replace`.
+    fn replacement_limit(&mut self, args: &[Rc<RefCell<ASTNode>>], name: &str) -> Result<Option<usize>, String> {
+        if args.len() < 4 {
+            return Ok(None);
+        }
+        match self.eval_value(Rc::clone(&args[3]))? {
+            Value::Number(n) if n >= 0 => Ok(Some(n as usize)),
+            Value::Number(n) => Err(format!("Replacement limit of {} cannot be negative: {}", name, n)),
+            _ => Err(format!("Fourth argument of {} must be a number", name)),
+        }
+    }
+
     fn push_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
         if args.len() != 2 {
             return Err("push function requires 2 arguments".to_string());
         }
-        let array = self.eval(Rc::clone(&args[0]))?.unwrap();
-        let element = self.eval(Rc::clone(&args[1]))?.unwrap();
+        let array = self.eval_unfrozen(&args[0], "push to")?;
+        let element = self.eval_value(Rc::clone(&args[1]))?;
         match array {
             Value::Array(mut arr) => {
                 arr.push(element);
                 Ok(Some(Value::Array(arr)))
             }
-            _ => Err("First argument of push must be an array".to_string()),
+            Value::Deque(items) => {
+                items.borrow_mut().push_back(element);
+                Ok(Some(Value::Deque(items)))
+            }
+            _ => Err("First argument of push must be an array or a deque".to_string()),
         }
     }
 
@@ -352,7 +3661,7 @@ impl Evaluator {
         if args.len() != 1 {
             return Err("pop function requires 1 argument".to_string());
         }
-        let array = self.eval(Rc::clone(&args[0]))?.unwrap();
+        let array = self.eval_unfrozen(&args[0], "pop from")?;
         match array {
             Value::Array(mut arr) => {
                 if let Some(last) = arr.pop() {
@@ -361,7 +3670,11 @@ impl Evaluator {
                     Err("Cannot pop from an empty array".to_string())
                 }
             }
-            _ => Err("Argument of pop must be an array".to_string()),
+            Value::Deque(items) => {
+                let last = items.borrow_mut().pop_back();
+                last.ok_or_else(|| "Cannot pop from an empty deque".to_string()).map(Some)
+            }
+            _ => Err("Argument of pop must be an array or a deque".to_string()),
         }
     }
 
@@ -369,7 +3682,7 @@ impl Evaluator {
         if args.len() != 1 {
             return Err("first function requires 1 argument".to_string());
         }
-        let array = self.eval(Rc::clone(&args[0]))?.unwrap();
+        let array = self.eval_value(Rc::clone(&args[0]))?;
         match array {
             Value::Array(arr) => {
                 if let Some(first) = arr.first() {
@@ -386,7 +3699,7 @@ impl Evaluator {
         if args.len() != 1 {
             return Err("last function requires 1 argument".to_string());
         }
-        let array = self.eval(Rc::clone(&args[0]))?.unwrap();
+        let array = self.eval_value(Rc::clone(&args[0]))?;
         match array {
             Value::Array(arr) => {
                 if let Some(last) = arr.last() {
@@ -403,11 +3716,12 @@ impl Evaluator {
         if args.len() != 1 {
             return Err("read_file function requires 1 argument".to_string());
         }
-        let file_path = self.eval(Rc::clone(&args[0]))?.unwrap();
+        let file_path = self.eval_value(Rc::clone(&args[0]))?;
         match file_path {
             Value::String(path) => {
                 use std::fs;
-                fs::read_to_string(path).map(Value::String).map(Some).map_err(|e| e.to_string())
+                self.check_read("read_file", &path)?;
+                fs::read_to_string(path).map(Value::String).map(Some).map_err(|e| self.io_error(e))
             }
             _ => Err("Argument to read_file must be a string".to_string()),
         }
@@ -417,14 +3731,658 @@ impl Evaluator {
         if args.len() != 2 {
             return Err("write_file function requires 2 arguments".to_string());
         }
-        let file_path = self.eval(Rc::clone(&args[0]))?.unwrap();
-        let data = self.eval(Rc::clone(&args[1]))?.unwrap();
+        let file_path = self.eval_value(Rc::clone(&args[0]))?;
+        let data = self.eval_value(Rc::clone(&args[1]))?;
         match (file_path, data) {
             (Value::String(path), Value::String(contents)) => {
                 use std::fs;
-                fs::write(path, contents).map(|_| None).map_err(|e| e.to_string())
+                self.check_write("write_file", &path)?;
+                if self.config.dry_run {
+                    self.would(format!("write {} bytes to {}", contents.len(), path));
+                    return Ok(None);
+                }
+                fs::write(path, contents).map(|_| None).map_err(|e| self.io_error(e))
             }
             _ => Err("Arguments to write_file must be strings".to_string()),
         }
     }
-}
\ No newline at end of file
+
+    fn eval_paths(&mut self, args: &[Rc<RefCell<ASTNode>>], name: &str, count: usize) -> Result<Vec<String>, String> {
+        if args.len() != count {
+            let plural = if count == 1 { "argument" } else { "arguments" };
+            return Err(format!("{} function requires {} {}", name, count, plural));
+        }
+        let mut paths = Vec::new();
+        for arg in args {
+            match self.eval_value(Rc::clone(arg))? {
+                Value::String(path) => paths.push(path),
+                _ => return Err(format!("Arguments to {} must be strings", name)),
+            }
+        }
+        Ok(paths)
+    }
+
+    fn copy_file_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let paths = self.eval_paths(args, "copy_file", 2)?;
+        self.check_read("copy_file", &paths[0])?;
+        self.check_write("copy_file", &paths[1])?;
+        if self.config.dry_run {
+            self.would(format!("copy {} to {}", paths[0], paths[1]));
+            return Ok(None);
+        }
+        fs::copy(&paths[0], &paths[1]).map(|_| None).map_err(|e| self.io_error(e))
+    }
+
+    fn move_file_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let paths = self.eval_paths(args, "move_file", 2)?;
+        self.check_write("move_file", &paths[0])?;
+        self.check_write("move_file", &paths[1])?;
+        if self.config.dry_run {
+            self.would(format!("move {} to {}", paths[0], paths[1]));
+            return Ok(None);
+        }
+        match fs::rename(&paths[0], &paths[1]) {
+            Ok(()) => Ok(None),
+            // rename cannot cross filesystems, so fall back to copy and delete.
+            Err(ref e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                let moved = fs::copy(&paths[0], &paths[1]).and_then(|_| fs::remove_file(&paths[0]));
+                moved.map(|_| None).map_err(|e| self.io_error(e))
+            }
+            Err(e) => Err(self.io_error(e)),
+        }
+    }
+
+    fn mkdir_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let paths = self.eval_paths(args, "mkdir", 1)?;
+        self.check_write("mkdir", &paths[0])?;
+        if self.config.dry_run {
+            self.would(format!("create directory {}", paths[0]));
+            return Ok(None);
+        }
+        fs::create_dir(&paths[0]).map(|_| None).map_err(|e| self.io_error(e))
+    }
+
+    fn mkdirs_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let paths = self.eval_paths(args, "mkdirs", 1)?;
+        self.check_write("mkdirs", &paths[0])?;
+        if self.config.dry_run {
+            self.would(format!("create directories {}", paths[0]));
+            return Ok(None);
+        }
+        fs::create_dir_all(&paths[0]).map(|_| None).map_err(|e| self.io_error(e))
+    }
+
+    fn chmod_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        if args.len() != 2 {
+            return Err("chmod function requires 2 arguments".to_string());
+        }
+        let path = match self.eval_value(Rc::clone(&args[0]))? {
+            Value::String(path) => path,
+            _ => return Err("First argument of chmod must be a string".to_string()),
+        };
+        // Modes are written as octal strings such as "755", since number
+        // literals are always decimal.
+        let mode = match self.eval_value(Rc::clone(&args[1]))? {
+            Value::String(mode) => match u32::from_str_radix(&mode, 8) {
+                Ok(bits) if bits <= 0o7777 => bits,
+                _ => return Err(format!("Invalid mode for chmod: {:?}", mode)),
+            },
+            _ => return Err("Second argument of chmod must be a string".to_string()),
+        };
+        self.check_write("chmod", &path)?;
+        if self.config.dry_run {
+            self.would(format!("change the mode of {} to {:o}", path, mode));
+            return Ok(None);
+        }
+        self.set_mode(&path, mode).map(|_| None)
+    }
+
+    #[cfg(unix)]
+    fn set_mode(&mut self, path: &str, mode: u32) -> Result<(), String> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(|e| self.io_error(e))
+    }
+
+    #[cfg(not(unix))]
+    fn set_mode(&mut self, _path: &str, _mode: u32) -> Result<(), String> {
+        Err(self.raise("unsupported", "chmod is only supported on Unix".to_string()))
+    }
+
+    fn touch_function(&mut self, args: &[Rc<RefCell<ASTNode>>]) -> Result<Option<Value>, String> {
+        let paths = self.eval_paths(args, "touch", 1)?;
+        self.check_write("touch", &paths[0])?;
+        if self.config.dry_run {
+            self.would(format!("touch {}", paths[0]));
+            return Ok(None);
+        }
+        let touched = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&paths[0])
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        touched.map(|_| None).map_err(|e| self.io_error(e))
+    }
+}
+
+// How `print` shows a value. Arrays and maps keep their debug form so
+// nested strings stay quoted.
+pub fn print_format(value: &Value) -> String {
+    match value {
+        Value::Number(n) => format!("{}", n),
+        Value::Float(n) => format!("{:?}", n),
+        Value::BigInt(n) => n.to_string(),
+        Value::Decimal(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Boolean(b) => format!("{}", b),
+        Value::Array(arr) => format!("{:?}", arr),
+        Value::Regex(re) => format!("{:?}", re),
+        Value::Iterator(iter) => format!("{:?}", iter),
+        Value::Set(items) => format!("Set({:?})", items),
+        Value::Builder(builder) => format!("StringBuilder({:?})", builder.borrow()),
+        Value::Heap(heap) => format!("{:?}", heap.borrow()),
+        Value::Deque(items) => format!("Deque({:?})", items.borrow()),
+        Value::Map(map) => format!("{:?}", map),
+        Value::Frozen(value, _) => format!("{:?}", value),
+        Value::Error { .. } => format!("{}", value),
+        Value::File(handle) => format!("{:?}", handle.borrow()),
+        Value::Socket(handle) => format!("{:?}", handle.borrow()),
+        Value::Process(handle) => format!("{:?}", handle.borrow()),
+        Value::Store(store) => format!("{:?}", store.borrow()),
+        Value::Progress(progress) => format!("{:?}", progress.borrow()),
+    }
+}
+
+// A value from a .env file. Double quotes allow \n, \t, \" and \\ escapes,
+// single quotes take the text literally, and an unquoted value ends at a
+// ` #` comment.
+fn parse_env_value(value: &str) -> String {
+    if let Some(inner) = value.strip_prefix('"').and_then(|rest| rest.rsplit_once('"')).map(|(inner, _)| inner) {
+        let mut result = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                result.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            }
+        }
+        return result;
+    }
+    if let Some(inner) = value.strip_prefix('\'').and_then(|rest| rest.rsplit_once('\'')).map(|(inner, _)| inner) {
+        return inner.to_string();
+    }
+    match value.find(" #") {
+        Some(comment) => value[..comment].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+// Wraps text in an ANSI style. Styling is left out when output is not a
+// terminal, or when NO_COLOR is set, so piped output stays plain.
+fn styled(text: &str, code: &str) -> String {
+    if std::io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none() {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+// Control sequences are only written to a terminal, like styles.
+fn terminal_control(sequence: &str) {
+    let mut stdout = std::io::stdout();
+    if stdout.is_terminal() {
+        let _ = stdout.write_all(sequence.as_bytes()).and_then(|_| stdout.flush());
+    }
+}
+
+fn set_echo(on: bool) {
+    let setting = if on { "echo" } else { "-echo" };
+    let _ = std::process::Command::new("stty").arg(setting).stdin(std::process::Stdio::inherit()).status();
+}
+
+// Windows keeps the name in COMPUTERNAME and Linux in /proc; elsewhere the
+// `hostname` command knows it.
+fn host_name() -> Option<String> {
+    let name = if cfg!(windows) {
+        env::var("COMPUTERNAME").ok()?
+    } else if let Ok(name) = fs::read_to_string("/proc/sys/kernel/hostname") {
+        name
+    } else {
+        let output = Command::new("hostname").stdin(Stdio::null()).output().ok()?;
+        String::from_utf8(output.stdout).ok()?
+    };
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+// The commands that print and replace the clipboard's text on this platform.
+// On Linux, Wayland sessions use wl-clipboard and X sessions xclip.
+fn clipboard_programs() -> (&'static [&'static str], &'static [&'static str]) {
+    if cfg!(target_os = "macos") {
+        (&["pbpaste"], &["pbcopy"])
+    } else if cfg!(windows) {
+        (&["powershell", "-NoProfile", "-Command", "Get-Clipboard -Raw"], &["clip"])
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        (&["wl-paste", "--no-newline"], &["wl-copy"])
+    } else {
+        (&["xclip", "-selection", "clipboard", "-out"], &["xclip", "-selection", "clipboard", "-in"])
+    }
+}
+
+// COLUMNS and LINES take priority, as in most shells; otherwise `stty` is
+// asked about the terminal on stdin.
+fn terminal_size() -> Option<(i64, i64)> {
+    let from_env = |name| env::var(name).ok().and_then(|value| value.parse().ok());
+    if let (Some(columns), Some(rows)) = (from_env("COLUMNS"), from_env("LINES")) {
+        return Some((columns, rows));
+    }
+    let output = std::process::Command::new("stty").arg("size").stdin(std::process::Stdio::inherit()).output().ok()?;
+    let text = String::from_utf8(output.stdout).ok()?;
+    let mut parts = text.split_whitespace().map(|part| part.parse().ok());
+    match (parts.next()?, parts.next()?) {
+        (Some(rows), Some(columns)) => Some((columns, rows)),
+        _ => None,
+    }
+}
+
+// Approximate bytes a value holds: its own slot plus the text and elements
+// it owns. Shared values such as builders are counted once per reference,
+// and iterators and files only for their slot.
+fn value_size(value: &Value) -> usize {
+    let contents = match value {
+        Value::String(s) => s.len(),
+        Value::BigInt(n) => n.byte_size(),
+        Value::Decimal(_) => 16,
+        Value::Array(items) | Value::Set(ValueSet { items, .. }) => items.iter().map(value_size).sum(),
+        Value::Map(map) => map.iter().map(|(key, value)| key.len() + value_size(value)).sum(),
+        Value::Builder(builder) => builder.borrow().len(),
+        Value::Heap(heap) => heap.borrow().items.iter().map(value_size).sum(),
+        Value::Deque(items) => items.borrow().iter().map(value_size).sum(),
+        Value::Frozen(value, _) => value_size(value),
+        Value::Error { kind, message, .. } => kind.len() + message.len(),
+        Value::Number(_) | Value::Float(_) | Value::Boolean(_) | Value::Regex(_) | Value::Iterator(_) | Value::File(_) | Value::Socket(_) | Value::Process(_) | Value::Store(_) | Value::Progress(_) => 0,
+    };
+    std::mem::size_of::<Value>() + contents
+}
+
+// Anything that `for ... in` can walk: arrays and sets yield their elements,
+// strings their characters, maps their keys, and iterators are consumed as
+// they are.
+fn into_iter(value: Value) -> Result<LazyIter, String> {
+    match value {
+        Value::Iterator(iter) => Ok(iter),
+        Value::Array(arr) => Ok(LazyIter::new(arr.into_iter().map(Ok))),
+        Value::String(s) => {
+            let chars: Vec<Value> = s.chars().map(|c| Value::String(c.to_string())).collect();
+            Ok(LazyIter::new(chars.into_iter().map(Ok)))
+        }
+        Value::Set(set) => Ok(LazyIter::new(set.items.into_iter().map(Ok))),
+        // Walks the items as they were when the loop started.
+        Value::Deque(items) => Ok(LazyIter::new(items.borrow().clone().into_iter().map(Ok))),
+        Value::Map(map) => Ok(LazyIter::new(map.into_keys().map(|key| Ok(Value::String(key))))),
+        other => Err(format!("Cannot iterate over {:?}", other)),
+    }
+}
+
+fn error_message_of(error: &Value) -> String {
+    match error {
+        Value::Error { message, .. } => message.clone(),
+        _ => String::new(),
+    }
+}
+
+// Stores `value` at the end of the path of indexes and keys. Only the last
+// key of a path can add a new map entry.
+fn assign_at(target: &mut Value, keys: &[Value], value: Value) -> Result<(), String> {
+    let Some((key, rest)) = keys.split_first() else {
+        *target = value;
+        return Ok(());
+    };
+    let slot = match (target, key) {
+        (Value::Array(items), Value::Number(index)) => match index_position(items.len(), *index) {
+            Some(position) => &mut items[position],
+            None => return Err(format!("Index out of bounds: {}", index)),
+        },
+        (Value::Map(map), Value::String(key)) if rest.is_empty() => {
+            map.insert(key.clone(), value);
+            return Ok(());
+        }
+        (Value::Map(map), Value::String(key)) => match map.get_mut(key) {
+            Some(slot) => slot,
+            None => return Err(format!("Key not found: {}", key)),
+        },
+        (Value::Frozen(inner, line), _) => return Err(format!("Cannot change a frozen {} (frozen at line {})", type_name(inner), line)),
+        (target, key) => return Err(format!("Cannot assign to index {} of a {}", print_format(key), type_name(target))),
+    };
+    assign_at(slot, rest, value)
+}
+
+// Where `index` points in a sequence of `len` elements; -1 is the last.
+fn index_position(len: usize, index: i64) -> Option<usize> {
+    let position = if index < 0 { len as i64 + index } else { index };
+    (0..len as i64).contains(&position).then_some(position as usize)
+}
+
+// The positions a slice covers. A negative bound counts back from the end,
+// and bounds past either end stop there, so a slice never fails.
+fn slice_range(len: usize, start: Option<i64>, end: Option<i64>) -> (usize, usize) {
+    let position = |bound: i64| if bound < 0 { (len as i64 + bound).max(0) as usize } else { (bound as usize).min(len) };
+    let start = start.map_or(0, position);
+    let end = end.map_or(len, position);
+    (start, end.max(start))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Number(_) => "number",
+        Value::Float(_) => "float",
+        Value::BigInt(_) => "big integer",
+        Value::Decimal(_) => "decimal",
+        Value::String(_) => "string",
+        Value::Boolean(_) => "boolean",
+        Value::Array(_) => "array",
+        Value::Regex(_) => "regex",
+        Value::Iterator(_) => "iterator",
+        Value::Set(_) => "set",
+        Value::Builder(_) => "string builder",
+        Value::Heap(_) => "heap",
+        Value::Deque(_) => "deque",
+        Value::Map(_) => "map",
+        Value::Frozen(value, _) => type_name(value),
+        Value::Error { .. } => "error",
+        Value::File(_) => "file",
+        Value::Socket(_) => "socket",
+        Value::Process(_) => "process",
+        Value::Store(_) => "store",
+        Value::Progress(_) => "progress",
+    }
+}
+
+// Gives a builder its own buffer. Other values are already independent when
+// cloned, but with `deep` set their elements are visited so that builders
+// nested inside arrays, sets and maps are copied too.
+fn copy_value(value: &Value, deep: bool) -> Result<Value, String> {
+    match value {
+        Value::Builder(builder) => Ok(Value::Builder(Rc::new(RefCell::new(builder.borrow().clone())))),
+        Value::Heap(heap) => {
+            let heap = heap.borrow();
+            let items = if deep { heap.items.iter().map(|item| copy_value(item, true)).collect::<Result<_, _>>()? } else { heap.items.clone() };
+            Ok(Value::Heap(Rc::new(RefCell::new(Heap { items, max: heap.max }))))
+        }
+        Value::Deque(items) => {
+            let items = items.borrow();
+            let copied = if deep { items.iter().map(|item| copy_value(item, true)).collect::<Result<_, _>>()? } else { items.clone() };
+            Ok(Value::Deque(Rc::new(RefCell::new(copied))))
+        }
+        Value::Iterator(_) => Err("Iterators cannot be copied; use collect to turn one into an array".to_string()),
+        Value::Array(arr) if deep => Ok(Value::Array(arr.iter().map(|item| copy_value(item, true)).collect::<Result<_, _>>()?)),
+        Value::Set(set) if deep => Ok(Value::Set(set.items.iter().map(|item| copy_value(item, true)).collect::<Result<_, _>>()?)),
+        Value::Map(map) if deep => {
+            let mut copied = BTreeMap::new();
+            for (key, item) in map {
+                copied.insert(key.clone(), copy_value(item, true)?);
+            }
+            Ok(Value::Map(copied))
+        }
+        other => Ok(other.clone()),
+    }
+}
+
+// How many bytes at the end of `bytes` start a UTF-8 character without
+// finishing it.
+fn incomplete_tail(bytes: &[u8]) -> usize {
+    for length in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - length];
+        if byte & 0xc0 == 0x80 {
+            continue;
+        }
+        let width = match byte {
+            0xf0.. => 4,
+            0xe0.. => 3,
+            0xc0.. => 2,
+            _ => 1,
+        };
+        return if width > length { length } else { 0 };
+    }
+    0
+}
+
+// Values that `values_equal` considers equal get the same key. Numbers of
+// every kind that are equal to a whole number or a float share the key of
+// that float, since a number and a float compare by converting the number.
+fn set_key(value: &Value) -> String {
+    let number = |n: f64| format!("n{}", if n == 0.0 { 0.0 } else { n });
+    match value {
+        Value::Number(n) => number(*n as f64),
+        Value::Float(n) => number(*n),
+        Value::BigInt(n) => match n.to_string().parse::<i64>() {
+            Ok(n) => number(n as f64),
+            Err(_) => format!("b{}", n),
+        },
+        Value::Decimal(n) => {
+            let text = n.to_string();
+            let text = if text.contains('.') { text.trim_end_matches('0').trim_end_matches('.') } else { &text };
+            match text.parse::<i64>() {
+                Ok(n) => number(n as f64),
+                Err(_) => format!("d{}", text),
+            }
+        }
+        Value::String(s) => format!("s{}", s),
+        Value::Boolean(b) => format!("b{}", b),
+        Value::Array(items) => format!("a[{}]", items.iter().map(set_key).collect::<Vec<_>>().join(",")),
+        Value::Map(map) => format!("m{{{}}}", map.iter().map(|(key, value)| format!("{}:{}", key, set_key(value))).collect::<Vec<_>>().join(",")),
+        // Equal sets can list their items in any order.
+        Value::Set(set) => format!("set{}", set.items.len()),
+        other => type_name(other).to_string(),
+    }
+}
+
+// Validates the right-hand side of `"-" * 40` or `[0] * n`, rejecting negative
+// counts and results whose length would not fit in memory.
+// The result is sized in bytes, `item_size` per element, since that is what
+// the allocation has to fit in. Memory that runs out below the limit is
+// reported by the caller when reserving it.
+fn repetition_count(len: usize, item_size: usize, count: i64) -> Result<usize, String> {
+    if count < 0 {
+        return Err(format!("Repetition count cannot be negative: {}", count));
+    }
+    let count = count as usize;
+    match len.checked_mul(count).and_then(|total| total.checked_mul(item_size)) {
+        Some(total) if total <= isize::MAX as usize => Ok(count),
+        _ => Err(format!("Repetition result is too large: {} * {}", len, count)),
+    }
+}
+
+fn mean(numbers: &[f64]) -> f64 {
+    numbers.iter().sum::<f64>() / numbers.len() as f64
+}
+
+// Interpolates linearly between the two closest ranks, so the 50th
+// percentile of an even number of values is the mean of the middle two.
+fn percentile(mut numbers: Vec<f64>, p: f64) -> f64 {
+    numbers.sort_by(|a, b| a.total_cmp(b));
+    let rank = p / 100.0 * (numbers.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    numbers[lower] + (numbers[upper] - numbers[lower]) * (rank - lower as f64)
+}
+
+fn is_arithmetic(operator: &Token) -> bool {
+    matches!(operator, Token::Plus | Token::Minus | Token::Star | Token::Slash)
+}
+
+fn operator_symbol(operator: &Token) -> &'static str {
+    match operator {
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Star => "*",
+        _ => "/",
+    }
+}
+
+fn is_big(value: &Value) -> bool {
+    matches!(value, Value::BigInt(_))
+}
+
+// Whole numbers widen to big integers when mixed with them.
+fn as_big(value: &Value) -> Option<BigInt> {
+    match value {
+        Value::Number(n) => Some(BigInt::from_i64(*n)),
+        Value::BigInt(n) => Some(n.clone()),
+        _ => None,
+    }
+}
+
+// The directory a glob pattern starts from: everything before the path
+// component holding the first wildcard.
+fn glob_root(pattern: &str) -> &str {
+    let literal = &pattern[..pattern.find(['*', '?', '[']).unwrap_or(pattern.len())];
+    match literal.rfind('/') {
+        Some(0) => "/",
+        Some(slash) => &literal[..slash],
+        None => ".",
+    }
+}
+
+fn is_decimal(value: &Value) -> bool {
+    matches!(value, Value::Decimal(_))
+}
+
+// Whole numbers widen to decimals when mixed with them.
+fn as_decimal(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Number(n) => Some(Decimal::from_i64(*n)),
+        Value::Decimal(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn is_float(value: &Value) -> bool {
+    matches!(value, Value::Float(_))
+}
+
+// The value of a number or float as a float, for mixed arithmetic.
+fn as_float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => Some(*n as f64),
+        Value::Float(n) => Some(*n),
+        _ => None,
+    }
+}
+
+// Element-wise comparison used by `==` and `!=` on arrays. Nested arrays are
+// compared recursively and elements of different types are never equal.
+fn arrays_equal(left: &[Value], right: &[Value]) -> bool {
+    left.len() == right.len() && left.iter().zip(right).all(|(l, r)| values_equal(l, r))
+}
+
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => l == r,
+        (l, r) if is_big(l) || is_big(r) => as_big(l).is_some() && as_big(l) == as_big(r),
+        (l, r) if is_decimal(l) || is_decimal(r) => as_decimal(l).is_some() && as_decimal(l) == as_decimal(r),
+        (l, r) if is_float(l) || is_float(r) => as_float(l).is_some() && as_float(l) == as_float(r),
+        (Value::String(l), Value::String(r)) => l == r,
+        (Value::Boolean(l), Value::Boolean(r)) => l == r,
+        (Value::Array(l), Value::Array(r)) => arrays_equal(l, r),
+        (Value::Map(l), Value::Map(r)) => l.len() == r.len() && l.iter().all(|(key, value)| r.get(key).is_some_and(|other| values_equal(value, other))),
+        (Value::Set(l), Value::Set(r)) => l.items.len() == r.items.len() && l.items.iter().all(|item| r.contains(item)),
+        _ => false,
+    }
+}
+
+// The order `sort` puts values in: numbers by value, strings by character,
+// false before true, and arrays element by element, so an array of keys
+// sorts by the first key and then by the next. `None` for values of types
+// that cannot be ordered against each other. With `natural`, runs of digits
+// inside strings compare as numbers.
+fn compare_values(left: &Value, right: &Value, natural: bool) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Frozen(l, _), r) => compare_values(l, r, natural),
+        (l, Value::Frozen(r, _)) => compare_values(l, r, natural),
+        (Value::Number(l), Value::Number(r)) => Some(l.cmp(r)),
+        (l, r) if is_big(l) || is_big(r) => Some(as_big(l)?.cmp(&as_big(r)?)),
+        (l, r) if is_decimal(l) || is_decimal(r) => Some(as_decimal(l)?.compare(&as_decimal(r)?)),
+        (l, r) if is_float(l) || is_float(r) => as_float(l)?.partial_cmp(&as_float(r)?),
+        (Value::String(l), Value::String(r)) if natural => Some(natural_cmp(l, r)),
+        (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+        (Value::Boolean(l), Value::Boolean(r)) => Some(l.cmp(r)),
+        (Value::Array(l), Value::Array(r)) => {
+            for (l, r) in l.iter().zip(r) {
+                match compare_values(l, r, natural)? {
+                    Ordering::Equal => {}
+                    ordering => return Some(ordering),
+                }
+            }
+            Some(l.len().cmp(&r.len()))
+        }
+        _ => None,
+    }
+}
+
+// Where `value` is in the sorted `items`, found by halving.
+fn search_sorted(items: &[Value], value: &Value, name: &str) -> Result<Option<usize>, String> {
+    let mut failed = None;
+    let found = items.binary_search_by(|item| {
+        compare_values(item, value, false).unwrap_or_else(|| {
+            failed.get_or_insert_with(|| format!("{} cannot order {} and {} values", name, type_name(item), type_name(value)));
+            Ordering::Equal
+        })
+    });
+    match failed {
+        Some(message) => Err(message),
+        None => Ok(found.ok()),
+    }
+}
+
+// Sorts (key, item) pairs by key and keeps the items.
+fn sort_keyed(mut keyed: Vec<(Value, Value)>, natural: bool, name: &str) -> Result<Vec<Value>, String> {
+    let mut failed = None;
+    keyed.sort_by(|(l, _), (r, _)| {
+        compare_values(l, r, natural).unwrap_or_else(|| {
+            failed.get_or_insert_with(|| format!("{} cannot order {} and {} values", name, type_name(l), type_name(r)));
+            Ordering::Equal
+        })
+    });
+    match failed {
+        Some(message) => Err(message),
+        None => Ok(keyed.into_iter().map(|(_, item)| item).collect()),
+    }
+}
+
+// "file2" before "file10": strings are compared piece by piece, where a
+// piece is a run of digits or a run of anything else, and runs of digits by
+// their value. Leading zeros only break ties.
+fn natural_cmp(left: &str, right: &str) -> Ordering {
+    fn pieces(text: &str) -> Vec<&str> {
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        for (i, c) in text.char_indices().skip(1) {
+            let previous = text[..i].chars().next_back().unwrap_or(c);
+            if previous.is_ascii_digit() != c.is_ascii_digit() {
+                pieces.push(&text[start..i]);
+                start = i;
+            }
+        }
+        if start < text.len() {
+            pieces.push(&text[start..]);
+        }
+        pieces
+    }
+    for (l, r) in pieces(left).into_iter().zip(pieces(right)) {
+        let ordering = if l.starts_with(|c: char| c.is_ascii_digit()) && r.starts_with(|c: char| c.is_ascii_digit()) {
+            let (l_value, r_value) = (l.trim_start_matches('0'), r.trim_start_matches('0'));
+            l_value.len().cmp(&r_value.len()).then_with(|| l_value.cmp(r_value))
+        } else {
+            l.cmp(r)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    left.len().cmp(&right.len()).then_with(|| left.cmp(right))
+}
@@ -0,0 +1,61 @@
+// Edit distance and fuzzy matching behind `edit_distance` and
+// `fuzzy_match`, and the "did you mean" suggestions in error messages.
+// Distances count characters, not bytes: the fewest insertions, deletions
+// and substitutions that turn one string into the other.
+
+pub fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // One row of the usual table, reused for every character of `a`.
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb { diagonal } else { 1 + diagonal.min(above).min(row[j]) };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+// How far a candidate may be from the query and still count as a typo of it.
+fn typo_limit(query: &str) -> usize {
+    (query.chars().count() / 3).max(1)
+}
+
+// Whether the characters of `query` appear in `candidate` in order, as in
+// "gtc" for "git_commit".
+fn is_subsequence(query: &str, candidate: &str) -> bool {
+    let mut rest = candidate.chars();
+    query.chars().all(|c| rest.any(|d| d == c))
+}
+
+// The candidates that contain the query's characters in order or are within
+// a typo of it, ignoring case, closest first. Equally close candidates keep
+// their order.
+pub fn rank<'a>(query: &str, candidates: &[&'a str]) -> Vec<&'a str> {
+    let query = query.to_lowercase();
+    let mut matches: Vec<(usize, &str)> = candidates
+        .iter()
+        .filter_map(|&candidate| {
+            let lowered = candidate.to_lowercase();
+            let distance = distance(&query, &lowered);
+            (distance <= typo_limit(&query) || is_subsequence(&query, &lowered)).then_some((distance, candidate))
+        })
+        .collect();
+    matches.sort_by_key(|&(distance, _)| distance);
+    matches.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+// The candidate a misspelled `name` most likely meant, if any is close
+// enough to be a typo.
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .filter(|&candidate| candidate != name)
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= typo_limit(name))
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
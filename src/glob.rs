@@ -0,0 +1,172 @@
+use std::fs;
+use std::path::Path;
+
+// Shell-style wildcard matching for the `glob` and `fnmatch` builtins.
+// `*` matches any run of characters, `?` a single character, and `[abc]`,
+// `[a-z]` or `[!a-z]` one character from (or not from) a set. In `glob`,
+// a path component of `**` matches any number of nested directories.
+
+pub fn fnmatch(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name)
+}
+
+// The usual single-backtrack matcher: on a mismatch, go back to the last
+// `*` and let it swallow one more character. An earlier `*` never needs to
+// be retried, because the later one can already absorb anything it would,
+// so matching takes at most pattern length times name length steps.
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // The pattern position just after the last `*`, and where in the name
+    // that `*` stopped matching.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, n));
+        } else if let Some(next) = step(pattern, p, name[n]) {
+            p = next;
+            n += 1;
+        } else if let Some((after, stopped)) = star {
+            p = after;
+            n = stopped + 1;
+            star = Some((after, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Where the pattern continues after `pattern[p]` matches `c`, or `None` when
+// it does not. `*` is handled by the caller.
+fn step(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match pattern.get(p)? {
+        '?' => Some(p + 1),
+        '[' => match class_end(&pattern[p..]) {
+            Some(end) => class_matches(&pattern[p + 1..p + end], c).then_some(p + end + 1),
+            None => (c == '[').then_some(p + 1),
+        },
+        &literal => (c == literal).then_some(p + 1),
+    }
+}
+
+// Index of the `]` closing the class that starts at `pattern[0]`. A `]`
+// right after the opening bracket (or after `!`) is part of the set.
+fn class_end(pattern: &[char]) -> Option<usize> {
+    let mut index = 1;
+    if pattern.get(index) == Some(&'!') {
+        index += 1;
+    }
+    if pattern.get(index) == Some(&']') {
+        index += 1;
+    }
+    pattern[index..].iter().position(|&c| c == ']').map(|offset| index + offset)
+}
+
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.split_first() {
+        Some(('!', rest)) => (true, rest),
+        _ => (false, class),
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            found |= class[i] <= c && c <= class[i + 2];
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+fn has_wildcard(part: &str) -> bool {
+    part.contains(['*', '?', '['])
+}
+
+// Expands a pattern such as `logs/**/*.txt` into the sorted list of paths
+// that exist and match it. Hidden entries are only matched by components
+// that themselves start with a dot, as in most shells.
+pub fn glob(pattern: &str) -> Vec<String> {
+    let (root, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => ("/", rest),
+        None => ("", pattern),
+    };
+    let parts: Vec<&str> = rest.split('/').filter(|part| !part.is_empty() && *part != ".").collect();
+    let mut results = Vec::new();
+    expand(root, &parts, &mut results);
+    results.sort();
+    results.dedup();
+    results
+}
+
+fn expand(prefix: &str, parts: &[&str], results: &mut Vec<String>) {
+    let (part, rest) = match parts.split_first() {
+        Some(split) => split,
+        None => {
+            if !prefix.is_empty() {
+                results.push(prefix.to_string());
+            }
+            return;
+        }
+    };
+    if *part == "**" {
+        expand(prefix, rest, results);
+        for (name, is_dir) in list_dir(prefix) {
+            if name.starts_with('.') {
+                continue;
+            }
+            let path = join(prefix, &name);
+            if is_dir {
+                expand(&path, parts, results);
+            } else if rest.is_empty() {
+                results.push(path);
+            }
+        }
+    } else if !has_wildcard(part) {
+        let path = join(prefix, part);
+        if fs::symlink_metadata(&path).is_ok() {
+            expand(&path, rest, results);
+        }
+    } else {
+        for (name, is_dir) in list_dir(prefix) {
+            if name.starts_with('.') && !part.starts_with('.') {
+                continue;
+            }
+            if fnmatch(part, &name) && (rest.is_empty() || is_dir) {
+                expand(&join(prefix, &name), rest, results);
+            }
+        }
+    }
+}
+
+// Directories that cannot be read are skipped rather than failing the
+// whole expansion. Symlinks are not followed into, which avoids cycles.
+fn list_dir(prefix: &str) -> Vec<(String, bool)> {
+    let dir = if prefix.is_empty() { "." } else { prefix };
+    let entries = match fs::read_dir(Path::new(dir)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let is_dir = entry.file_type().map(|kind| kind.is_dir()).unwrap_or(false);
+            (entry.file_name().to_string_lossy().into_owned(), is_dir)
+        })
+        .collect()
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else if prefix.ends_with('/') {
+        format!("{}{}", prefix, name)
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
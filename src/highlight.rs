@@ -0,0 +1,58 @@
+use crate::semantic;
+
+// HTML output for `nabeelscript highlight`. Each token becomes a span with a
+// class named after its kind, such as `ns-keyword`, and the page carries a
+// small stylesheet so it can be opened or embedded as it is. Pages that
+// bring their own styles only need to define the same classes.
+
+const STYLE: &str = "pre.nabeel { background: #fafafa; border: 1px solid #ddd; padding: 1em; overflow-x: auto; }
+.ns-keyword { color: #a626a4; font-weight: bold; }
+.ns-function { color: #4078f2; }
+.ns-identifier { color: #383a42; }
+.ns-string { color: #50a14f; }
+.ns-number { color: #986801; }
+.ns-comment { color: #a0a1a7; font-style: italic; }
+.ns-operator { color: #0184bc; }
+.ns-punctuation { color: #696c77; }";
+
+// Renders one source file as a `<pre>` block. Text between tokens, such as
+// whitespace, is copied through unchanged.
+pub fn to_html(source: &str) -> Result<String, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut html = String::from("<pre class=\"nabeel\"><code>");
+    let mut position = 0;
+    for token in semantic::tokens(source)? {
+        push_escaped(&mut html, &chars[position..token.start]);
+        html.push_str(&format!("<span class=\"ns-{}\">", token.kind.name()));
+        push_escaped(&mut html, &chars[token.start..token.end]);
+        html.push_str("</span>");
+        position = token.end;
+    }
+    push_escaped(&mut html, &chars[position.min(chars.len())..]);
+    html.push_str("</code></pre>");
+    Ok(html)
+}
+
+// Wraps rendered blocks, each with its title, in a complete HTML page.
+pub fn page(blocks: &[(String, String)]) -> String {
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>\n{}\n</style>\n</head>\n<body>\n", STYLE);
+    for (title, block) in blocks {
+        let mut escaped = String::new();
+        push_escaped(&mut escaped, &title.chars().collect::<Vec<char>>());
+        html.push_str(&format!("<h3>{}</h3>\n{}\n", escaped, block));
+    }
+    html.push_str("</body>\n</html>");
+    html
+}
+
+fn push_escaped(html: &mut String, text: &[char]) {
+    for &c in text {
+        match c {
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '&' => html.push_str("&amp;"),
+            '"' => html.push_str("&quot;"),
+            c => html.push(c),
+        }
+    }
+}
@@ -1,6 +1,11 @@
+use std::cell::RefCell;
+use std::io::{self, Read};
+use std::rc::Rc;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Number(i64),
+    Float(f64),
     StringLiteral(String),
     Plus,
     Minus,
@@ -13,10 +18,8 @@ pub enum Token {
     LParen,
     RParen,
     EOF,
-    Join,
-    Split,
-    Count,
     Comma,
+    Colon,
     True,
     False,
     Eq,      // ==
@@ -28,6 +31,7 @@ pub enum Token {
     And,     // &&
     Or,      // ||
     Not,     // !
+    Spread,  // ...
     LBracket,
     RBracket,
     If,
@@ -37,47 +41,204 @@ pub enum Token {
     RBrace,
     While,
     For,
-    Length,
-    Uppercase,
-    Lowercase,
-    Trim,
-    Replace,
-    Push,
-    Pop,
-    First,
-    Last,
-    ReadFile,
-    WriteFile,
+    In,
+    Try,
+    Catch,
+    Break,
+    Continue,
+    Repeat,
+    // Only produced by `Lexer::with_comments`; holds the text after `//`.
+    Comment(String),
+}
+
+// The characters of a script, decoded as they are needed. A lexer and the
+// copies the parser makes to look ahead share one source, so input read by
+// any of them is read from the reader only once.
+struct Source {
+    chars: Vec<char>,
+    // None for scripts given as a string, and once the reader is used up.
+    reader: Option<Box<dyn Read>>,
+    // The start of a UTF-8 sequence split across two reads.
+    partial: Vec<u8>,
+    error: Option<String>,
 }
 
+impl Source {
+    fn get(&mut self, index: usize) -> Option<char> {
+        while index >= self.chars.len() && self.reader.is_some() {
+            self.fill();
+        }
+        self.chars.get(index).copied()
+    }
+
+    fn fill(&mut self) {
+        let Some(reader) = &mut self.reader else {
+            return;
+        };
+        let mut chunk = [0u8; 8192];
+        let count = match reader.read(&mut chunk) {
+            Ok(0) => {
+                if !self.partial.is_empty() {
+                    self.error = Some("Input is not valid UTF-8".to_string());
+                }
+                self.reader = None;
+                return;
+            }
+            Ok(count) => count,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => return,
+            Err(e) => {
+                self.error = Some(format!("Error reading input: {}", e));
+                self.reader = None;
+                return;
+            }
+        };
+        self.partial.extend_from_slice(&chunk[..count]);
+        let valid = match std::str::from_utf8(&self.partial) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => {
+                self.error = Some("Input is not valid UTF-8".to_string());
+                self.reader = None;
+                return;
+            }
+        };
+        let text = std::str::from_utf8(&self.partial[..valid]).unwrap_or_default();
+        self.chars.extend(text.chars());
+        self.partial.drain(..valid);
+    }
+
+    fn text(&self, start: usize, end: usize) -> String {
+        self.chars[start..end].iter().collect()
+    }
+}
+
+#[derive(Clone)]
 pub struct Lexer {
-    input: String,
+    source: Rc<RefCell<Source>>,
     position: usize,
     current_char: Option<char>,
+    // Character offset of `current_char`; a `\r\n` pair starts at its `\r`.
+    char_start: usize,
+    line: usize,
+    // Character offset where the current line starts.
+    line_start: usize,
+    token_line: usize,
+    token_line_start: usize,
+    // Character offset where the most recently returned token starts.
+    token_start: usize,
+    keep_comments: bool,
 }
 
 impl Lexer {
     pub fn new(input: String) -> Self {
+        Lexer::start(Source { chars: input.chars().collect(), reader: None, partial: Vec::new(), error: None })
+    }
+
+    // A lexer that reads the script from `reader` a chunk at a time as tokens
+    // are asked for, so a long script piped in need not be held in full
+    // before parsing starts.
+    pub fn from_reader(reader: impl Read + 'static) -> Self {
+        Lexer::start(Source { chars: Vec::new(), reader: Some(Box::new(reader)), partial: Vec::new(), error: None })
+    }
+
+    fn start(source: Source) -> Self {
         let mut lexer = Lexer {
-            input,
+            source: Rc::new(RefCell::new(source)),
             position: 0,
             current_char: None,
+            char_start: 0,
+            line: 1,
+            line_start: 0,
+            token_line: 1,
+            token_line_start: 0,
+            token_start: 0,
+            keep_comments: false,
         };
         lexer.read_char();
+        // Editors on Windows often start UTF-8 files with a byte order mark.
+        if lexer.current_char == Some('\u{feff}') {
+            lexer.read_char();
+            lexer.line_start = lexer.position - 1;
+        }
+        // A `#!` line at the very start lets scripts be run directly on Unix.
+        if lexer.current_char == Some('#') && lexer.peek() == Some('!') {
+            lexer.skip_comment();
+        }
         lexer
     }
 
+    // A lexer that returns comments as `Token::Comment` instead of skipping
+    // them, for tools such as highlighters that need to see them.
+    pub fn with_comments(input: String) -> Self {
+        Lexer { keep_comments: true, ..Lexer::new(input) }
+    }
+
+    // The line the most recently returned token started on.
+    pub fn token_line(&self) -> usize {
+        self.token_line
+    }
+
+    // The column, counted in characters from 0, where the most recently
+    // returned token starts.
+    pub fn token_column(&self) -> usize {
+        self.token_start - self.token_line_start
+    }
+
+    // The character offsets where the most recently returned token starts
+    // and ends, end exclusive.
+    pub fn token_span(&self) -> (usize, usize) {
+        (self.token_start, self.char_start)
+    }
+
+    // The text of the most recently returned token.
+    pub fn token_text(&self) -> String {
+        self.text_from(self.token_start)
+    }
+
+    // The whole line the most recently returned token starts on, without
+    // its line ending, for showing in error messages.
+    pub fn token_line_text(&self) -> String {
+        let mut source = self.source.borrow_mut();
+        let mut text = String::new();
+        let mut position = self.token_line_start;
+        while let Some(c) = source.get(position) {
+            if c == '\n' || c == '\r' {
+                break;
+            }
+            text.push(c);
+            position += 1;
+        }
+        text
+    }
+
     fn read_char(&mut self) {
-        self.current_char = if self.position >= self.input.len() {
-            None
-        } else {
-            Some(self.input.chars().nth(self.position).unwrap())
-        };
+        if self.current_char == Some('\n') {
+            self.line += 1;
+            self.line_start = self.position;
+        }
+        self.char_start = self.position;
+        self.current_char = self.source.borrow_mut().get(self.position);
+        // Windows `\r\n` and old Mac `\r` line endings both read as a
+        // single `\n`, so comments, strings and line numbers never see a `\r`.
+        if self.current_char == Some('\r') {
+            if self.source.borrow_mut().get(self.position + 1) == Some('\n') {
+                self.position += 1;
+            }
+            self.current_char = Some('\n');
+        }
         self.position += 1;
     }
 
     pub fn next_token(&mut self) -> Result<Token, String> {
         self.skip_whitespace();
+        self.token_line = self.line;
+        self.token_line_start = self.line_start;
+        self.token_start = self.char_start;
+        if self.current_char.is_none() {
+            if let Some(error) = self.source.borrow_mut().error.take() {
+                return Err(error);
+            }
+        }
         match self.current_char {
             Some('+') => {
                 self.read_char();
@@ -93,7 +254,11 @@ impl Lexer {
             }
             Some('/') => {
                 self.read_char();
-                if self.current_char == Some('/') {
+                if self.current_char == Some('/') && self.keep_comments {
+                    let start = self.position;
+                    self.skip_comment();
+                    Ok(Token::Comment(self.source.borrow().text(start, self.char_start)))
+                } else if self.current_char == Some('/') {
                     self.skip_comment();
                     self.next_token()
                 } else {
@@ -149,6 +314,16 @@ impl Lexer {
                     Err("Expected '&&'".to_string())
                 }
             }
+            Some('.') => {
+                self.read_char();
+                if self.current_char == Some('.') && self.peek() == Some('.') {
+                    self.read_char();
+                    self.read_char();
+                    Ok(Token::Spread)
+                } else {
+                    Err("Expected '...'".to_string())
+                }
+            }
             Some('|') => {
                 if self.peek() == Some('|') {
                     self.read_char();
@@ -171,14 +346,12 @@ impl Lexer {
                 Ok(Token::RParen)
             }
             Some('"') => self.read_string().map(Token::StringLiteral),
-            Some(c) if c.is_digit(10) => self.read_number().map(Token::Number),
+            Some('`') => self.read_raw_string().map(Token::StringLiteral),
+            Some(c) if c.is_digit(10) => self.read_number(),
             Some(c) if c.is_alphabetic() || c == '_' => {
                 let ident = self.read_identifier();
                 match ident.as_str() {
                     "print" => Ok(Token::Print),
-                    "join" => Ok(Token::Join),
-                    "split" => Ok(Token::Split),
-                    "count" => Ok(Token::Count),
                     "true" => Ok(Token::True),
                     "false" => Ok(Token::False),
                     "if" => Ok(Token::If),
@@ -186,17 +359,12 @@ impl Lexer {
                     "elseif" => Ok(Token::ElseIf),
                     "while" => Ok(Token::While),
                     "for" => Ok(Token::For),
-                    "length" => Ok(Token::Length),
-                    "uppercase" => Ok(Token::Uppercase),
-                    "lowercase" => Ok(Token::Lowercase),
-                    "trim" => Ok(Token::Trim),
-                    "replace" => Ok(Token::Replace),
-                    "push" => Ok(Token::Push),
-                    "pop" => Ok(Token::Pop),
-                    "first" => Ok(Token::First),
-                    "last" => Ok(Token::Last),
-                    "read_file" => Ok(Token::ReadFile),
-                    "write_file" => Ok(Token::WriteFile),
+                    "in" => Ok(Token::In),
+                    "try" => Ok(Token::Try),
+                    "catch" => Ok(Token::Catch),
+                    "break" => Ok(Token::Break),
+                    "continue" => Ok(Token::Continue),
+                    "repeat" => Ok(Token::Repeat),
                     _ => Ok(Token::Identifier(ident)),
                 }
             }
@@ -204,6 +372,10 @@ impl Lexer {
                 self.read_char();
                 Ok(Token::Comma)
             }
+            Some(':') => {
+                self.read_char();
+                Ok(Token::Colon)
+            }
             Some('[') => {
                 self.read_char();
                 Ok(Token::LBracket)
@@ -237,37 +409,113 @@ impl Lexer {
         }
     }
 
-    fn read_number(&mut self) -> Result<i64, String> {
-        let start = self.position - 1;
-        while self.current_char.is_some() && self.current_char.unwrap().is_digit(10) {
+    fn read_number(&mut self) -> Result<Token, String> {
+        let start = self.char_start;
+        // Underscores may separate digit groups, as in `10_000_000`.
+        while self.current_char.is_some() && (self.current_char.unwrap().is_digit(10) || self.current_char.unwrap() == '_') {
             self.read_char();
         }
-        self.input[start..self.position - 1].parse().map_err(|e: std::num::ParseIntError| e.to_string())
+        // A dot followed by a digit makes a float such as `2.5`.
+        if self.current_char == Some('.') && self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.read_char();
+            while self.current_char.is_some_and(|c| c.is_ascii_digit() || c == '_') {
+                self.read_char();
+            }
+            let text = self.text_from(start).replace('_', "");
+            return text.parse().map(Token::Float).map_err(|e: std::num::ParseFloatError| e.to_string());
+        }
+        self.text_from(start).replace('_', "").parse().map(Token::Number).map_err(|e: std::num::ParseIntError| e.to_string())
     }
 
     fn read_identifier(&mut self) -> String {
-        let start = self.position - 1;
-        while self.current_char.is_some() && (self.current_char.unwrap().is_alphabetic() || self.current_char.unwrap() == '_') {
+        let start = self.char_start;
+        while self.current_char.is_some() && (self.current_char.unwrap().is_alphanumeric() || self.current_char.unwrap() == '_') {
             self.read_char();
         }
-        self.input[start..self.position - 1].to_string()
+        self.text_from(start)
     }
 
     fn read_string(&mut self) -> Result<String, String> {
+        if self.peek() == Some('"') && self.source.borrow_mut().get(self.position + 1) == Some('"') {
+            return self.read_triple_quoted_string();
+        }
         self.read_char(); // Skip the opening quote
-        let start = self.position - 1;
+        let start = self.char_start;
         while self.current_char.is_some() && self.current_char.unwrap() != '"' {
             self.read_char();
         }
         if self.current_char.is_none() {
-            return Err("Unterminated string literal".to_string());
+            return Err("Unterminated string literal: expected '\"' before the end of the file".to_string());
         }
-        let result = self.input[start..self.position - 1].to_string();
+        let result = self.text_from(start);
         self.read_char(); // Skip the closing quote
         Ok(result)
     }
 
+    // The characters from `start` up to the current one, with line endings
+    // normalized as `read_char` does.
+    fn text_from(&self, start: usize) -> String {
+        let text = self.source.borrow().text(start, self.char_start);
+        if text.contains('\r') {
+            text.replace("\r\n", "\n").replace('\r', "\n")
+        } else {
+            text
+        }
+    }
+
+    // Backtick strings hold everything up to the next backtick unchanged,
+    // including double quotes and line breaks, which suits regexes, Windows
+    // paths and quoted text.
+    fn read_raw_string(&mut self) -> Result<String, String> {
+        self.read_char(); // Skip the opening backtick
+        let start = self.char_start;
+        while self.current_char.is_some_and(|c| c != '`') {
+            self.read_char();
+        }
+        if self.current_char.is_none() {
+            return Err("Unterminated raw string literal: expected '`' before the end of the file".to_string());
+        }
+        let result = self.text_from(start);
+        self.read_char(); // Skip the closing backtick
+        Ok(result)
+    }
+
+    // `"""` strings run to the next `"""` and may hold quotes and line
+    // breaks. When the opening quotes end their line, the literal is a
+    // block: that first line break is dropped, as is the indentation shared
+    // by its lines and any spaces before the closing quotes, so the text can
+    // be indented along with the code around it.
+    fn read_triple_quoted_string(&mut self) -> Result<String, String> {
+        for _ in 0..3 {
+            self.read_char();
+        }
+        let start = self.char_start;
+        while self.current_char.is_some() && !(self.current_char == Some('"') && self.peek() == Some('"') && self.source.borrow_mut().get(self.position + 1) == Some('"')) {
+            self.read_char();
+        }
+        if self.current_char.is_none() {
+            return Err("Unterminated triple-quoted string literal: expected '\"\"\"' before the end of the file".to_string());
+        }
+        let text = self.text_from(start);
+        for _ in 0..3 {
+            self.read_char();
+        }
+        let Some(block) = text.strip_prefix('\n') else {
+            return Ok(text);
+        };
+        let mut lines: Vec<&str> = block.split('\n').collect();
+        if lines.last().is_some_and(|line| line.trim().is_empty()) {
+            *lines.last_mut().unwrap() = "";
+        }
+        let indent = lines.iter().filter(|line| !line.trim().is_empty()).map(|line| line.len() - line.trim_start().len()).min().unwrap_or(0);
+        let lines: Vec<&str> = lines.iter().map(|line| if line.trim().is_empty() { "" } else { line.get(indent..).unwrap_or(line.trim_start()) }).collect();
+        Ok(lines.join("\n"))
+    }
+
     fn peek(&self) -> Option<char> {
-        self.input.chars().nth(self.position)
+        match self.source.borrow_mut().get(self.position) {
+            Some('\r') => Some('\n'),
+            c => c,
+        }
     }
 }
\ No newline at end of file
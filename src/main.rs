@@ -1,48 +1,355 @@
 mod lexer;
 mod parser;
 mod evaluator;
+mod ast_json;
+mod audit;
+mod bigint;
+mod builtins;
+mod cache;
+mod cli;
+mod decimal;
+mod diagnostic;
+mod fuzzy;
+mod glob;
+mod highlight;
+mod json;
+mod manifest;
+mod notebook;
+mod permissions;
+mod progress;
+mod random;
+mod regex;
+mod repl;
+mod resolver;
+mod semantic;
+mod sha256;
+mod store;
+mod template;
+mod trivia;
+mod url;
+mod visit;
+mod walk;
 
 use lexer::Lexer;
 use parser::Parser;
-use evaluator::Evaluator;
+use parser::ASTNode;
+use evaluator::{Evaluator, Value};
+use audit::AuditLog;
+use resolver::Resolver;
+use cli::{Command, Options};
+use diagnostic::{Diagnostic, ErrorFormat, Span};
+use json::Json;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::fs;
 use std::env;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <file.nabeel>", args[0]);
-        return;
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match cli::parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    let ok = match options.command {
+        Command::Run if options.ast => print_asts(&options.files, options.error_format),
+        Command::Run if options.ast_json => print_ast_json(&options.files, options.error_format),
+        Command::Run if options.tokens => print_tokens(&options.files),
+        Command::Run => run_files(&options),
+        Command::Check => check_files(&options.files, options.error_format),
+        Command::Watch => watch_files(&options),
+        Command::Test => test_files(&options),
+        Command::Highlight => highlight_files(&options.files),
+        Command::Notebook => {
+            notebook::run(&options);
+            true
+        }
+        Command::Repl => {
+            repl::run(&options);
+            true
+        }
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            true
+        }
+        Command::Version => {
+            println!("nabeelscript {}", cli::VERSION);
+            true
+        }
+    };
+    if !ok {
+        process::exit(1);
     }
-    let file_path = &args[1];
+}
+
+// A path of "-" reads the script from stdin, lexing it as it arrives.
+fn parse_file(file_path: &str, format: ErrorFormat) -> Option<Rc<RefCell<ASTNode>>> {
+    let lexer = if file_path == "-" {
+        Lexer::from_reader(std::io::stdin())
+    } else {
+        Lexer::new(read_file(file_path, format)?)
+    };
+    parse_source(file_path, Parser::new(lexer), format).map(|(ast, _)| ast)
+}
 
-    let input = match fs::read_to_string(file_path) {
-        Ok(content) => content,
+fn read_file(file_path: &str, format: ErrorFormat) -> Option<String> {
+    match fs::read_to_string(file_path) {
+        Ok(content) => Some(content),
         Err(e) => {
-            eprintln!("Error reading file {}: {}", file_path, e);
-            return;
+            Diagnostic::read(file_path, e.to_string()).report(format);
+            None
         }
-    };
+    }
+}
 
-    let lexer = Lexer::new(input);
-    let mut parser = match Parser::new(lexer) {
+// Returns the parser along with the tree for callers that want what else it
+// collected, such as comments.
+fn parse_source(file_path: &str, parser: Result<Parser, String>, format: ErrorFormat) -> Option<(Rc<RefCell<ASTNode>>, Parser)> {
+    let mut parser = match parser {
         Ok(parser) => parser,
         Err(e) => {
-            eprintln!("Error initializing parser: {}", e);
-            return;
+            Diagnostic::syntax(file_path, e, None).report(format);
+            return None;
         }
     };
 
-    let ast = match parser.parse() {
-        Ok(ast) => ast,
+    match parser.parse() {
+        Ok(ast) => Some((ast, parser)),
         Err(e) => {
-            eprintln!("Error parsing input: {}", e);
-            return;
+            let (line, column, length) = parser.position();
+            Diagnostic::syntax(file_path, e, Some(Span::at(line, column, length))).with_source_line(parser.source_line()).report(format);
+            None
         }
-    };
-    let mut evaluator = Evaluator::new();
-    match evaluator.eval(ast) {
-        Ok(_) => (),
-        Err(e) => eprintln!("Error evaluating AST: {}", e),
     }
-}
\ No newline at end of file
+}
+
+// Files run in the order given and share one set of variables, so earlier
+// files can define values for later ones. Nothing runs unless every file
+// parses.
+fn run_files(options: &Options) -> bool {
+    let started = Instant::now();
+    let mut programs = Vec::new();
+    for file_path in &options.files {
+        match parse_file(file_path, options.error_format) {
+            Some(ast) => programs.push((file_path, ast)),
+            None => return false,
+        }
+    }
+    let mut evaluator = Evaluator::with_config(options.limits.clone());
+    evaluator.set_trace(options.trace);
+    if let Some(path) = &options.audit {
+        match AuditLog::open(path) {
+            Ok(audit) => evaluator.set_audit(audit),
+            Err(e) => {
+                eprintln!("{}", e);
+                return false;
+            }
+        }
+    }
+    let mut ok = true;
+    for (file_path, ast) in programs {
+        if let Err(e) = evaluator.eval(ast) {
+            if let Value::Error { kind, message, line } = evaluator.caught_error(e) {
+                Diagnostic::runtime(file_path, kind, message, line).report(options.error_format);
+            }
+            ok = false;
+            break;
+        }
+    }
+    if options.stats {
+        print_stats(&evaluator, started.elapsed());
+    }
+    ok
+}
+
+fn print_stats(evaluator: &Evaluator, elapsed: Duration) {
+    let stats = evaluator.stats();
+    eprintln!("--- stats ---");
+    eprintln!("Statements executed: {}", stats.statements);
+    eprintln!("Function calls:      {}", stats.function_calls);
+    eprintln!("Allocations:         {}", stats.allocations);
+    eprintln!("Peak variables:      {}", stats.peak_variables);
+    eprintln!("Peak memory:         {} bytes", stats.peak_memory);
+    eprintln!("Wall time:           {:.3} ms", elapsed.as_secs_f64() * 1000.0);
+}
+
+fn print_asts(file_paths: &[String], format: ErrorFormat) -> bool {
+    for file_path in file_paths {
+        match parse_file(file_path, format) {
+            Some(ast) => println!("{:#?}", ast.borrow()),
+            None => return false,
+        }
+    }
+    true
+}
+
+// Prints one JSON object per file holding its syntax tree and comments, as
+// {"file": ..., "ast": {"type": "Program", ...}}; see ast_json.rs.
+fn print_ast_json(file_paths: &[String], format: ErrorFormat) -> bool {
+    for file_path in file_paths {
+        let Some(input) = read_file(file_path, format) else {
+            return false;
+        };
+        let Some((ast, mut parser)) = parse_source(file_path, Parser::with_comments(input.clone()), format) else {
+            return false;
+        };
+        let trivia = trivia::attach(&input, &ast, parser.take_comments());
+        println!("{}", Json::object(vec![("file", Json::string(file_path)), ("ast", ast_json::to_json(&ast, &trivia))]));
+    }
+    true
+}
+
+// Prints one JSON object per file listing its tokens for highlighters,
+// as {"file": ..., "tokens": [{"line", "column", "length", "kind"}, ...]}.
+fn print_tokens(file_paths: &[String]) -> bool {
+    for file_path in file_paths {
+        let source = match fs::read_to_string(file_path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error reading file {}: {}", file_path, e);
+                return false;
+            }
+        };
+        match semantic::tokens(&source) {
+            Ok(tokens) => {
+                let tokens = Json::Array(tokens.iter().map(|token| token.to_json()).collect());
+                println!("{}", Json::object(vec![("file", Json::string(file_path)), ("tokens", tokens)]));
+            }
+            Err(e) => {
+                eprintln!("Error parsing {}: {}", file_path, e);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+// Prints one standalone HTML page with a highlighted block for each file.
+fn highlight_files(file_paths: &[String]) -> bool {
+    let mut blocks = Vec::new();
+    for file_path in file_paths {
+        let source = match fs::read_to_string(file_path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error reading file {}: {}", file_path, e);
+                return false;
+            }
+        };
+        match highlight::to_html(&source) {
+            Ok(block) => blocks.push((file_path.clone(), block)),
+            Err(e) => {
+                eprintln!("Error parsing {}: {}", file_path, e);
+                return false;
+            }
+        }
+    }
+    println!("{}", highlight::page(&blocks));
+    true
+}
+
+// Parses the files and looks for problems without running anything.
+// Returns whether every file is free of errors.
+fn check_files(file_paths: &[String], format: ErrorFormat) -> bool {
+    let mut programs = Vec::new();
+    for file_path in file_paths {
+        match parse_file(file_path, format) {
+            Some(ast) => programs.push((file_path, ast)),
+            None => return false,
+        }
+    }
+    let mut resolver = Resolver::new();
+    for (_, ast) in &programs {
+        resolver.declare(ast);
+    }
+    let mut ok = true;
+    for (file_path, ast) in &programs {
+        for problem in resolver.check(ast) {
+            Diagnostic::check(file_path, problem.code, problem.message, problem.line).report(format);
+            ok = false;
+        }
+    }
+    ok
+}
+
+// Runs each file on its own with `print` output captured and compares the
+// lines with the file's `// expect: <line>` comments, in order. A comment
+// can follow code on the same line or stand alone. Returns whether every
+// file matched.
+fn test_files(options: &Options) -> bool {
+    let mut failed = 0;
+    for file_path in &options.files {
+        let ast = match parse_file(file_path, options.error_format) {
+            Some(ast) => ast,
+            None => {
+                failed += 1;
+                continue;
+            }
+        };
+        let expected = match fs::read_to_string(file_path) {
+            Ok(source) => expectations(&source),
+            Err(_) => Vec::new(),
+        };
+        let mut evaluator = Evaluator::with_config(options.limits.clone());
+        evaluator.set_trace(options.trace);
+        evaluator.capture_output();
+        let result = evaluator.eval(ast);
+        let mut problems = Vec::new();
+        let actual = evaluator.take_output();
+        for (i, (line, want)) in expected.iter().enumerate() {
+            match actual.get(i) {
+                Some(got) if got == want => {}
+                Some(got) => problems.push(format!("line {}: expected {:?}, got {:?}", line, want, got)),
+                None => problems.push(format!("line {}: expected {:?}, got no more output", line, want)),
+            }
+        }
+        for got in actual.iter().skip(expected.len()) {
+            problems.push(format!("unexpected output {:?}", got));
+        }
+        if let Err(e) = result {
+            problems.push(format!("line {}: {}", evaluator.current_line(), e));
+        }
+        if problems.is_empty() {
+            println!("PASS {} ({} checks)", file_path, expected.len());
+        } else {
+            println!("FAIL {}", file_path);
+            for problem in problems {
+                println!("    {}", problem);
+            }
+            failed += 1;
+        }
+    }
+    println!("{} passed, {} failed", options.files.len() - failed, failed);
+    failed == 0
+}
+
+// The expected output lines with the source line each comes from.
+fn expectations(source: &str) -> Vec<(usize, String)> {
+    source
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| line.split_once("// expect:").map(|(_, expected)| (i + 1, expected.strip_prefix(' ').unwrap_or(expected).to_string())))
+        .collect()
+}
+
+// Runs the scripts, then polls their modification times and runs them
+// again whenever one changes, clearing the terminal first. Stops on Ctrl+C.
+fn watch_files(options: &Options) -> bool {
+    let file_paths = &options.files;
+    let modified = |path: &String| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let mut last_modified: Vec<Option<SystemTime>> = Vec::new();
+    loop {
+        let current: Vec<Option<SystemTime>> = file_paths.iter().map(modified).collect();
+        if current != last_modified {
+            last_modified = current;
+            print!("\x1b[2J\x1b[H");
+            run_files(options);
+            println!("\n[watching {} for changes]", file_paths.join(", "));
+        }
+        thread::sleep(Duration::from_millis(300));
+    }
+}
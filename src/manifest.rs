@@ -0,0 +1,167 @@
+use crate::evaluator::EvaluatorConfig;
+use crate::permissions::{Grant, Permissions};
+use std::fs;
+use std::path::Path;
+
+// The `nabeel.toml` project manifest. When `run`, `check`, `watch` or `test`
+// is given no files, the manifest in the current directory names the entry
+// file and the limits to run it under:
+//
+//   [project]
+//   name = "invoices"
+//   entry = "src/main.nabeel"
+//
+//   [limits]
+//   max_memory = 50_000_000
+//
+//   [permissions]
+//   allow_read = ["data"]
+//   allow_net = ["api.example.com:443"]
+//   allow_env = true
+//   allow_run = ["git"]
+//
+//   [dependencies]
+//   helpers = "https://example.com/helpers.git"
+//
+// Only the subset of TOML these tables need is understood: sections,
+// comments and `key = value` with strings, integers, booleans and one-line
+// arrays of strings. Limits given on the command line win over the file, and
+// so do permissions: a `[permissions]` table turns the sandbox on unless
+// the command line already did. Imports do not exist yet, so
+// dependencies are read but only reported.
+
+pub const FILE_NAME: &str = "nabeel.toml";
+
+pub struct Manifest {
+    pub entry: String,
+    pub limits: EvaluatorConfig,
+    pub dependencies: Vec<(String, String)>,
+}
+
+enum TomlValue {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<String>),
+}
+
+pub fn find() -> Option<&'static str> {
+    Path::new(FILE_NAME).is_file().then_some(FILE_NAME)
+}
+
+pub fn load(path: &str) -> Result<Manifest, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+    let mut manifest = Manifest { entry: String::new(), limits: EvaluatorConfig::default(), dependencies: Vec::new() };
+    let mut section = String::new();
+    for (number, line) in text.lines().enumerate() {
+        let error = |message: String| format!("{} line {}: {}", path, number + 1, message);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            section = name.trim().to_string();
+            if !matches!(section.as_str(), "project" | "limits" | "permissions" | "dependencies") {
+                return Err(error(format!("Unknown section [{}]", section)));
+            }
+            if section == "permissions" {
+                manifest.limits.permissions.get_or_insert_with(Permissions::default);
+            }
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| error("Expected key = value".to_string()))?;
+        let key = key.trim().trim_matches('"');
+        let value = parse_value(value.trim()).map_err(&error)?;
+        match (section.as_str(), key, value) {
+            // The name is for people reading the file; nothing uses it yet.
+            ("project", "name", TomlValue::String(_)) => {}
+            ("project", "entry", TomlValue::String(entry)) => manifest.entry = entry,
+            ("limits", _, TomlValue::Integer(n)) if n >= 0 => match key {
+                "max_depth" => manifest.limits.max_depth = Some(n as usize),
+                "max_iterations" => manifest.limits.max_loop_iterations = Some(n as u64),
+                "max_string_length" => manifest.limits.max_string_length = Some(n as usize),
+                "max_array_length" => manifest.limits.max_array_length = Some(n as usize),
+                "max_memory" => manifest.limits.max_memory = Some(n as usize),
+                _ => return Err(error(format!("Unknown limit: {}", key))),
+            },
+            ("permissions", _, value) => {
+                let permissions = manifest.limits.permissions.get_or_insert_with(Permissions::default);
+                let grant = match key {
+                    "allow_read" => &mut permissions.read,
+                    "allow_write" => &mut permissions.write,
+                    "allow_net" => &mut permissions.net,
+                    "allow_run" => &mut permissions.run,
+                    "allow_env" => match value {
+                        TomlValue::Boolean(allowed) => {
+                            permissions.env = allowed;
+                            continue;
+                        }
+                        _ => return Err(error("allow_env must be true or false".to_string())),
+                    },
+                    _ => return Err(error(format!("Unknown permission: {}", key))),
+                };
+                *grant = match value {
+                    TomlValue::Boolean(true) => Grant::Everything,
+                    TomlValue::Boolean(false) => Grant::Nothing,
+                    TomlValue::Array(items) if key == "allow_read" || key == "allow_write" => Grant::paths(items),
+                    TomlValue::Array(items) => Grant::Only(items),
+                    _ => return Err(error(format!("{} must be true, false or a list of strings", key))),
+                };
+            }
+            ("dependencies", _, TomlValue::String(source)) => manifest.dependencies.push((key.to_string(), source)),
+            ("", _, _) => return Err(error(format!("{} must be inside a section such as [project]", key))),
+            (section, key, _) => return Err(error(format!("Invalid value for {} in [{}]", key, section))),
+        }
+    }
+    if manifest.entry.is_empty() {
+        return Err(format!("{} does not name an entry file; add entry = \"main.nabeel\" under [project]", path));
+    }
+    Ok(manifest)
+}
+
+// Fills in the limits the command line left unset.
+pub fn merge_limits(limits: &mut EvaluatorConfig, manifest: &EvaluatorConfig) {
+    limits.max_depth = limits.max_depth.or(manifest.max_depth);
+    limits.max_loop_iterations = limits.max_loop_iterations.or(manifest.max_loop_iterations);
+    limits.max_string_length = limits.max_string_length.or(manifest.max_string_length);
+    limits.max_array_length = limits.max_array_length.or(manifest.max_array_length);
+    limits.max_memory = limits.max_memory.or(manifest.max_memory);
+    if limits.permissions.is_none() {
+        limits.permissions = manifest.permissions.clone();
+    }
+}
+
+// A `#` starts a comment unless it is inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(text: &str) -> Result<TomlValue, String> {
+    if let Some(inner) = text.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        return Ok(TomlValue::String(inner.replace("\\\"", "\"").replace("\\\\", "\\")));
+    }
+    match text {
+        "true" => return Ok(TomlValue::Boolean(true)),
+        "false" => return Ok(TomlValue::Boolean(false)),
+        _ => {}
+    }
+    if let Some(inner) = text.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        let mut items = Vec::new();
+        for item in inner.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            match parse_value(item)? {
+                TomlValue::String(item) => items.push(item),
+                _ => return Err(format!("Arrays may only hold strings: {}", text)),
+            }
+        }
+        return Ok(TomlValue::Array(items));
+    }
+    text.replace('_', "").parse().map(TomlValue::Integer).map_err(|_| format!("Invalid value: {}", text))
+}
@@ -0,0 +1,160 @@
+use crate::cli::Options;
+use crate::evaluator::{print_format, Evaluator, Value};
+use crate::json::{self, Json};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::repl::ends_with_expression;
+use std::fs;
+use std::io::{self, Write};
+
+// Cell execution for notebook-style frontends. A script is split into cells
+// at lines starting with `// %%`, and cells run one at a time against a
+// single evaluator, so variables set by one cell are visible to the next.
+//
+// The frontend talks JSON-RPC 2.0, one request per line on stdin and one
+// response per line on stdout. Each request gives the document either as
+// "source" text or as a "path":
+//
+//   cells     {source | path}                 -> [{index, start_line, end_line}]
+//   run_cell  {source | path, index | line}   -> {index, output, value, error}
+//   reset     {}                              -> true, forgetting all variables
+//
+// A failing cell is not a protocol error: its "error" holds the kind,
+// message and line, and "output" what it printed before failing. Cells
+// cannot read stdin, since it carries the requests; prompts and the like
+// raise an "io" error instead.
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+pub struct Cell {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub source: String,
+}
+
+// Each marker starts a new cell that runs to the line before the next one.
+// Text before the first marker forms a cell of its own unless it is blank.
+pub fn split_cells(source: &str) -> Vec<Cell> {
+    let mut cells: Vec<Cell> = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let number = i + 1;
+        match cells.last_mut() {
+            Some(cell) if !line.trim_start().starts_with("// %%") => {
+                cell.end_line = number;
+                cell.source.push_str(line);
+                cell.source.push('\n');
+            }
+            _ => cells.push(Cell { start_line: number, end_line: number, source: format!("{}\n", line) }),
+        }
+    }
+    if cells.len() > 1 && cells[0].source.trim().is_empty() {
+        cells.remove(0);
+    }
+    cells
+}
+
+pub fn run(options: &Options) {
+    let mut evaluator = new_evaluator(options);
+    // Each request is read on its own so stdin is not held locked while a
+    // cell runs.
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match json::parse(&line) {
+            Ok(request) => {
+                let id = request.get("id").cloned().unwrap_or(Json::Null);
+                let params = request.get("params").cloned().unwrap_or(Json::Object(Vec::new()));
+                let result = match request.get("method").and_then(Json::as_str) {
+                    Some("cells") => document(&params).map(|source| cells_result(&source)),
+                    Some("run_cell") => run_cell(&mut evaluator, &params),
+                    Some("reset") => {
+                        evaluator = new_evaluator(options);
+                        Ok(Json::Bool(true))
+                    }
+                    Some(method) => Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+                    None => Err((INVALID_PARAMS, "Request has no method".to_string())),
+                };
+                response(id, result)
+            }
+            Err(e) => response(Json::Null, Err((PARSE_ERROR, e))),
+        };
+        println!("{}", response);
+        let _ = io::stdout().flush();
+    }
+}
+
+fn new_evaluator(options: &Options) -> Evaluator {
+    let mut evaluator = Evaluator::with_config(options.limits.clone());
+    evaluator.set_trace(options.trace);
+    evaluator.capture_output();
+    evaluator
+}
+
+fn response(id: Json, result: Result<Json, (i64, String)>) -> Json {
+    let outcome = match result {
+        Ok(result) => ("result", result),
+        Err((code, message)) => ("error", Json::object(vec![("code", Json::Number(code as f64)), ("message", Json::String(message))])),
+    };
+    Json::object(vec![("jsonrpc", Json::string("2.0")), ("id", id), outcome])
+}
+
+fn document(params: &Json) -> Result<String, (i64, String)> {
+    if let Some(source) = params.get("source").and_then(Json::as_str) {
+        return Ok(source.to_string());
+    }
+    match params.get("path").and_then(Json::as_str) {
+        Some(path) => fs::read_to_string(path).map_err(|e| (INVALID_PARAMS, format!("Cannot read {}: {}", path, e))),
+        None => Err((INVALID_PARAMS, "Expected a \"source\" or \"path\" parameter".to_string())),
+    }
+}
+
+fn cells_result(source: &str) -> Json {
+    let cells = split_cells(source);
+    let cells = cells.iter().enumerate().map(|(index, cell)| {
+        Json::object(vec![
+            ("index", Json::Number(index as f64)),
+            ("start_line", Json::Number(cell.start_line as f64)),
+            ("end_line", Json::Number(cell.end_line as f64)),
+        ])
+    });
+    Json::Array(cells.collect())
+}
+
+fn run_cell(evaluator: &mut Evaluator, params: &Json) -> Result<Json, (i64, String)> {
+    let source = document(params)?;
+    let cells = split_cells(&source);
+    let index = match (params.get("index").and_then(Json::as_usize), params.get("line").and_then(Json::as_usize)) {
+        (Some(index), _) => index,
+        (None, Some(line)) => cells.iter().position(|cell| (cell.start_line..=cell.end_line).contains(&line)).unwrap_or(cells.len()),
+        (None, None) => return Err((INVALID_PARAMS, "Expected an \"index\" or \"line\" parameter".to_string())),
+    };
+    let cell = cells.get(index).ok_or((INVALID_PARAMS, format!("No such cell; the document has {}", cells.len())))?;
+    // Padding with the lines above the cell keeps reported line numbers
+    // relative to the whole document.
+    let padded = format!("{}{}", "\n".repeat(cell.start_line - 1), cell.source);
+    let mut value = Json::Null;
+    let mut error = Json::Null;
+    match Parser::new(Lexer::new(padded)).and_then(|mut parser| parser.parse()) {
+        Ok(ast) => match evaluator.eval(ast.clone()) {
+            Ok(Some(result)) if ends_with_expression(&ast.borrow()) => value = Json::String(print_format(&result)),
+            Ok(_) => {}
+            Err(message) => {
+                if let Value::Error { kind, message, line } = evaluator.caught_error(message) {
+                    error = Json::object(vec![("kind", Json::String(kind)), ("message", Json::String(message)), ("line", Json::Number(line as f64))]);
+                }
+            }
+        },
+        Err(message) => error = Json::object(vec![("kind", Json::string("syntax")), ("message", Json::String(message)), ("line", Json::Null)]),
+    }
+    let output = evaluator.take_output().into_iter().map(Json::String).collect();
+    Ok(Json::object(vec![("index", Json::Number(index as f64)), ("output", Json::Array(output)), ("value", value), ("error", error)]))
+}
@@ -1,45 +1,183 @@
+use crate::builtins::{self, BUILTINS};
+use crate::fuzzy;
+use crate::evaluator::Value;
 use crate::lexer::{Lexer, Token};
 use std::rc::Rc;
 use std::cell::RefCell;
 
+// A `key: value` pair of a map literal.
+pub type MapEntry = (Rc<RefCell<ASTNode>>, Rc<RefCell<ASTNode>>);
+
+// A call argument with the name it was passed by, if any.
+type Argument = (Option<String>, Rc<RefCell<ASTNode>>);
+
 #[derive(Debug)]
 pub enum ASTNode {
     Number(i64),
+    Float(f64),
     StringLiteral(String),
     BinaryOp(Rc<RefCell<ASTNode>>, Token, Rc<RefCell<ASTNode>>),
     Identifier(String),
     Assign(String, Rc<RefCell<ASTNode>>),
+    // `name[i][j] = value`, with the indexes from the outermost in.
+    IndexAssign(String, Vec<Rc<RefCell<ASTNode>>>, Rc<RefCell<ASTNode>>),
     Print(Rc<RefCell<ASTNode>>),
     Program(Vec<Rc<RefCell<ASTNode>>>),
     FunctionCall(String, Vec<Rc<RefCell<ASTNode>>>),
     Boolean(bool),
     Comparison(Rc<RefCell<ASTNode>>, Token, Rc<RefCell<ASTNode>>),
+    // `a < b <= c`: the operands in order and the operators between them.
+    // Each operand is evaluated at most once.
+    ChainedComparison(Vec<Rc<RefCell<ASTNode>>>, Vec<Token>),
     LogicalOp(Rc<RefCell<ASTNode>>, Token, Rc<RefCell<ASTNode>>),
     Not(Rc<RefCell<ASTNode>>),
     Array(Vec<Rc<RefCell<ASTNode>>>),
+    Map(Vec<MapEntry>),
     IndexAccess(Rc<RefCell<ASTNode>>, Rc<RefCell<ASTNode>>),
+    // `target[start:end]`, where either bound may be left out.
+    Slice(Rc<RefCell<ASTNode>>, Option<Rc<RefCell<ASTNode>>>, Option<Rc<RefCell<ASTNode>>>),
     If(Rc<RefCell<ASTNode>>, Vec<Rc<RefCell<ASTNode>>>, Vec<(Rc<RefCell<ASTNode>>, Vec<Rc<RefCell<ASTNode>>>)>, Option<Vec<Rc<RefCell<ASTNode>>>>),
     While(Rc<RefCell<ASTNode>>, Vec<Rc<RefCell<ASTNode>>>),
     For(Rc<RefCell<ASTNode>>, Rc<RefCell<ASTNode>>, Rc<RefCell<ASTNode>>, Vec<Rc<RefCell<ASTNode>>>),
+    ForIn(String, Rc<RefCell<ASTNode>>, Vec<Rc<RefCell<ASTNode>>>),
+    // `repeat count { ... }`.
+    Repeat(Rc<RefCell<ASTNode>>, Vec<Rc<RefCell<ASTNode>>>),
+    // `...items` in an array literal or a call's arguments.
+    Spread(Rc<RefCell<ASTNode>>),
+    // A value already computed, which the evaluator puts in place of spread
+    // arguments and the operands of a chained comparison. Never produced by
+    // parsing.
+    Evaluated(Value),
+    // `[element for name in iterable if condition]`.
+    Comprehension(Rc<RefCell<ASTNode>>, String, Rc<RefCell<ASTNode>>, Option<Rc<RefCell<ASTNode>>>),
+    TryCatch(Vec<Rc<RefCell<ASTNode>>>, String, Vec<Rc<RefCell<ASTNode>>>),
+    // `name: while ...` or `name: for ...`, so `break name;` and
+    // `continue name;` can reach past inner loops.
+    Labeled(String, Rc<RefCell<ASTNode>>),
+    // `break;` or `continue;`, with the label of the loop they apply to.
+    Break(Option<String>),
+    Continue(Option<String>),
+    // Every statement records the line it starts on for runtime diagnostics.
+    Statement(usize, Rc<RefCell<ASTNode>>),
+}
+
+// A `//` comment seen by a parser made with `with_comments`. A trailing
+// comment shares its line with code before it; any other comment stands on
+// a line of its own.
+pub struct Comment {
+    pub line: usize,
+    pub text: String,
+    pub trailing: bool,
 }
 
 pub struct Parser {
     lexer: Lexer,
     current_token: Token,
+    current_line: usize,
+    // Only collected in comment-preserving mode.
+    comments: Option<Vec<Comment>>,
+    // What is being parsed, innermost last, for error messages.
+    context: Vec<String>,
+    // The labels of the loops around the current statement, innermost
+    // last, with `None` for loops without one.
+    loops: Vec<Option<String>>,
 }
 
 impl Parser {
-    pub fn new(mut lexer: Lexer) -> Result<Self, String> {
-        let current_token = lexer.next_token()?;
-        Ok(Parser { lexer, current_token })
+    pub fn new(lexer: Lexer) -> Result<Self, String> {
+        Parser::start(lexer, None)
+    }
+
+    // A parser that keeps the script's comments for tools that must not lose
+    // them, such as `--emit=ast-json`; `take_comments` returns them after
+    // parsing.
+    pub fn with_comments(source: String) -> Result<Self, String> {
+        Parser::start(Lexer::with_comments(source), Some(Vec::new()))
+    }
+
+    fn start(lexer: Lexer, comments: Option<Vec<Comment>>) -> Result<Self, String> {
+        let mut parser = Parser { lexer, current_token: Token::EOF, current_line: 0, comments, context: Vec::new(), loops: Vec::new() };
+        parser.advance()?;
+        Ok(parser)
+    }
+
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        self.comments.take().unwrap_or_default()
+    }
+
+    // Moves to the next token, setting comments aside.
+    fn advance(&mut self) -> Result<(), String> {
+        loop {
+            let token = self.lexer.next_token()?;
+            if let Token::Comment(text) = token {
+                let line = self.lexer.token_line();
+                let trailing = self.current_line == line;
+                if let Some(comments) = &mut self.comments {
+                    comments.push(Comment { line, text, trailing });
+                }
+                continue;
+            }
+            self.current_token = token;
+            self.current_line = self.lexer.token_line();
+            return Ok(());
+        }
+    }
+
+    // The line, column and length in characters of the current token.
+    // After a failed parse this is the token the error is about.
+    pub fn position(&self) -> (usize, usize, usize) {
+        let (start, end) = self.lexer.token_span();
+        (self.lexer.token_line(), self.lexer.token_column(), end.saturating_sub(start))
+    }
+
+    // The line the current token is on, as it appears in the source.
+    pub fn source_line(&self) -> String {
+        self.lexer.token_line_text()
     }
 
     fn eat(&mut self, token: Token) -> Result<(), String> {
         if self.current_token == token {
-            self.current_token = self.lexer.next_token()?;
-            Ok(())
+            self.advance()
         } else {
-            Err(format!("Unexpected token: {:?}, expected: {:?}", self.current_token, token))
+            Err(self.error(format!("Expected {}, found {}", describe(&token), self.found())))
+        }
+    }
+
+    // Runs `parse` with `context` naming what it parses, so errors inside
+    // say "while parsing arguments of `join`" and the like.
+    fn within<T>(&mut self, context: &str, parse: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        self.context.push(context.to_string());
+        let result = parse(self);
+        self.context.pop();
+        result
+    }
+
+    // Adds what was being parsed to `message`.
+    fn error(&self, message: String) -> String {
+        match self.context.last() {
+            Some(context) => format!("{} while parsing {}", message, context),
+            None => message,
+        }
+    }
+
+    // The current token as it reads in the source.
+    fn found(&self) -> String {
+        match self.current_token {
+            Token::EOF => "the end of the file".to_string(),
+            Token::StringLiteral(_) => "a string".to_string(),
+            _ => format!("`{}`", self.lexer.token_text()),
+        }
+    }
+
+    // The token after the current one, read from a copy of the lexer so
+    // nothing is consumed.
+    fn peek_token(&self) -> Result<Token, String> {
+        let mut lexer = self.lexer.clone();
+        loop {
+            match lexer.next_token()? {
+                Token::Comment(_) => continue,
+                token => return Ok(token),
+            }
         }
     }
 
@@ -56,37 +194,59 @@ impl Parser {
     }
 
     fn parse_statement(&mut self) -> Result<Rc<RefCell<ASTNode>>, String> {
+        let line = self.current_line;
+        let statement = self.parse_statement_body()?;
+        Ok(Rc::new(RefCell::new(ASTNode::Statement(line, statement))))
+    }
+
+    fn parse_statement_body(&mut self) -> Result<Rc<RefCell<ASTNode>>, String> {
         match &self.current_token {
-            Token::If => self.parse_if_statement(),
-            Token::Print => {
-                self.eat(Token::Print)?;
-                let expr = self.parse_expression()?;
-                self.eat(Token::Semicolon)?;
+            Token::If => self.within("`if` statement", Self::parse_if_statement),
+            Token::Print => self.within("`print` statement", |parser| {
+                parser.eat(Token::Print)?;
+                let expr = parser.parse_expression()?;
+                parser.eat(Token::Semicolon)?;
                 Ok(Rc::new(RefCell::new(ASTNode::Print(expr))))
-            }
-            Token::Identifier(name) => {
+            }),
+            Token::Identifier(name) if self.peek_token()? == Token::Assign => {
                 let name = name.clone();
-                self.eat(Token::Identifier(name.clone()))?;
-                if self.current_token == Token::Assign {
-                    self.eat(Token::Assign)?;
-                    let expr = self.parse_expression()?;
-                    self.eat(Token::Semicolon)?;
+                self.within(&format!("assignment to `{}`", name), |parser| {
+                    parser.eat(Token::Identifier(name.clone()))?;
+                    parser.eat(Token::Assign)?;
+                    let expr = parser.parse_expression()?;
+                    parser.eat(Token::Semicolon)?;
                     Ok(Rc::new(RefCell::new(ASTNode::Assign(name, expr))))
-                } else {
-                    // If it's not an assignment, treat it as an expression
-                    let expr = self.parse_expression()?;
-                    self.eat(Token::Semicolon)?;
-                    Ok(expr)
-                }
+                })
+            }
+            Token::While => self.within("`while` loop", |parser| parser.parse_loop(None)),
+            Token::Try => self.within("`try` statement", Self::parse_try_statement),
+            Token::For => self.within("`for` loop", |parser| parser.parse_loop(None)),
+            Token::Repeat => self.within("`repeat` loop", |parser| parser.parse_loop(None)),
+            Token::Identifier(name) if self.peek_token()? == Token::Colon => {
+                let name = name.clone();
+                self.within(&format!("loop labeled `{}`", name), |parser| {
+                    parser.eat(Token::Identifier(name.clone()))?;
+                    parser.eat(Token::Colon)?;
+                    let body = parser.parse_loop(Some(name.clone()))?;
+                    Ok(Rc::new(RefCell::new(ASTNode::Labeled(name, body))))
+                })
             }
-            Token::While => self.parse_while_statement(),
-            Token::For => self.parse_for_statement(),
-            _ => {
+            Token::Break | Token::Continue => self.within("statement", Self::parse_loop_control),
+            _ => self.within("statement", |parser| {
                 // For any other token, treat it as an expression
-                let expr = self.parse_expression()?;
-                self.eat(Token::Semicolon)?;
+                let expr = parser.parse_expression()?;
+                if parser.current_token == Token::Assign {
+                    let Some((name, indexes)) = index_target(&expr) else {
+                        return Err(parser.error("Only a variable or an element of one can be assigned to".to_string()));
+                    };
+                    parser.eat(Token::Assign)?;
+                    let value = parser.parse_expression()?;
+                    parser.eat(Token::Semicolon)?;
+                    return Ok(Rc::new(RefCell::new(ASTNode::IndexAssign(name, indexes, value))));
+                }
+                parser.eat(Token::Semicolon)?;
                 Ok(expr)
-            }
+            }),
         }
     }
 
@@ -118,6 +278,9 @@ impl Parser {
     fn parse_block(&mut self) -> Result<Vec<Rc<RefCell<ASTNode>>>, String> {
         let mut statements = Vec::new();
         while self.current_token != Token::RBrace {
+            if self.current_token == Token::EOF {
+                return Err(self.error("Expected '}' before the end of the file".to_string()));
+            }
             statements.push(self.parse_statement()?);
         }
         self.eat(Token::RBrace)?;
@@ -168,16 +331,25 @@ impl Parser {
     }
 
     fn parse_comparison(&mut self) -> Result<Rc<RefCell<ASTNode>>, String> {
-        let mut node = self.parse_term()?;
+        let mut operands = vec![self.parse_term()?];
+        let mut operators = Vec::new();
 
+        // Chained comparisons like `0 <= x < 10` mean `0 <= x && x < 10`,
+        // except that `x` is only evaluated once.
         while matches!(self.current_token, Token::Lt | Token::Gt | Token::LtEq | Token::GtEq) {
-            let op = self.current_token.clone();
+            operators.push(self.current_token.clone());
             self.eat(self.current_token.clone())?;
-            let right = self.parse_term()?;
-            node = Rc::new(RefCell::new(ASTNode::Comparison(node, op, right)));
+            operands.push(self.parse_term()?);
         }
 
-        Ok(node)
+        Ok(match operators.len() {
+            0 => operands.pop().unwrap(),
+            1 => {
+                let right = operands.pop().unwrap();
+                Rc::new(RefCell::new(ASTNode::Comparison(operands.pop().unwrap(), operators.pop().unwrap(), right)))
+            }
+            _ => Rc::new(RefCell::new(ASTNode::ChainedComparison(operands, operators))),
+        })
     }
 
     fn parse_term(&mut self) -> Result<Rc<RefCell<ASTNode>>, String> {
@@ -211,6 +383,17 @@ impl Parser {
             self.eat(Token::Not)?;
             let expr = self.parse_unary()?;
             Ok(Rc::new(RefCell::new(ASTNode::Not(expr))))
+        } else if self.current_token == Token::Minus {
+            // A negative literal such as `-1` is a literal; negating anything
+            // else is `0 - x`, which follows each numeric type's own rules.
+            self.eat(Token::Minus)?;
+            let expr = self.parse_unary()?;
+            let negated = match &*expr.borrow() {
+                ASTNode::Number(n) => ASTNode::Number(-n),
+                ASTNode::Float(n) => ASTNode::Float(-n),
+                _ => ASTNode::BinaryOp(Rc::new(RefCell::new(ASTNode::Number(0))), Token::Minus, Rc::clone(&expr)),
+            };
+            Ok(Rc::new(RefCell::new(negated)))
         } else {
             self.parse_postfix()
         }
@@ -223,9 +406,16 @@ impl Parser {
             match &self.current_token {
                 Token::LBracket => {
                     self.eat(Token::LBracket)?;
-                    let index = self.parse_expression()?;
+                    let start = if self.current_token == Token::Colon { None } else { Some(self.parse_expression()?) };
+                    node = match start {
+                        Some(index) if self.current_token != Token::Colon => Rc::new(RefCell::new(ASTNode::IndexAccess(node, index))),
+                        start => {
+                            self.eat(Token::Colon)?;
+                            let end = if self.current_token == Token::RBracket { None } else { Some(self.parse_expression()?) };
+                            Rc::new(RefCell::new(ASTNode::Slice(node, start, end)))
+                        }
+                    };
                     self.eat(Token::RBracket)?;
-                    node = Rc::new(RefCell::new(ASTNode::IndexAccess(node, index)));
                 }
                 _ => break,
             }
@@ -241,6 +431,11 @@ impl Parser {
                 self.eat(Token::Number(value))?;
                 Ok(Rc::new(RefCell::new(ASTNode::Number(value))))
             }
+            Token::Float(n) => {
+                let value = *n;
+                self.eat(Token::Float(value))?;
+                Ok(Rc::new(RefCell::new(ASTNode::Float(value))))
+            }
             Token::StringLiteral(s) => {
                 let value = s.clone();
                 self.eat(Token::StringLiteral(value.clone()))?;
@@ -257,6 +452,20 @@ impl Parser {
             Token::Identifier(name) => {
                 let value = name.clone();
                 self.eat(Token::Identifier(value.clone()))?;
+                // Builtin names are ordinary identifiers, so `set` can be a
+                // variable and `set(items)` a call. Every function is a
+                // builtin, so a call to any other name is most likely a
+                // misspelling of one.
+                if self.current_token == Token::LParen {
+                    if builtins::find(&value).is_some() {
+                        return self.parse_call(&value);
+                    }
+                    let mut message = self.error(format!("Unknown function `{}`", value));
+                    if let Some(similar) = fuzzy::suggest(&value, BUILTINS.iter().map(|builtin| builtin.name)) {
+                        message.push_str(&format!("; did you mean `{}`?", similar));
+                    }
+                    return Err(message);
+                }
                 Ok(Rc::new(RefCell::new(ASTNode::Identifier(value))))
             }
             Token::LParen => {
@@ -265,75 +474,173 @@ impl Parser {
                 self.eat(Token::RParen)?;
                 Ok(expr)
             }
-            Token::Join | Token::Split | Token::Count |
-            Token::Length | Token::Uppercase | Token::Lowercase | Token::Trim | Token::Replace |
-            Token::Push | Token::Pop | Token::First | Token::Last => {
-                let func_name = match &self.current_token {
-                    Token::Join => "join",
-                    Token::Split => "split",
-                    Token::Count => "count",
-                    Token::Length => "length",
-                    Token::Uppercase => "uppercase",
-                    Token::Lowercase => "lowercase",
-                    Token::Trim => "trim",
-                    Token::Replace => "replace",
-                    Token::Push => "push",
-                    Token::Pop => "pop",
-                    Token::First => "first",
-                    Token::Last => "last",
-                    _ => unreachable!(),
-                };
-                self.eat(self.current_token.clone())?;
-                self.eat(Token::LParen)?;
-                let mut args = Vec::new();
-                if self.current_token != Token::RParen {
-                    args.push(self.parse_expression()?);
-                    while self.current_token == Token::Comma {
-                        self.eat(Token::Comma)?;
-                        args.push(self.parse_expression()?);
-                    }
-                }
-                self.eat(Token::RParen)?;
-                Ok(Rc::new(RefCell::new(ASTNode::FunctionCall(func_name.to_string(), args))))
-            }
             Token::LBracket => {
                 self.eat(Token::LBracket)?;
-                let mut elements = Vec::new();
-                if self.current_token != Token::RBracket {
-                    elements.push(self.parse_expression()?);
-                    while self.current_token == Token::Comma {
-                        self.eat(Token::Comma)?;
-                        elements.push(self.parse_expression()?);
-                    }
+                self.within("array literal", Self::parse_array)
+            }
+            Token::LBrace => {
+                self.eat(Token::LBrace)?;
+                let entries = self.within("map literal", |parser| parser.parse_list(Vec::new(), Token::RBrace, "map entry", Self::parse_map_entry))?;
+                Ok(Rc::new(RefCell::new(ASTNode::Map(entries))))
+            }
+            _ => Err(self.error(format!("Expected an expression, found {}", self.found()))),
+        }
+    }
+
+    // The arguments of a builtin whose name has just been read.
+    fn parse_call(&mut self, name: &str) -> Result<Rc<RefCell<ASTNode>>, String> {
+        self.eat(Token::LParen)?;
+        let args = self.within(&format!("arguments of `{}`", name), |parser| {
+            let args = parser.parse_list(Vec::new(), Token::RParen, "argument", Self::parse_argument)?;
+            parser.place_arguments(name, args)
+        })?;
+        Ok(Rc::new(RefCell::new(ASTNode::FunctionCall(name.to_string(), args))))
+    }
+
+    // An argument, optionally named as in `limit: 2`.
+    fn parse_argument(&mut self) -> Result<Argument, String> {
+        if let Token::Identifier(name) = &self.current_token {
+            if self.peek_token()? == Token::Colon {
+                let name = name.clone();
+                self.eat(Token::Identifier(name.clone()))?;
+                self.eat(Token::Colon)?;
+                return Ok((Some(name), self.parse_expression()?));
+            }
+        }
+        Ok((None, self.parse_element()?))
+    }
+
+    // An array element or call argument, which may be spread with `...`.
+    fn parse_element(&mut self) -> Result<Rc<RefCell<ASTNode>>, String> {
+        if self.current_token != Token::Spread {
+            return self.parse_expression();
+        }
+        self.eat(Token::Spread)?;
+        let items = self.parse_expression()?;
+        Ok(Rc::new(RefCell::new(ASTNode::Spread(items))))
+    }
+
+    // Puts named arguments in the positions the builtin's signature gives
+    // their names, so the evaluator only ever sees positional arguments.
+    fn place_arguments(&self, name: &str, args: Vec<Argument>) -> Result<Vec<Rc<RefCell<ASTNode>>>, String> {
+        let Some(builtin) = builtins::find(name) else {
+            return Ok(args.into_iter().map(|(_, arg)| arg).collect());
+        };
+        let parameters = builtin.parameters();
+        let mut placed = Vec::new();
+        let mut named = false;
+        let mut spread = false;
+        for (parameter, arg) in args {
+            let Some(parameter) = parameter else {
+                if named {
+                    return Err(self.error(format!("Arguments of {} passed by position must come before named ones", name)));
                 }
-                self.eat(Token::RBracket)?;
-                Ok(Rc::new(RefCell::new(ASTNode::Array(elements))))
+                spread |= matches!(*arg.borrow(), ASTNode::Spread(_));
+                placed.push(Some(arg));
+                continue;
+            };
+            if spread {
+                return Err(self.error(format!("Arguments of {} cannot be named after a spread argument, whose length is not known", name)));
             }
-            Token::ReadFile | Token::WriteFile => {
-                let func_name = match &self.current_token {
-                    Token::ReadFile => "read_file",
-                    Token::WriteFile => "write_file",
-                    _ => unreachable!(),
-                };
-                self.eat(self.current_token.clone())?;
-                self.eat(Token::LParen)?;
-                let mut args = Vec::new();
-                if self.current_token != Token::RParen {
-                    args.push(self.parse_expression()?);
-                    while self.current_token == Token::Comma {
-                        self.eat(Token::Comma)?;
-                        args.push(self.parse_expression()?);
-                    }
+            named = true;
+            let Some(index) = parameters.iter().position(|known| *known == parameter) else {
+                return Err(self.error(format!("{} has no argument named `{}`; expected {}", name, parameter, builtin.signature)));
+            };
+            if placed.get(index).is_some_and(Option::is_some) {
+                return Err(self.error(format!("Argument `{}` of {} is given twice", parameter, name)));
+            }
+            if placed.len() <= index {
+                placed.resize(index + 1, None);
+            }
+            placed[index] = Some(arg);
+        }
+        placed
+            .into_iter()
+            .enumerate()
+            .map(|(index, arg)| arg.ok_or_else(|| self.error(format!("Argument `{}` of {} is missing before the named ones; expected {}", parameters[index], name, builtin.signature))))
+            .collect()
+    }
+
+    // Items separated by commas up to `close`, which is consumed, following
+    // any `items` the caller has already read. A comma after the last item
+    // is allowed so multi-line literals can end every line with one; a
+    // missing or doubled comma gets its own message.
+    fn parse_list<T>(&mut self, mut items: Vec<T>, close: Token, item: &str, mut parse_item: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let closing = match close {
+            Token::RParen => "')'",
+            Token::RBracket => "']'",
+            _ => "'}'",
+        };
+        loop {
+            if !items.is_empty() {
+                match self.current_token {
+                    Token::Comma => self.advance()?,
+                    ref token if *token == close => break,
+                    _ => return Err(self.error(format!("Expected ',' or {} after {}, found {}", closing, item, self.found()))),
                 }
-                self.eat(Token::RParen)?;
-                Ok(Rc::new(RefCell::new(ASTNode::FunctionCall(func_name.to_string(), args))))
             }
-            _ => Err(format!("Unexpected token: {:?}", self.current_token)),
+            match self.current_token {
+                ref token if *token == close => break,
+                Token::Comma if items.is_empty() => return Err(self.error(format!("Expected {} or {} before ','", item, closing))),
+                Token::Comma => return Err(self.error(format!("Expected {} or {} after ',', found another ','", item, closing))),
+                Token::EOF => return Err(self.error(format!("Expected {} before the end of the file", closing))),
+                _ => {}
+            }
+            items.push(parse_item(self)?);
+        }
+        self.eat(close)?;
+        Ok(items)
+    }
+
+    // The rest of an array literal after its '[': either elements, or one
+    // expression and a `for` clause making a comprehension such as
+    // `[x * 2 for x in items if x > 0]`.
+    fn parse_array(&mut self) -> Result<Rc<RefCell<ASTNode>>, String> {
+        if self.current_token == Token::RBracket {
+            self.eat(Token::RBracket)?;
+            return Ok(Rc::new(RefCell::new(ASTNode::Array(Vec::new()))));
         }
+        let first = self.parse_element()?;
+        if self.current_token != Token::For {
+            let elements = self.parse_list(vec![first], Token::RBracket, "array element", Self::parse_element)?;
+            return Ok(Rc::new(RefCell::new(ASTNode::Array(elements))));
+        }
+        self.eat(Token::For)?;
+        let name = match &self.current_token {
+            Token::Identifier(name) => name.clone(),
+            _ => return Err(self.error(format!("Expected a variable name after `for`, found {}", self.found()))),
+        };
+        self.eat(Token::Identifier(name.clone()))?;
+        self.eat(Token::In)?;
+        let iterable = self.parse_expression()?;
+        let condition = if self.current_token == Token::If {
+            self.eat(Token::If)?;
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+        self.eat(Token::RBracket)?;
+        Ok(Rc::new(RefCell::new(ASTNode::Comprehension(first, name, iterable, condition))))
+    }
+
+    fn parse_map_entry(&mut self) -> Result<MapEntry, String> {
+        let key = self.parse_expression()?;
+        self.eat(Token::Colon)?;
+        let value = self.parse_expression()?;
+        Ok((key, value))
     }
 
     fn parse_for_statement(&mut self) -> Result<Rc<RefCell<ASTNode>>, String> {
         self.eat(Token::For)?;
+        if let Token::Identifier(name) = &self.current_token {
+            let name = name.clone();
+            self.eat(Token::Identifier(name.clone()))?;
+            self.eat(Token::In)?;
+            let iterable = self.parse_expression()?;
+            self.eat(Token::LBrace)?;
+            let block = self.parse_block()?;
+            return Ok(Rc::new(RefCell::new(ASTNode::ForIn(name, iterable, block))));
+        }
         self.eat(Token::LParen)?;
         let init = self.parse_for_expression()?;
         self.eat(Token::Semicolon)?;
@@ -359,6 +666,68 @@ impl Parser {
         self.parse_expression()
     }
 
+    fn parse_try_statement(&mut self) -> Result<Rc<RefCell<ASTNode>>, String> {
+        self.eat(Token::Try)?;
+        self.eat(Token::LBrace)?;
+        let try_block = self.parse_block()?;
+        self.eat(Token::Catch)?;
+        let name = match &self.current_token {
+            Token::Identifier(name) => name.clone(),
+            _ => return Err(self.error(format!("Expected a variable name after `catch`, found {}", self.found()))),
+        };
+        self.eat(Token::Identifier(name.clone()))?;
+        self.eat(Token::LBrace)?;
+        let catch_block = self.parse_block()?;
+        Ok(Rc::new(RefCell::new(ASTNode::TryCatch(try_block, name, catch_block))))
+    }
+
+    // A `while`, `for` or `repeat` loop, keeping track of it so `break` and
+    // `continue` inside can be checked.
+    fn parse_loop(&mut self, label: Option<String>) -> Result<Rc<RefCell<ASTNode>>, String> {
+        if label.is_some() && self.loops.contains(&label) {
+            return Err(self.error(format!("The label `{}` is already used by an enclosing loop", label.unwrap_or_default())));
+        }
+        let parse = match self.current_token {
+            Token::While => Self::parse_while_statement,
+            Token::For => Self::parse_for_statement,
+            Token::Repeat => Self::parse_repeat_statement,
+            _ => return Err(self.error(format!("Expected a loop after the label, found {}", self.found()))),
+        };
+        self.loops.push(label);
+        let result = parse(self);
+        self.loops.pop();
+        result
+    }
+
+    fn parse_loop_control(&mut self) -> Result<Rc<RefCell<ASTNode>>, String> {
+        let (keyword, is_break) = if self.current_token == Token::Break { ("break", true) } else { ("continue", false) };
+        if self.loops.is_empty() {
+            return Err(self.error(format!("`{}` can only be used inside a loop", keyword)));
+        }
+        self.eat(self.current_token.clone())?;
+        let label = match &self.current_token {
+            Token::Identifier(name) => Some(name.clone()),
+            _ => None,
+        };
+        if let Some(name) = &label {
+            if !self.loops.contains(&label) {
+                return Err(self.error(format!("No loop around this `{}` is labeled `{}`", keyword, name)));
+            }
+            self.eat(Token::Identifier(name.clone()))?;
+        }
+        self.eat(Token::Semicolon)?;
+        let node = if is_break { ASTNode::Break(label) } else { ASTNode::Continue(label) };
+        Ok(Rc::new(RefCell::new(node)))
+    }
+
+    fn parse_repeat_statement(&mut self) -> Result<Rc<RefCell<ASTNode>>, String> {
+        self.eat(Token::Repeat)?;
+        let count = self.parse_expression()?;
+        self.eat(Token::LBrace)?;
+        let block = self.parse_block()?;
+        Ok(Rc::new(RefCell::new(ASTNode::Repeat(count, block))))
+    }
+
     fn parse_while_statement(&mut self) -> Result<Rc<RefCell<ASTNode>>, String> {
         self.eat(Token::While)?;
         let condition = self.parse_expression()?;
@@ -366,4 +735,42 @@ impl Parser {
         let block = self.parse_block()?;
         Ok(Rc::new(RefCell::new(ASTNode::While(condition, block))))
     }
-}
\ No newline at end of file
+}
+
+// The variable and indexes of an element being assigned to, such as
+// `grid[i][j]`.
+fn index_target(node: &Rc<RefCell<ASTNode>>) -> Option<(String, Vec<Rc<RefCell<ASTNode>>>)> {
+    match &*node.borrow() {
+        ASTNode::IndexAccess(target, index) => {
+            let (name, mut indexes) = match &*target.borrow() {
+                ASTNode::Identifier(name) => (name.clone(), Vec::new()),
+                _ => index_target(target)?,
+            };
+            indexes.push(Rc::clone(index));
+            Some((name, indexes))
+        }
+        _ => None,
+    }
+}
+
+// A token the parser wanted, as it would be written in the source.
+fn describe(token: &Token) -> String {
+    match token {
+        Token::LParen => "'('".to_string(),
+        Token::RParen => "')'".to_string(),
+        Token::LBrace => "'{'".to_string(),
+        Token::RBrace => "'}'".to_string(),
+        Token::LBracket => "'['".to_string(),
+        Token::RBracket => "']'".to_string(),
+        Token::Semicolon => "';'".to_string(),
+        Token::Colon => "':'".to_string(),
+        Token::Comma => "','".to_string(),
+        Token::Assign => "'='".to_string(),
+        Token::In => "`in`".to_string(),
+        Token::Catch => "`catch`".to_string(),
+        Token::While => "`while`".to_string(),
+        Token::For => "`for`".to_string(),
+        Token::Identifier(_) => "a name".to_string(),
+        token => format!("{:?}", token),
+    }
+}
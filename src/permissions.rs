@@ -0,0 +1,167 @@
+use std::path::{Component, Path, PathBuf};
+
+// Capability checks behind `--allow-read`, `--allow-write`, `--allow-net`,
+// `--allow-env` and `--allow-run`. Giving any of these flags, or `--sandbox`,
+// turns the sandbox on: from then on each file, network, environment and
+// process builtin needs the matching permission, and everything not granted
+// is refused with a "permission_denied" error. Without any of them scripts
+// run unrestricted, as they always have.
+//
+// Path grants cover everything below them. Paths are compared after
+// resolving `.`, `..` and symbolic links as far as they exist, so a link
+// inside an allowed directory cannot lead a script out of it. Granted paths
+// are resolved once, when they are given, so a script that changes its
+// working directory does not take a relative grant such as `.` with it.
+
+#[derive(Clone, Debug, Default)]
+pub enum Grant {
+    #[default]
+    Nothing,
+    Everything,
+    Only(Vec<String>),
+}
+
+impl Grant {
+    // `--allow-read` alone grants everything; `--allow-read=a,b` grants a list.
+    // Repeating a flag adds to the list.
+    pub fn add(&mut self, list: Option<&str>) {
+        let items: Vec<String> = match list {
+            Some(list) => list.split(',').filter(|item| !item.is_empty()).map(str::to_string).collect(),
+            None => {
+                *self = Grant::Everything;
+                return;
+            }
+        };
+        match self {
+            Grant::Everything => {}
+            Grant::Only(existing) => existing.extend(items),
+            Grant::Nothing => *self = Grant::Only(items),
+        }
+    }
+
+    // Like `add`, for `--allow-read` and `--allow-write`.
+    pub fn add_paths(&mut self, list: Option<&str>) {
+        self.add(list);
+        self.resolve_paths();
+    }
+
+    // A list of directories from the manifest.
+    pub fn paths(roots: Vec<String>) -> Grant {
+        let mut grant = Grant::Only(roots);
+        grant.resolve_paths();
+        grant
+    }
+
+    fn resolve_paths(&mut self) {
+        if let Grant::Only(roots) = self {
+            for root in roots {
+                *root = resolve(Path::new(root)).to_string_lossy().into_owned();
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Permissions {
+    pub read: Grant,
+    pub write: Grant,
+    pub net: Grant,
+    pub env: bool,
+    pub run: Grant,
+}
+
+impl Permissions {
+    pub fn check_read(&self, path: &str) -> Result<(), String> {
+        check_path(&self.read, path, "Reading", "--allow-read")
+    }
+
+    pub fn check_write(&self, path: &str) -> Result<(), String> {
+        check_path(&self.write, path, "Writing", "--allow-write")
+    }
+
+    // Hosts may be granted alone, allowing any port, or as host:port.
+    pub fn check_net(&self, host: &str, port: u16) -> Result<(), String> {
+        let allowed = match &self.net {
+            Grant::Nothing => false,
+            Grant::Everything => true,
+            Grant::Only(hosts) => hosts.iter().any(|granted| granted == host || *granted == format!("{}:{}", host, port)),
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(format!("Connecting to {}:{} requires --allow-net", host, port))
+        }
+    }
+
+    // Programs are granted by the name or path they are started with.
+    pub fn check_run(&self, program: &str) -> Result<(), String> {
+        let allowed = match &self.run {
+            Grant::Nothing => false,
+            Grant::Everything => true,
+            Grant::Only(programs) => programs.iter().any(|granted| granted == program),
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(format!("Running {} requires --allow-run", program))
+        }
+    }
+
+    pub fn check_env(&self) -> Result<(), String> {
+        if self.env {
+            Ok(())
+        } else {
+            Err("Accessing environment variables requires --allow-env".to_string())
+        }
+    }
+}
+
+fn check_path(grant: &Grant, path: &str, action: &str, flag: &str) -> Result<(), String> {
+    let allowed = match grant {
+        Grant::Nothing => false,
+        Grant::Everything => true,
+        Grant::Only(roots) => {
+            let target = resolve(Path::new(path));
+            roots.iter().any(|root| target.starts_with(root))
+        }
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err(format!("{} {} requires {}", action, path, flag))
+    }
+}
+
+// Makes the path absolute, resolves symbolic links in the longest prefix
+// that exists, then removes `.` and `..` from the part that does not.
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut existing = absolute.as_path();
+    let mut rest = Vec::new();
+    let mut resolved = loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            break canonical;
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => break existing.to_path_buf(),
+        }
+    };
+    for name in rest.iter().rev() {
+        resolved.push(name);
+    }
+    let mut normalized = PathBuf::new();
+    for component in resolved.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,104 @@
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+// Progress display behind the `progress_*` builtins. It redraws a single
+// stderr line with a bar, the count, the rate and an estimate of the time
+// left. With a total of 0 the amount of work is unknown and a spinner is
+// shown instead of a bar. When stderr is not a terminal only the final
+// summary is written, so logs are not filled with redraws.
+
+const BAR_WIDTH: usize = 30;
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct Progress {
+    label: String,
+    total: u64,
+    done: u64,
+    started: Instant,
+    last_drawn: Option<Instant>,
+    finished: bool,
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.total {
+            0 => write!(f, "<progress {}>", self.done),
+            total => write!(f, "<progress {}/{}>", self.done, total),
+        }
+    }
+}
+
+impl Progress {
+    pub fn new(label: String, total: u64) -> Progress {
+        let mut progress = Progress { label, total, done: 0, started: Instant::now(), last_drawn: None, finished: false };
+        progress.draw(false);
+        progress
+    }
+
+    pub fn tick(&mut self, amount: u64) -> Result<(), String> {
+        if self.finished {
+            return Err("Progress has already finished".to_string());
+        }
+        self.done = self.done.saturating_add(amount);
+        // Redraws are throttled so a fast loop is not slowed down by output.
+        if self.last_drawn.is_none_or(|drawn| drawn.elapsed() >= REDRAW_INTERVAL) {
+            self.draw(false);
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) {
+        if !self.finished {
+            self.finished = true;
+            self.draw(true);
+        }
+    }
+
+    fn draw(&mut self, last: bool) {
+        let terminal = io::stderr().is_terminal();
+        if !terminal && !last {
+            return;
+        }
+        self.last_drawn = Some(Instant::now());
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { self.done as f64 / elapsed } else { 0.0 };
+        let mut line = String::new();
+        if !self.label.is_empty() {
+            line.push_str(&self.label);
+            line.push(' ');
+        }
+        if self.total == 0 {
+            if !last {
+                line.push(SPINNER[(elapsed * 10.0) as usize % SPINNER.len()]);
+                line.push(' ');
+            }
+            line.push_str(&format!("{} done, {:.1}/s", self.done, rate));
+        } else {
+            let fraction = (self.done as f64 / self.total as f64).min(1.0);
+            let filled = (fraction * BAR_WIDTH as f64) as usize;
+            line.push_str(&format!("[{}{}] {}/{} {:.1}/s", "#".repeat(filled), ".".repeat(BAR_WIDTH - filled), self.done, self.total, rate));
+            if last {
+                line.push_str(&format!(" in {}", format_seconds(elapsed)));
+            } else if rate > 0.0 && self.done < self.total {
+                line.push_str(&format!(" ETA {}", format_seconds((self.total - self.done) as f64 / rate)));
+            }
+        }
+        let mut stderr = io::stderr();
+        let prefix = if terminal { "\r\x1b[2K" } else { "" };
+        let suffix = if last { "\n" } else { "" };
+        let _ = write!(stderr, "{}{}{}", prefix, line, suffix).and_then(|_| stderr.flush());
+    }
+}
+
+fn format_seconds(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    if seconds >= 3600 {
+        format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60)
+    } else if seconds >= 60 {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
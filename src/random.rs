@@ -0,0 +1,94 @@
+use std::io;
+
+// Randomness for `uuid`, `random_hex` and `random_token`. Normally bytes
+// come straight from the operating system's generator, /dev/urandom on Unix
+// and BCryptGenRandom on Windows, so the results are fit for identifiers and
+// secrets that must not be guessable. Running with `--seed` switches to a
+// seeded generator instead, which gives the same results on every run for
+// tests and caching, and must never be used for secrets.
+
+// The most bytes or characters one call may ask for, so a typo cannot
+// allocate gigabytes when no string length limit is set.
+pub const MAX_LENGTH: usize = 1 << 20;
+
+const TOKEN_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+pub enum Source {
+    System,
+    // SplitMix64 state.
+    Seeded(u64),
+}
+
+impl Source {
+    pub fn new(seed: Option<u64>) -> Source {
+        seed.map_or(Source::System, Source::Seeded)
+    }
+
+    pub fn bytes(&mut self, count: usize) -> io::Result<Vec<u8>> {
+        let mut buffer = vec![0u8; count];
+        match self {
+            Source::System => system_bytes(&mut buffer)?,
+            Source::Seeded(state) => {
+                for chunk in buffer.chunks_mut(8) {
+                    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+                    let mut z = *state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+                    z ^= z >> 31;
+                    chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+                }
+            }
+        }
+        Ok(buffer)
+    }
+
+    pub fn hex(&mut self, count: usize) -> io::Result<String> {
+        Ok(self.bytes(count)?.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    // URL-safe characters, each carrying six random bits. The alphabet has
+    // exactly 64 entries, so masking a byte picks every character equally often.
+    pub fn token(&mut self, length: usize) -> io::Result<String> {
+        Ok(self.bytes(length)?.iter().map(|byte| TOKEN_ALPHABET[(byte & 63) as usize] as char).collect())
+    }
+
+    // A version 4 UUID in the usual 8-4-4-4-12 form.
+    pub fn uuid(&mut self) -> io::Result<String> {
+        let mut raw = self.bytes(16)?;
+        raw[6] = raw[6] & 0x0f | 0x40;
+        raw[8] = raw[8] & 0x3f | 0x80;
+        let hex: String = raw.iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32]))
+    }
+}
+
+#[cfg(unix)]
+fn system_bytes(buffer: &mut [u8]) -> io::Result<()> {
+    use std::io::Read;
+    std::fs::File::open("/dev/urandom")?.read_exact(buffer)
+}
+
+#[cfg(windows)]
+fn system_bytes(buffer: &mut [u8]) -> io::Result<()> {
+    // Asks for the system's preferred generator, so no algorithm handle is
+    // needed.
+    const BCRYPT_USE_SYSTEM_PREFERRED_RNG: u32 = 2;
+    #[link(name = "bcrypt")]
+    unsafe extern "system" {
+        fn BCryptGenRandom(algorithm: *mut std::ffi::c_void, buffer: *mut u8, length: u32, flags: u32) -> i32;
+    }
+    for chunk in buffer.chunks_mut(u32::MAX as usize) {
+        // SAFETY: the pointer and length describe `chunk`, which is valid
+        // for writes for the whole call.
+        let status = unsafe { BCryptGenRandom(std::ptr::null_mut(), chunk.as_mut_ptr(), chunk.len() as u32, BCRYPT_USE_SYSTEM_PREFERRED_RNG) };
+        if status != 0 {
+            return Err(io::Error::other(format!("BCryptGenRandom failed with status {:#x}", status)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn system_bytes(_: &mut [u8]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "No secure random generator on this platform; run with --seed for repeatable, insecure values"))
+}
@@ -0,0 +1,599 @@
+use std::fmt;
+
+// A small backtracking regular expression engine used by the regex builtins.
+// Supported syntax: literals, `.`, character classes (`[a-z]`, `[^0-9]`),
+// the escapes `\d \w \s \D \W \S \b`, anchors `^` and `$`, groups `(...)`
+// and `(?:...)`, alternation `|`, and the quantifiers `* + ? {n} {n,} {n,m}`
+// with an optional trailing `?` for lazy matching.
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Vec<ClassItem>, bool),
+    Start,
+    End,
+    WordBoundary,
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat(Box<Node>, usize, Option<usize>, bool),
+}
+
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+pub struct Regex {
+    pattern: String,
+    program: Vec<Inst>,
+    loops: usize,
+    group_count: usize,
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "regex({:?})", self.pattern)
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let mut parser = RegexParser {
+            chars: pattern.chars().collect(),
+            position: 0,
+            group_count: 0,
+        };
+        let root = parser.parse_alternation()?;
+        if parser.position < parser.chars.len() {
+            return Err(format!("Unmatched ')' in regex: {}", pattern));
+        }
+        let mut compiler = Compiler { program: Vec::new(), loops: 0 };
+        compiler.compile(&root);
+        compiler.program.push(Inst::Match);
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            program: compiler.program,
+            loops: compiler.loops,
+            group_count: parser.group_count,
+        })
+    }
+
+    // Finds the leftmost match starting at or after `start`, returning the
+    // span of the whole match in slot 0 followed by each capture group.
+    pub fn captures_at(&self, text: &[char], start: usize) -> Option<Captures> {
+        let slot_count = 2 * (self.group_count + 1);
+        (start..=text.len()).find_map(|begin| {
+            let mut slots = run(&self.program, self.loops, slot_count, text, begin)?;
+            slots[0] = Some(begin);
+            Some(slots.chunks(2).map(|pair| pair[0].zip(pair[1])).collect())
+        })
+    }
+
+    // Iterates over non-overlapping matches from left to right. Empty matches
+    // advance by one character so the search always makes progress.
+    pub fn captures_all(&self, text: &[char]) -> Vec<Captures> {
+        let mut matches = Vec::new();
+        let mut position = 0;
+        while position <= text.len() {
+            let captures = match self.captures_at(text, position) {
+                Some(captures) => captures,
+                None => break,
+            };
+            let (start, end) = captures[0].unwrap();
+            position = if end == start { end + 1 } else { end };
+            matches.push(captures);
+        }
+        matches
+    }
+
+    pub fn split(&self, text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut pieces = Vec::new();
+        let mut last = 0;
+        for captures in self.captures_all(&chars) {
+            let (start, end) = captures[0].unwrap();
+            if start == end {
+                continue;
+            }
+            pieces.push(chars[last..start].iter().collect());
+            last = end;
+        }
+        pieces.push(chars[last..].iter().collect());
+        pieces
+    }
+
+    // Replaces up to `limit` matches (all of them when `None`). In the
+    // replacement, `$0` is the whole match, `$1`..`$n` are capture groups
+    // and `$$` is a literal dollar sign.
+    pub fn replace(&self, text: &str, replacement: &str, limit: Option<usize>) -> Result<String, String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut result = String::new();
+        let mut last = 0;
+        let matches = self.captures_all(&chars);
+        for captures in matches.iter().take(limit.unwrap_or(matches.len())) {
+            let (start, end) = captures[0].unwrap();
+            result.extend(&chars[last..start]);
+            self.expand(replacement, &chars, captures, &mut result)?;
+            last = end;
+        }
+        result.extend(&chars[last..]);
+        Ok(result)
+    }
+
+    fn expand(&self, replacement: &str, text: &[char], captures: &Captures, out: &mut String) -> Result<(), String> {
+        let mut chars = replacement.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                out.push(c);
+                continue;
+            }
+            if chars.peek() == Some(&'$') {
+                chars.next();
+                out.push('$');
+                continue;
+            }
+            let mut digits = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                digits.push(d);
+                chars.next();
+            }
+            if digits.is_empty() {
+                out.push('$');
+                continue;
+            }
+            let group: usize = digits.parse().map_err(|_| format!("Invalid group reference ${}", digits))?;
+            if group > self.group_count {
+                return Err(format!("Invalid group reference ${}: the regex has {} groups", group, self.group_count));
+            }
+            if let Some((start, end)) = captures[group] {
+                out.extend(&text[start..end]);
+            }
+        }
+        Ok(())
+    }
+}
+
+// The pattern compiled to instructions for a backtracking matcher. Choices
+// still to try are kept on an explicit stack rather than the call stack, so
+// long inputs cannot overflow it however many times a group repeats.
+#[derive(Debug)]
+enum Inst {
+    // Consumes one character matching the node: a char, `.` or a class.
+    One(Node),
+    // Consumes `min` to `max` characters matching the node.
+    Many(Node, usize, Option<usize>, bool),
+    Start,
+    End,
+    WordBoundary,
+    // Records the current position in a capture slot.
+    Save(usize),
+    // Continues at the first target, backtracking to the second.
+    Split(usize, usize),
+    Jump(usize),
+    // Starts a repetition by resetting its counter.
+    Enter(usize),
+    // Decides between another iteration, which follows, and leaving for the
+    // target, in the order the repetition prefers.
+    Loop(usize, usize, Option<usize>, bool, usize),
+    // Notes where an iteration began.
+    Mark(usize),
+    // Ends an iteration and jumps back to the loop.
+    Iterate(usize, usize, usize),
+    Match,
+}
+
+// Undo records and untried choices, popped in order when a path fails.
+enum Backtrack {
+    Resume(usize, usize),
+    Slot(usize, Option<usize>),
+    Count(usize, usize),
+    Mark(usize, usize),
+    // The untried counts `low..=high` of a `Many`, continuing at `next`.
+    Counts { next: usize, start: usize, low: usize, high: usize, greedy: bool },
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+    loops: usize,
+}
+
+impl Compiler {
+    fn compile(&mut self, node: &Node) {
+        match node {
+            Node::Empty => {}
+            Node::Char(_) | Node::Any | Node::Class(..) => self.program.push(Inst::One(node.clone())),
+            Node::Start => self.program.push(Inst::Start),
+            Node::End => self.program.push(Inst::End),
+            Node::WordBoundary => self.program.push(Inst::WordBoundary),
+            Node::Group(inner, Some(index)) => {
+                self.program.push(Inst::Save(2 * index));
+                self.compile(inner);
+                self.program.push(Inst::Save(2 * index + 1));
+            }
+            Node::Group(inner, None) => self.compile(inner),
+            Node::Concat(nodes) => nodes.iter().for_each(|node| self.compile(node)),
+            Node::Alternate(options) => {
+                let mut jumps = Vec::new();
+                for (i, option) in options.iter().enumerate() {
+                    if i + 1 == options.len() {
+                        self.compile(option);
+                        break;
+                    }
+                    let split = self.program.len();
+                    self.program.push(Inst::Split(split + 1, 0));
+                    self.compile(option);
+                    jumps.push(self.program.len());
+                    self.program.push(Inst::Jump(0));
+                    self.program[split] = Inst::Split(split + 1, self.program.len());
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat(inner, min, max, greedy) if matches!(**inner, Node::Char(_) | Node::Any | Node::Class(..)) => {
+                self.program.push(Inst::Many((**inner).clone(), *min, *max, *greedy));
+            }
+            Node::Repeat(inner, min, max, greedy) => {
+                let counter = self.loops;
+                self.loops += 1;
+                self.program.push(Inst::Enter(counter));
+                let head = self.program.len();
+                self.program.push(Inst::Loop(counter, *min, *max, *greedy, 0));
+                self.program.push(Inst::Mark(counter));
+                self.compile(inner);
+                self.program.push(Inst::Iterate(counter, *min, head));
+                self.program[head] = Inst::Loop(counter, *min, *max, *greedy, self.program.len());
+            }
+        }
+    }
+}
+
+// Runs the program from `begin`, returning the capture slots of the first
+// match in order of preference.
+fn run(program: &[Inst], loops: usize, slot_count: usize, text: &[char], begin: usize) -> Option<Vec<Option<usize>>> {
+    let mut slots = vec![None; slot_count];
+    let mut counts = vec![0; loops];
+    let mut marks = vec![0; loops];
+    let mut stack = Vec::new();
+    let (mut pc, mut pos) = (0, begin);
+    loop {
+        let proceed = match &program[pc] {
+            Inst::One(node) => {
+                let matched = pos < text.len() && matches_char(node, text[pos]);
+                pos += 1;
+                pc += 1;
+                matched
+            }
+            Inst::Many(node, min, max, greedy) => {
+                let mut available = 0;
+                while pos + available < text.len() && max.is_none_or(|max| available < max) && matches_char(node, text[pos + available]) {
+                    available += 1;
+                }
+                let (next, start, greedy) = (pc + 1, pos, *greedy);
+                if available >= *min {
+                    let count = if greedy { available } else { *min };
+                    if available > *min {
+                        let (low, high) = if greedy { (*min, available - 1) } else { (min + 1, available) };
+                        stack.push(Backtrack::Counts { next, start, low, high, greedy });
+                    }
+                    pos += count;
+                    pc = next;
+                }
+                available >= *min
+            }
+            Inst::Start => {
+                pc += 1;
+                pos == 0
+            }
+            Inst::End => {
+                pc += 1;
+                pos == text.len()
+            }
+            Inst::WordBoundary => {
+                let before = pos > 0 && is_word_char(text[pos - 1]);
+                let after = pos < text.len() && is_word_char(text[pos]);
+                pc += 1;
+                before != after
+            }
+            Inst::Save(slot) => {
+                stack.push(Backtrack::Slot(*slot, slots[*slot]));
+                slots[*slot] = Some(pos);
+                pc += 1;
+                true
+            }
+            Inst::Split(first, second) => {
+                stack.push(Backtrack::Resume(*second, pos));
+                pc = *first;
+                true
+            }
+            Inst::Jump(target) => {
+                pc = *target;
+                true
+            }
+            Inst::Enter(counter) => {
+                stack.push(Backtrack::Count(*counter, counts[*counter]));
+                counts[*counter] = 0;
+                pc += 1;
+                true
+            }
+            Inst::Loop(counter, min, max, greedy, exit) => {
+                let count = counts[*counter];
+                if count < *min {
+                    pc += 1;
+                } else if max.is_some_and(|max| count >= max) {
+                    pc = *exit;
+                } else if *greedy {
+                    stack.push(Backtrack::Resume(*exit, pos));
+                    pc += 1;
+                } else {
+                    stack.push(Backtrack::Resume(pc + 1, pos));
+                    pc = *exit;
+                }
+                true
+            }
+            Inst::Mark(counter) => {
+                stack.push(Backtrack::Mark(*counter, marks[*counter]));
+                marks[*counter] = pos;
+                pc += 1;
+                true
+            }
+            Inst::Iterate(counter, min, head) => {
+                // Once the minimum is met, an iteration that consumed nothing
+                // cannot lead anywhere new.
+                let count = counts[*counter];
+                stack.push(Backtrack::Count(*counter, count));
+                counts[*counter] = count + 1;
+                pc = *head;
+                pos != marks[*counter] || count < *min
+            }
+            Inst::Match => {
+                slots[1] = Some(pos);
+                return Some(slots);
+            }
+        };
+        if proceed {
+            continue;
+        }
+        loop {
+            match stack.pop()? {
+                Backtrack::Resume(target, at) => {
+                    (pc, pos) = (target, at);
+                    break;
+                }
+                Backtrack::Slot(slot, value) => slots[slot] = value,
+                Backtrack::Count(counter, value) => counts[counter] = value,
+                Backtrack::Mark(counter, value) => marks[counter] = value,
+                Backtrack::Counts { next, start, low, high, greedy } => {
+                    let count = if greedy { high } else { low };
+                    if low < high {
+                        let (low, high) = if greedy { (low, high - 1) } else { (low + 1, high) };
+                        stack.push(Backtrack::Counts { next, start, low, high, greedy });
+                    }
+                    (pc, pos) = (next, start + count);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn matches_char(node: &Node, c: char) -> bool {
+    match node {
+        Node::Char(expected) => c == *expected,
+        Node::Any => c != '\n',
+        Node::Class(items, negated) => items.iter().any(|item| class_item_matches(item, c)) != *negated,
+        _ => false,
+    }
+}
+
+fn class_item_matches(item: &ClassItem, c: char) -> bool {
+    match item {
+        ClassItem::Char(expected) => c == *expected,
+        ClassItem::Range(low, high) => *low <= c && c <= *high,
+        ClassItem::Digit(negated) => c.is_ascii_digit() != *negated,
+        ClassItem::Word(negated) => is_word_char(c) != *negated,
+        ClassItem::Space(negated) => c.is_whitespace() != *negated,
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+struct RegexParser {
+    chars: Vec<char>,
+    position: usize,
+    group_count: usize,
+}
+
+impl RegexParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.position += 1;
+        c
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, String> {
+        let mut options = vec![self.parse_concat()?];
+        while self.peek() == Some('|') {
+            self.position += 1;
+            options.push(self.parse_concat()?);
+        }
+        Ok(if options.len() == 1 { options.pop().unwrap() } else { Node::Alternate(options) })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('(') => {
+                let index = if self.chars[self.position..].starts_with(&['?', ':']) {
+                    self.position += 2;
+                    None
+                } else {
+                    self.group_count += 1;
+                    Some(self.group_count)
+                };
+                let inner = self.parse_alternation()?;
+                if self.next() != Some(')') {
+                    return Err("Unclosed group in regex".to_string());
+                }
+                Ok(Node::Group(Box::new(inner), index))
+            }
+            Some('[') => self.parse_class(),
+            Some('\\') => match self.next() {
+                Some('b') => Ok(Node::WordBoundary),
+                Some(c) => Ok(match escape_class(c) {
+                    Some(item) => Node::Class(vec![item], false),
+                    None => Node::Char(escape_char(c)),
+                }),
+                None => Err("Regex cannot end with a backslash".to_string()),
+            },
+            Some(c @ ('*' | '+' | '?')) => Err(format!("Nothing to repeat before '{}' in regex", c)),
+            Some(c) => Ok(Node::Char(c)),
+            None => Err("Unexpected end of regex".to_string()),
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.position += 1;
+        }
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                Some(']') if !first => break,
+                Some(c) => c,
+                None => return Err("Unclosed character class in regex".to_string()),
+            };
+            first = false;
+            let low = if c == '\\' {
+                let escaped = self.next().ok_or("Unclosed character class in regex")?;
+                if let Some(item) = escape_class(escaped) {
+                    items.push(item);
+                    continue;
+                }
+                escape_char(escaped)
+            } else {
+                c
+            };
+            if self.peek() == Some('-') && self.chars.get(self.position + 1).is_some_and(|&next| next != ']') {
+                self.position += 1;
+                let high = match self.next() {
+                    Some('\\') => escape_char(self.next().ok_or("Unclosed character class in regex")?),
+                    Some(high) => high,
+                    None => return Err("Unclosed character class in regex".to_string()),
+                };
+                if high < low {
+                    return Err(format!("Invalid range {}-{} in regex", low, high));
+                }
+                items.push(ClassItem::Range(low, high));
+            } else {
+                items.push(ClassItem::Char(low));
+            }
+        }
+        Ok(Node::Class(items, negated))
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('{') => match self.parse_braces() {
+                Some(bounds) => bounds,
+                None => return Ok(atom),
+            },
+            Some(c @ ('*' | '+' | '?')) => {
+                self.position += 1;
+                match c {
+                    '*' => (0, None),
+                    '+' => (1, None),
+                    _ => (0, Some(1)),
+                }
+            }
+            _ => return Ok(atom),
+        };
+        if let Some(max) = max {
+            if max < min {
+                return Err(format!("Invalid repetition {{{},{}}} in regex", min, max));
+            }
+        }
+        let greedy = if self.peek() == Some('?') {
+            self.position += 1;
+            false
+        } else {
+            true
+        };
+        Ok(Node::Repeat(Box::new(atom), min, max, greedy))
+    }
+
+    // Parses `{n}`, `{n,}` or `{n,m}`. Anything else leaves the brace to be
+    // matched literally, as most regex dialects do.
+    fn parse_braces(&mut self) -> Option<(usize, Option<usize>)> {
+        let rest: String = self.chars[self.position..].iter().collect();
+        let close = rest.find('}')?;
+        let body = &rest[1..close];
+        let bounds = match body.split_once(',') {
+            None => {
+                let n = body.parse().ok()?;
+                (n, Some(n))
+            }
+            Some((low, "")) => (low.parse().ok()?, None),
+            Some((low, high)) => (low.parse().ok()?, Some(high.parse().ok()?)),
+        };
+        self.position += rest[..=close].chars().count();
+        Some(bounds)
+    }
+}
+
+fn escape_class(c: char) -> Option<ClassItem> {
+    match c {
+        'd' => Some(ClassItem::Digit(false)),
+        'D' => Some(ClassItem::Digit(true)),
+        'w' => Some(ClassItem::Word(false)),
+        'W' => Some(ClassItem::Word(true)),
+        's' => Some(ClassItem::Space(false)),
+        'S' => Some(ClassItem::Space(true)),
+        _ => None,
+    }
+}
+
+fn escape_char(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        other => other,
+    }
+}
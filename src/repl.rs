@@ -0,0 +1,102 @@
+use crate::audit::AuditLog;
+use crate::cli::Options;
+use crate::evaluator::{print_format, Evaluator};
+use crate::lexer::Lexer;
+use crate::parser::{ASTNode, Parser};
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+// Interactive session. Input is read a line at a time and run as soon as it
+// parses; while a statement is unfinished, such as an open `{` block, more
+// lines are read with a `...` prompt. A blank line gives up on the pending
+// input. Variables persist between entries, and `:load path` runs a script
+// file into the session.
+
+pub fn run(options: &Options) {
+    let mut evaluator = Evaluator::with_config(options.limits.clone());
+    evaluator.set_trace(options.trace);
+    if let Some(path) = &options.audit {
+        match AuditLog::open(path) {
+            Ok(audit) => evaluator.set_audit(audit),
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        }
+    }
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut pending = String::new();
+    loop {
+        print!("{}", if pending.is_empty() { "> " } else { "... " });
+        let _ = io::stdout().flush();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        if pending.is_empty() {
+            if let Some(path) = line.trim().strip_prefix(":load ") {
+                load(&mut evaluator, path.trim());
+                continue;
+            }
+        }
+        let cancelled = line.trim().is_empty();
+        pending.push_str(&line);
+        pending.push('\n');
+        if pending.trim().is_empty() {
+            pending.clear();
+            continue;
+        }
+        let parsed = Parser::new(Lexer::new(pending.clone())).and_then(|mut parser| parser.parse());
+        let ast = match parsed {
+            Ok(ast) => ast,
+            Err(e) if e.contains("the end of the file") && !cancelled => continue,
+            Err(e) => {
+                eprintln!("Error parsing input: {}", e);
+                pending.clear();
+                continue;
+            }
+        };
+        pending.clear();
+        match evaluator.eval(ast.clone()) {
+            Ok(Some(value)) if ends_with_expression(&ast.borrow()) => println!("{}", print_format(&value)),
+            Ok(_) => (),
+            Err(e) => eprintln!("Error evaluating AST: {}", e),
+        }
+    }
+    println!();
+}
+
+fn load(evaluator: &mut Evaluator, path: &str) {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => return eprintln!("Error reading file {}: {}", path, e),
+    };
+    let ast = match Parser::new(Lexer::new(source)).and_then(|mut parser| parser.parse()) {
+        Ok(ast) => ast,
+        Err(e) => return eprintln!("Error parsing {}: {}", path, e),
+    };
+    if let Err(e) = evaluator.eval(ast) {
+        eprintln!("Error evaluating AST: {}", e);
+        eprintln!("    at line {} of {}", evaluator.current_line(), path);
+    }
+}
+
+// Only a bare expression echoes its value; assignments and other statements
+// stay quiet, as they would in a script.
+pub fn ends_with_expression(program: &ASTNode) -> bool {
+    let last = match program {
+        ASTNode::Program(statements) => match statements.last() {
+            Some(last) => last.borrow(),
+            None => return false,
+        },
+        _ => return false,
+    };
+    match &*last {
+        ASTNode::Statement(_, statement) => !matches!(
+            &*statement.borrow(),
+            ASTNode::Assign(..) | ASTNode::IndexAssign(..) | ASTNode::Print(_) | ASTNode::If(..) | ASTNode::While(..) | ASTNode::For(..) | ASTNode::ForIn(..) | ASTNode::Repeat(..) | ASTNode::TryCatch(..) | ASTNode::Labeled(..)
+        ),
+        _ => false,
+    }
+}
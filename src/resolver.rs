@@ -0,0 +1,108 @@
+use crate::builtins::{self, Type};
+use crate::fuzzy;
+use crate::parser::ASTNode;
+use crate::visit::walk;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+// Static checks run by `--check` after parsing. Variables have no
+// declarations, so the resolver only reports names that are read somewhere
+// but never assigned anywhere in the program, which can never succeed. It
+// also reports builtin calls with the wrong number of arguments, or with a
+// literal argument of a type the builtin never accepts.
+
+pub struct Problem {
+    pub line: usize,
+    // "undefined_variable", "arity" or "argument_type".
+    pub code: &'static str,
+    pub message: String,
+}
+
+const ORDINALS: [&str; 4] = ["First", "Second", "Third", "Fourth"];
+
+pub struct Resolver {
+    assigned: HashSet<String>,
+    line: usize,
+    problems: Vec<Problem>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver {
+            assigned: HashSet::new(),
+            line: 0,
+            problems: Vec::new(),
+        }
+    }
+
+    // Records every name the program assigns. Call this for all files before
+    // `check`, since earlier files may define variables for later ones.
+    pub fn declare(&mut self, node: &Rc<RefCell<ASTNode>>) {
+        walk(node, &mut |node| match node {
+            ASTNode::Assign(name, _) | ASTNode::ForIn(name, _, _) | ASTNode::Comprehension(_, name, _, _) | ASTNode::TryCatch(_, name, _) => {
+                self.assigned.insert(name.clone());
+            }
+            _ => {}
+        });
+    }
+
+    pub fn check(&mut self, node: &Rc<RefCell<ASTNode>>) -> Vec<Problem> {
+        self.line = 0;
+        let mut reported = HashSet::new();
+        walk(node, &mut |node| match node {
+            ASTNode::Statement(line, _) => self.line = *line,
+            ASTNode::Identifier(name) if !self.assigned.contains(name) && reported.insert(name.clone()) => {
+                let mut names: Vec<&str> = self.assigned.iter().map(String::as_str).collect();
+                names.sort_unstable();
+                let message = match fuzzy::suggest(name, names) {
+                    Some(similar) => format!("Undefined variable: {}; did you mean {}?", name, similar),
+                    None => format!("Undefined variable: {}", name),
+                };
+                self.problems.push(Problem { line: self.line, code: "undefined_variable", message });
+            }
+            ASTNode::FunctionCall(name, args) => self.check_call(name, args),
+            _ => {}
+        });
+        std::mem::take(&mut self.problems)
+    }
+
+    fn check_call(&mut self, name: &str, args: &[Rc<RefCell<ASTNode>>]) {
+        let Some(builtin) = builtins::find(name) else {
+            return;
+        };
+        let spread = args.iter().any(|arg| matches!(*arg.borrow(), ASTNode::Spread(_)));
+        if !spread && (args.len() < builtin.min_args || args.len() > builtin.max_args) {
+            let expected = match (builtin.min_args, builtin.max_args) {
+                (min, max) if min == max => format!("{} argument{}", min, if min == 1 { "" } else { "s" }),
+                (min, max) => format!("{} to {} arguments", min, max),
+            };
+            self.problems.push(Problem {
+                line: self.line,
+                code: "arity",
+                message: format!("{} takes {} but was given {}; expected {}", name, expected, args.len(), builtin.signature),
+            });
+            return;
+        }
+        // After a spread the positions of the remaining arguments are unknown.
+        let fixed = args.iter().take_while(|arg| !matches!(*arg.borrow(), ASTNode::Spread(_)));
+        for (index, arg) in fixed.enumerate() {
+            let expected = builtins::argument_type(name, index);
+            let found = match &*arg.borrow() {
+                ASTNode::StringLiteral(_) => Type::String,
+                ASTNode::Number(_) | ASTNode::Float(_) => Type::Number,
+                ASTNode::Boolean(_) => Type::Boolean,
+                ASTNode::Array(_) => Type::Array,
+                ASTNode::Map(_) => Type::Map,
+                _ => continue,
+            };
+            if expected != Type::Any && found != expected {
+                self.problems.push(Problem {
+                    line: self.line,
+                    code: "argument_type",
+                    message: format!("{} argument of {} must be {}, not {}", ORDINALS[index], name, expected.describe(), found.describe()),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::builtins;
+use crate::json::Json;
+use crate::lexer::{Lexer, Token};
+
+// Classifies every token of a script for syntax highlighting. The spans come
+// from the real lexer, so editors, `--tokens` and `highlight` always agree
+// with what the parser sees. Lines count from 1 and columns from 0, both in
+// characters.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Keyword,
+    Function,
+    Identifier,
+    String,
+    Number,
+    Comment,
+    Operator,
+    Punctuation,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Keyword => "keyword",
+            Kind::Function => "function",
+            Kind::Identifier => "identifier",
+            Kind::String => "string",
+            Kind::Number => "number",
+            Kind::Comment => "comment",
+            Kind::Operator => "operator",
+            Kind::Punctuation => "punctuation",
+        }
+    }
+}
+
+pub struct SemanticToken {
+    pub line: usize,
+    pub column: usize,
+    // Character offsets into the whole source, end exclusive.
+    pub start: usize,
+    pub end: usize,
+    pub kind: Kind,
+}
+
+impl SemanticToken {
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("line", Json::Number(self.line as f64)),
+            ("column", Json::Number(self.column as f64)),
+            ("length", Json::Number((self.end - self.start) as f64)),
+            ("kind", Json::string(self.kind.name())),
+        ])
+    }
+}
+
+pub fn tokens(source: &str) -> Result<Vec<SemanticToken>, String> {
+    let mut lexer = Lexer::with_comments(source.to_string());
+    let mut tokens: Vec<SemanticToken> = Vec::new();
+    // The last identifier, while nothing but comments has followed it.
+    let mut identifier: Option<(usize, String)> = None;
+    loop {
+        let token = lexer.next_token()?;
+        if token == Token::EOF {
+            return Ok(tokens);
+        }
+        // Builtin names are ordinary identifiers; one is a function where it
+        // is called.
+        match (&token, identifier.take()) {
+            (Token::LParen, Some((index, name))) if builtins::find(&name).is_some() => tokens[index].kind = Kind::Function,
+            (Token::Comment(_), pending) => identifier = pending,
+            _ => {}
+        }
+        if let Token::Identifier(name) = &token {
+            identifier = Some((tokens.len(), name.clone()));
+        }
+        let (start, end) = lexer.token_span();
+        tokens.push(SemanticToken { line: lexer.token_line(), column: lexer.token_column(), start, end, kind: classify(&token) });
+    }
+}
+
+fn classify(token: &Token) -> Kind {
+    match token {
+        Token::Number(_) | Token::Float(_) => Kind::Number,
+        Token::StringLiteral(_) => Kind::String,
+        Token::Comment(_) => Kind::Comment,
+        Token::Identifier(_) => Kind::Identifier,
+        Token::Plus | Token::Minus | Token::Star | Token::Slash | Token::Assign => Kind::Operator,
+        Token::Eq | Token::NotEq | Token::Lt | Token::Gt | Token::LtEq | Token::GtEq => Kind::Operator,
+        Token::And | Token::Or | Token::Not | Token::Spread => Kind::Operator,
+        Token::Semicolon | Token::Comma | Token::Colon => Kind::Punctuation,
+        Token::LParen | Token::RParen | Token::LBracket | Token::RBracket | Token::LBrace | Token::RBrace => Kind::Punctuation,
+        // Every other token is a word the lexer reserves, such as `while`.
+        _ => Kind::Keyword,
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+
+// The file format behind `store_open`. Each line holds one entry as
+// `key<TAB>type<TAB>value`, where the type is `s`, `n` or `b` for a string,
+// number or boolean. Tabs, newlines and backslashes inside keys and string
+// values are escaped so every entry stays on one line.
+
+pub enum Stored {
+    String(String),
+    Number(i64),
+    Boolean(bool),
+}
+
+pub struct Store {
+    pub path: String,
+    pub entries: BTreeMap<String, Stored>,
+}
+
+impl fmt::Debug for Store {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<store {:?} ({} entries)>", self.path, self.entries.len())
+    }
+}
+
+impl Store {
+    // A missing file is an empty store; it is created on the first write.
+    pub fn open(path: &str) -> Result<Store, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Cannot open store {}: {}", path, e)),
+        };
+        let mut entries = BTreeMap::new();
+        for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.is_empty()) {
+            let corrupt = || format!("Store {} is corrupt at line {}", path, number + 1);
+            let mut fields = line.splitn(3, '\t');
+            let (key, kind, value) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(key), Some(kind), Some(value)) => (unescape(key), kind, value),
+                _ => return Err(corrupt()),
+            };
+            let value = match kind {
+                "s" => Stored::String(unescape(value)),
+                "n" => Stored::Number(value.parse().map_err(|_| corrupt())?),
+                "b" => Stored::Boolean(value == "true"),
+                _ => return Err(corrupt()),
+            };
+            entries.insert(key, value);
+        }
+        Ok(Store { path: path.to_string(), entries })
+    }
+
+    // Writes to a temporary file first and renames it over the store, so an
+    // interrupted write never leaves a half-written file behind.
+    pub fn save(&self) -> io::Result<()> {
+        let mut text = String::new();
+        for (key, value) in &self.entries {
+            let (kind, value) = match value {
+                Stored::String(s) => ("s", escape(s)),
+                Stored::Number(n) => ("n", n.to_string()),
+                Stored::Boolean(b) => ("b", b.to_string()),
+            };
+            text.push_str(&format!("{}\t{}\t{}\n", escape(key), kind, value));
+        }
+        let temp_path = format!("{}.tmp", self.path);
+        fs::write(&temp_path, text)?;
+        fs::rename(&temp_path, &self.path)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
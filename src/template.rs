@@ -0,0 +1,186 @@
+use crate::evaluator::{print_format, Value};
+use std::collections::BTreeMap;
+
+// The template language behind `render`. Text is copied as it is, and tags
+// in double braces are replaced:
+//
+//   {{name}}                    the value of `name`; `a.b` looks inside maps
+//   {{#each items}}...{{/each}} the body once per array element or map entry
+//   {{#if key}}...{{else}}...{{/if}}
+//
+// Inside `each`, names are looked up in the current element first and then
+// in the enclosing data, `{{.}}` is the element itself and `{{@index}}` its
+// position. For a map, each entry is a map with "key" and "value". `if`
+// takes its first branch unless the value is missing, false, zero, or an
+// empty string or collection. Lines holding only a block tag leave no blank
+// line behind. Naming a value that does not exist outside an `if` is an
+// error, so typos do not silently print nothing.
+
+enum Part {
+    Text(String),
+    Value(String),
+    Each(String, Vec<Part>),
+    If(String, Vec<Part>, Vec<Part>),
+}
+
+pub fn render(template: &str, data: &Value) -> Result<String, String> {
+    let template = strip_standalone_tags(template);
+    let mut rest = template.as_str();
+    let (parts, end) = parse(&mut rest)?;
+    if let Some(tag) = end {
+        return Err(format!("Unexpected {{{{{}}}}} in template", tag));
+    }
+    let mut output = String::new();
+    write(&parts, &mut vec![Scope { value: data.clone(), index: None }], &mut output)?;
+    Ok(output)
+}
+
+// A line holding nothing but a block tag, such as `{{/each}}`, is markup
+// rather than output, so the whole line goes and only the tag is kept.
+fn strip_standalone_tags(template: &str) -> String {
+    template
+        .split_inclusive('\n')
+        .map(|line| {
+            let tag = line.trim();
+            let inner = tag.strip_prefix("{{").and_then(|tag| tag.strip_suffix("}}")).filter(|inner| !inner.contains("{{")).map(str::trim);
+            match inner {
+                Some(inner) if inner.starts_with('#') || inner.starts_with('/') || inner == "else" => tag,
+                _ => line,
+            }
+        })
+        .collect()
+}
+
+// Reads parts until the input ends or a closing tag (`/each`, `/if` or
+// `else`) is found, which is returned for the caller to check.
+fn parse(rest: &mut &str) -> Result<(Vec<Part>, Option<String>), String> {
+    let mut parts = Vec::new();
+    loop {
+        let Some(open) = rest.find("{{") else {
+            if !rest.is_empty() {
+                parts.push(Part::Text(rest.to_string()));
+            }
+            *rest = "";
+            return Ok((parts, None));
+        };
+        if open > 0 {
+            parts.push(Part::Text(rest[..open].to_string()));
+        }
+        let close = rest[open..].find("}}").ok_or("Unclosed {{ in template")? + open;
+        let tag = rest[open + 2..close].trim().to_string();
+        *rest = &rest[close + 2..];
+        if let Some(name) = tag.strip_prefix("#each ") {
+            let (body, end) = parse(rest)?;
+            expect_end(end, "/each", &tag)?;
+            parts.push(Part::Each(name.trim().to_string(), body));
+        } else if let Some(name) = tag.strip_prefix("#if ") {
+            let (then_parts, end) = parse(rest)?;
+            let else_parts = if end.as_deref() == Some("else") {
+                let (else_parts, end) = parse(rest)?;
+                expect_end(end, "/if", &tag)?;
+                else_parts
+            } else {
+                expect_end(end, "/if", &tag)?;
+                Vec::new()
+            };
+            parts.push(Part::If(name.trim().to_string(), then_parts, else_parts));
+        } else if tag == "/each" || tag == "/if" || tag == "else" {
+            return Ok((parts, Some(tag)));
+        } else if tag.is_empty() || tag.starts_with('#') {
+            return Err(format!("Unknown template tag {{{{{}}}}}", tag));
+        } else {
+            parts.push(Part::Value(tag));
+        }
+    }
+}
+
+fn expect_end(end: Option<String>, expected: &str, opened: &str) -> Result<(), String> {
+    match end {
+        Some(tag) if tag == expected => Ok(()),
+        Some(tag) => Err(format!("Expected {{{{{}}}}} to close {{{{{}}}}}, found {{{{{}}}}}", expected, opened, tag)),
+        None => Err(format!("{{{{{}}}}} is never closed with {{{{{}}}}}", opened, expected)),
+    }
+}
+
+struct Scope {
+    value: Value,
+    index: Option<usize>,
+}
+
+fn write(parts: &[Part], scopes: &mut Vec<Scope>, output: &mut String) -> Result<(), String> {
+    for part in parts {
+        match part {
+            Part::Text(text) => output.push_str(text),
+            Part::Value(name) => match lookup(scopes, name) {
+                Some(value) => output.push_str(&print_format(&value)),
+                None => return Err(format!("No value for {{{{{}}}}} in template", name)),
+            },
+            Part::If(name, then_parts, else_parts) => {
+                let branch = if lookup(scopes, name).is_some_and(|value| truthy(&value)) { then_parts } else { else_parts };
+                write(branch, scopes, output)?;
+            }
+            Part::Each(name, body) => {
+                let items = match lookup(scopes, name).map(thawed) {
+                    Some(Value::Array(items)) => items,
+                    Some(Value::Map(map)) => map
+                        .into_iter()
+                        .map(|(key, value)| Value::Map(BTreeMap::from([("key".to_string(), Value::String(key)), ("value".to_string(), value)])))
+                        .collect(),
+                    Some(other) => return Err(format!("{{{{#each {}}}}} needs an array or map, got {}", name, other)),
+                    None => return Err(format!("No value for {{{{#each {}}}}} in template", name)),
+                };
+                for (index, item) in items.into_iter().enumerate() {
+                    scopes.push(Scope { value: item, index: Some(index) });
+                    let written = write(body, scopes, output);
+                    scopes.pop();
+                    written?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Resolves a name from the innermost scope outwards.
+fn lookup(scopes: &[Scope], name: &str) -> Option<Value> {
+    let innermost = scopes.last()?;
+    match name {
+        "." => return Some(innermost.value.clone()),
+        "@index" => return innermost.index.map(|index| Value::Number(index as i64)),
+        _ => {}
+    }
+    let mut path = name.split('.');
+    let first = path.next()?;
+    let mut value = scopes.iter().rev().find_map(|scope| field(&scope.value, first))?;
+    for key in path {
+        value = field(&value, key)?;
+    }
+    Some(value)
+}
+
+fn thawed(value: Value) -> Value {
+    match value {
+        Value::Frozen(inner, _) => *inner,
+        value => value,
+    }
+}
+
+fn field(value: &Value, key: &str) -> Option<Value> {
+    match value {
+        Value::Map(map) => map.get(key).cloned(),
+        Value::Frozen(inner, _) => field(inner, key),
+        _ => None,
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Boolean(b) => *b,
+        Value::Number(n) => *n != 0,
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Map(map) => !map.is_empty(),
+        Value::Frozen(inner, _) => truthy(inner),
+        _ => true,
+    }
+}
@@ -0,0 +1,79 @@
+use crate::parser::{ASTNode, Comment};
+use crate::visit::walk;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+// Attaches the comments and blank lines of a script to its statements, so
+// tools that rewrite code can put them back where they were. Comments on
+// their own lines lead the next statement; a comment after code on the same
+// line trails the statement that line belongs to. Statements are found by
+// the line they start on, which is how `ASTNode::Statement` records them.
+// When several start on one line, leading comments and the blank line go to
+// the first of them and a trailing comment to the last, so each comment is
+// attached exactly once.
+
+#[derive(Default)]
+pub struct Trivia {
+    pub leading: Vec<String>,
+    pub trailing: Option<String>,
+    // Whether a blank line separates the statement, or its leading
+    // comments, from whatever comes before.
+    pub blank_line_before: bool,
+}
+
+pub struct Attached {
+    // Keyed by the address of each `ASTNode::Statement`, which stays put
+    // while the tree is alive.
+    statements: BTreeMap<*const ASTNode, Trivia>,
+    // Comments after the last statement.
+    pub dangling: Vec<String>,
+}
+
+impl Attached {
+    pub fn get(&self, statement: &ASTNode) -> Option<&Trivia> {
+        self.statements.get(&(statement as *const ASTNode))
+    }
+}
+
+pub fn attach(source: &str, program: &Rc<RefCell<ASTNode>>, comments: Vec<Comment>) -> Attached {
+    // Every statement with its line, in source order.
+    let mut statements = Vec::new();
+    walk(program, &mut |node| {
+        if let ASTNode::Statement(line, _) = node {
+            statements.push((*line, node as *const ASTNode));
+        }
+    });
+    statements.sort_by_key(|&(line, _)| line);
+    let source_lines: Vec<&str> = source.lines().collect();
+    let blank = |line: usize| line >= 2 && source_lines.get(line - 2).is_some_and(|text| text.trim().is_empty());
+    let mut attached = Attached { statements: BTreeMap::new(), dangling: Vec::new() };
+    let mut first_leading = BTreeMap::new();
+    for comment in comments {
+        let owner = if comment.trailing {
+            statements.iter().rev().find(|&&(line, _)| line <= comment.line)
+        } else {
+            statements.iter().find(|&&(line, _)| line > comment.line)
+        };
+        match owner {
+            Some(&(_, statement)) if comment.trailing => attached.statements.entry(statement).or_default().trailing = Some(comment.text),
+            Some(&(_, statement)) => {
+                first_leading.entry(statement).or_insert(comment.line);
+                attached.statements.entry(statement).or_default().leading.push(comment.text);
+            }
+            None => attached.dangling.push(comment.text),
+        }
+    }
+    let mut previous = None;
+    for (line, statement) in statements {
+        if previous == Some(line) {
+            continue;
+        }
+        previous = Some(line);
+        let top = first_leading.get(&statement).copied().unwrap_or(line);
+        if blank(top) {
+            attached.statements.entry(statement).or_default().blank_line_before = true;
+        }
+    }
+    attached
+}
@@ -0,0 +1,129 @@
+// URL splitting and percent-encoding for the `url_parse`, `url_encode`,
+// `url_decode` and `query_string` builtins. Parsing follows the common
+// `scheme://user@host:port/path?query#fragment` shape rather than the full
+// WHATWG algorithm, which is enough for scripts picking URLs apart.
+
+pub struct Url {
+    pub scheme: String,
+    pub user: String,
+    pub host: String,
+    pub port: Option<u16>,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub fragment: String,
+}
+
+pub fn parse(input: &str) -> Result<Url, String> {
+    let (rest, fragment) = match input.split_once('#') {
+        Some((rest, fragment)) => (rest, decode_part(fragment)?),
+        None => (input, String::new()),
+    };
+    let (rest, query) = match rest.split_once('?') {
+        Some((rest, query)) => (rest, parse_query(query)?),
+        None => (rest, Vec::new()),
+    };
+    let (scheme, rest) = match rest.split_once("://") {
+        Some((scheme, rest)) if is_scheme(scheme) => (scheme.to_lowercase(), rest),
+        _ => {
+            // Without an authority the whole remainder is a path, as in "/docs/index.html".
+            let path = decode_part(rest)?;
+            return Ok(Url { scheme: String::new(), user: String::new(), host: String::new(), port: None, path, query, fragment });
+        }
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, ""),
+    };
+    let (user, host_port) = match authority.rsplit_once('@') {
+        Some((user, host_port)) => (decode_part(user)?, host_port),
+        None => (String::new(), authority),
+    };
+    // IPv6 hosts are bracketed so their colons are not taken for a port.
+    let (host, port) = if let Some(bracketed) = host_port.strip_prefix('[') {
+        match bracketed.split_once(']') {
+            Some((host, port)) => (host, port.strip_prefix(':')),
+            None => return Err(format!("Unclosed '[' in URL host: {}", input)),
+        }
+    } else {
+        match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (host_port, None),
+        }
+    };
+    let port = match port {
+        Some("") | None => None,
+        Some(port) => Some(port.parse().map_err(|_| format!("Invalid port in URL: {}", port))?),
+    };
+    Ok(Url {
+        scheme,
+        user,
+        host: host.to_lowercase(),
+        port,
+        path: if path.is_empty() { "/".to_string() } else { decode_part(path)? },
+        query,
+        fragment,
+    })
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic()) && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+}
+
+fn parse_query(query: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        pairs.push((decode(key)?, decode(value)?));
+    }
+    Ok(pairs)
+}
+
+// Leaves only the unreserved characters of RFC 3986 as they are.
+pub fn encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Reverses `encode`. A `+` also decodes to a space, as in HTML form data.
+pub fn decode(text: &str) -> Result<String, String> {
+    percent_decode(text, true)
+}
+
+// Outside the query string a `+` is just a plus sign.
+fn decode_part(text: &str) -> Result<String, String> {
+    percent_decode(text, false)
+}
+
+fn percent_decode(text: &str, plus_is_space: bool) -> Result<String, String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(byte) => decoded.push(byte),
+                    None => return Err(format!("Invalid percent escape in {:?} at position {}", text, i)),
+                }
+                i += 3;
+            }
+            b'+' if plus_is_space => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| format!("Percent escapes in {:?} are not valid UTF-8", text))
+}
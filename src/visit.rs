@@ -0,0 +1,68 @@
+use crate::parser::ASTNode;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Generic traversal of the syntax tree for analysis passes such as the
+// resolver. `children` knows the shape of every node, so a new pass only
+// needs to match the nodes it cares about:
+//
+//   let mut calls = 0;
+//   walk(&program, &mut |node| if let ASTNode::FunctionCall(..) = node { calls += 1 });
+
+// The direct children of a node, in source order.
+pub fn children(node: &ASTNode) -> Vec<Rc<RefCell<ASTNode>>> {
+    match node {
+        ASTNode::Number(_) | ASTNode::Float(_) | ASTNode::StringLiteral(_) | ASTNode::Identifier(_) | ASTNode::Boolean(_) | ASTNode::Evaluated(_) => Vec::new(),
+        ASTNode::Break(_) | ASTNode::Continue(_) => Vec::new(),
+        ASTNode::BinaryOp(left, _, right) | ASTNode::Comparison(left, _, right) | ASTNode::LogicalOp(left, _, right) | ASTNode::IndexAccess(left, right) => {
+            vec![Rc::clone(left), Rc::clone(right)]
+        }
+        ASTNode::Assign(_, value) | ASTNode::Print(value) | ASTNode::Not(value) | ASTNode::Spread(value) | ASTNode::Labeled(_, value) | ASTNode::Statement(_, value) => {
+            vec![Rc::clone(value)]
+        }
+        ASTNode::Program(nodes) | ASTNode::FunctionCall(_, nodes) | ASTNode::Array(nodes) | ASTNode::ChainedComparison(nodes, _) => nodes.clone(),
+        ASTNode::Map(entries) => entries.iter().flat_map(|(key, value)| [Rc::clone(key), Rc::clone(value)]).collect(),
+        ASTNode::If(condition, then_block, elifs, else_block) => {
+            let mut nodes = vec![Rc::clone(condition)];
+            nodes.extend(then_block.iter().cloned());
+            for (condition, block) in elifs {
+                nodes.push(Rc::clone(condition));
+                nodes.extend(block.iter().cloned());
+            }
+            nodes.extend(else_block.iter().flatten().cloned());
+            nodes
+        }
+        ASTNode::While(condition, body) | ASTNode::ForIn(_, condition, body) | ASTNode::Repeat(condition, body) => {
+            let mut nodes = vec![Rc::clone(condition)];
+            nodes.extend(body.iter().cloned());
+            nodes
+        }
+        ASTNode::For(init, condition, update, body) => {
+            let mut nodes = vec![Rc::clone(init), Rc::clone(condition), Rc::clone(update)];
+            nodes.extend(body.iter().cloned());
+            nodes
+        }
+        ASTNode::IndexAssign(_, indexes, value) => indexes.iter().chain([value]).cloned().collect(),
+        ASTNode::Slice(target, start, end) => {
+            let mut nodes = vec![Rc::clone(target)];
+            nodes.extend(start.iter().chain(end).cloned());
+            nodes
+        }
+        ASTNode::Comprehension(element, _, iterable, condition) => {
+            let mut nodes = vec![Rc::clone(element), Rc::clone(iterable)];
+            nodes.extend(condition.iter().cloned());
+            nodes
+        }
+        ASTNode::TryCatch(body, _, handler) => body.iter().chain(handler).cloned().collect(),
+    }
+}
+
+// Calls `visit` on the node and then on each of its descendants, in source
+// order.
+pub fn walk<F: FnMut(&ASTNode)>(node: &Rc<RefCell<ASTNode>>, visit: &mut F) {
+    let node = node.borrow();
+    visit(&node);
+    for child in children(&node) {
+        walk(&child, visit);
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+// Recursive directory traversal behind the `walk_dir` builtin. Entries are
+// produced depth-first, with each directory's children in name order, and
+// directories are only read when the walk reaches them.
+
+pub struct WalkEntry {
+    pub path: String,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    pub size: u64,
+    pub modified: i64,
+}
+
+pub struct DirWalker {
+    // Children still to visit, last entry first, with their depth.
+    pending: Vec<(PathBuf, usize)>,
+    max_depth: Option<usize>,
+    follow_links: bool,
+    // Canonical paths of directories already entered, so following links
+    // cannot send the walk around a cycle forever.
+    visited: HashSet<PathBuf>,
+}
+
+impl DirWalker {
+    pub fn new(root: &str, max_depth: Option<usize>, follow_links: bool) -> Result<DirWalker, std::io::Error> {
+        let mut walker = DirWalker {
+            pending: Vec::new(),
+            max_depth,
+            follow_links,
+            visited: HashSet::new(),
+        };
+        let root = PathBuf::from(root);
+        if let Ok(canonical) = fs::canonicalize(&root) {
+            walker.visited.insert(canonical);
+        }
+        walker.push_children(&root, 1)?;
+        Ok(walker)
+    }
+
+    fn push_children(&mut self, dir: &PathBuf, depth: usize) -> Result<(), std::io::Error> {
+        let mut children: Vec<PathBuf> = fs::read_dir(dir)?.map(|entry| entry.map(|entry| entry.path())).collect::<Result<_, _>>()?;
+        children.sort();
+        self.pending.extend(children.into_iter().rev().map(|path| (path, depth)));
+        Ok(())
+    }
+
+    fn visit(&mut self, path: PathBuf, depth: usize) -> Result<WalkEntry, String> {
+        let link_metadata = fs::symlink_metadata(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let is_symlink = link_metadata.file_type().is_symlink();
+        let metadata = if is_symlink && self.follow_links {
+            // A dangling link is still reported, just without following it.
+            fs::metadata(&path).unwrap_or(link_metadata)
+        } else {
+            link_metadata
+        };
+        let is_dir = metadata.is_dir();
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs() as i64);
+        let may_descend = self.max_depth.is_none_or(|max| depth < max);
+        if is_dir && may_descend && (!is_symlink || self.follow_links) {
+            let first_visit = match fs::canonicalize(&path) {
+                Ok(canonical) => self.visited.insert(canonical),
+                Err(_) => true,
+            };
+            if first_visit {
+                self.push_children(&path, depth + 1).map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+        }
+        Ok(WalkEntry {
+            path: path.to_string_lossy().into_owned(),
+            is_dir,
+            is_symlink,
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+impl Iterator for DirWalker {
+    type Item = Result<WalkEntry, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, depth) = self.pending.pop()?;
+        Some(self.visit(path, depth))
+    }
+}